@@ -0,0 +1,373 @@
+use super::{
+    arrow_head, callout_bubble, callout_tail_base, marker_radius, Shape, Style,
+    HIGHLIGHTER_OPACITY, SPOTLIGHT_DIM,
+};
+use eframe::egui::{self, Color32, Pos2, Rect, Vec2};
+use image::RgbaImage;
+use resvg::{tiny_skia, usvg};
+use std::fmt::Write;
+use std::path::Path;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ExportError {
+    #[error("Failed to access the file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid annotation file: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Failed to render the annotations: {0}")]
+    Render(#[from] usvg::Error),
+    #[error("Invalid frame size {0}x{1}")]
+    FrameSize(u32, u32),
+    #[error("Failed to save the image: {0}")]
+    Image(#[from] image::ImageError),
+}
+
+// Annotations as they are kept, to load them in a later session
+pub fn save_json(path: &Path, shapes: &[Shape]) -> Result<(), ExportError> {
+    std::fs::write(path, serde_json::to_string_pretty(shapes)?)?;
+    Ok(())
+}
+
+pub fn load_json(path: &Path) -> Result<Vec<Shape>, ExportError> {
+    Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+}
+
+pub fn save_svg(
+    path: &Path,
+    shapes: &[Shape],
+    size: Vec2,
+    pixels_per_point: f32,
+) -> Result<(), ExportError> {
+    std::fs::write(path, to_svg(shapes, size, pixels_per_point))?;
+    Ok(())
+}
+
+// Draw the annotations over a captured frame, both in physical pixels of the monitor
+pub fn flatten(
+    frame: RgbaImage,
+    shapes: &[Shape],
+    pixels_per_point: f32,
+) -> Result<RgbaImage, ExportError> {
+    let (width, height) = frame.dimensions();
+    let size = egui::vec2(width as f32, height as f32);
+
+    let mut options = usvg::Options::default();
+    load_fonts(&mut options);
+    let tree = usvg::Tree::from_str(&to_svg(shapes, size, pixels_per_point), &options)?;
+
+    // Frames are opaque, so their pixels are premultiplied already
+    let mut pixmap = tiny_skia::IntSize::from_wh(width, height)
+        .and_then(|size| tiny_skia::Pixmap::from_vec(frame.into_raw(), size))
+        .ok_or(ExportError::FrameSize(width, height))?;
+    resvg::render(
+        &tree,
+        tiny_skia::Transform::identity(),
+        &mut pixmap.as_mut(),
+    );
+    RgbaImage::from_raw(width, height, pixmap.take()).ok_or(ExportError::FrameSize(width, height))
+}
+
+// The fonts bundled with egui, so the text looks like on the overlay without system fonts
+fn load_fonts(options: &mut usvg::Options) {
+    let fonts = egui::FontDefinitions::default();
+    let proportional = fonts
+        .families
+        .get(&egui::FontFamily::Proportional)
+        .and_then(|names| names.first())
+        .and_then(|name| fonts.font_data.get(name));
+
+    let database = options.fontdb_mut();
+    if let Some(data) = proportional {
+        database.load_font_data(data.font.to_vec());
+    }
+    let family = database
+        .faces()
+        .next()
+        .and_then(|face| face.families.first())
+        .map(|(family, _)| family.clone());
+    // Emoji and the other fallbacks
+    for data in fonts.font_data.values() {
+        database.load_font_data(data.font.to_vec());
+    }
+    if let Some(family) = family {
+        database.set_sans_serif_family(family.clone());
+        options.font_family = family;
+    }
+}
+
+// Coordinates of the shapes become the SVG user units, `size` is the annotated area
+pub fn to_svg(shapes: &[Shape], size: Vec2, pixels_per_point: f32) -> String {
+    let mut svg = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#,
+        w = size.x,
+        h = size.y,
+    );
+    svg.push('\n');
+    for shape in shapes {
+        write_shape(&mut svg, shape, size, pixels_per_point);
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn write_shape(svg: &mut String, shape: &Shape, size: Vec2, pixels_per_point: f32) {
+    let px = |points: f32| points * pixels_per_point;
+    // Writing to a String never fails
+    let _ = match shape {
+        Shape::Rectangle { rect, color, style } => {
+            // The outline is drawn outside of the rectangle
+            let outline = rect.expand(px(style.width) / 2.0);
+            writeln!(
+                svg,
+                r#"<rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" {} {}/>"#,
+                outline.min.x,
+                outline.min.y,
+                outline.width(),
+                outline.height(),
+                fill(*color, style),
+                stroke(*color, style, pixels_per_point, style.dashed),
+            )
+        }
+        Shape::Circle {
+            center,
+            radius,
+            color,
+            style,
+        } => writeln!(
+            svg,
+            r#"<circle cx="{:.1}" cy="{:.1}" r="{:.1}" {} {}/>"#,
+            center.x,
+            center.y,
+            radius,
+            fill(*color, style),
+            stroke(*color, style, pixels_per_point, style.dashed),
+        ),
+        Shape::Ellipse { rect, color, style } => writeln!(
+            svg,
+            r#"<ellipse cx="{:.1}" cy="{:.1}" rx="{:.1}" ry="{:.1}" {} {}/>"#,
+            rect.center().x,
+            rect.center().y,
+            rect.width() / 2.0,
+            rect.height() / 2.0,
+            fill(*color, style),
+            stroke(*color, style, pixels_per_point, style.dashed),
+        ),
+        Shape::Arrow {
+            start,
+            end,
+            color,
+            style,
+        } => {
+            let _ = write_polyline(svg, &[*start, *end], *color, style, pixels_per_point);
+            match arrow_head(*start, *end, px(style.arrow_size)) {
+                Some([left, right]) => writeln!(
+                    svg,
+                    r#"<polyline points="{}" fill="none" {}/>"#,
+                    points(&[left, *end, right]),
+                    stroke(*color, style, pixels_per_point, false),
+                ),
+                None => Ok(()),
+            }
+        }
+        Shape::Line {
+            start,
+            end,
+            color,
+            style,
+        } => write_polyline(svg, &[*start, *end], *color, style, pixels_per_point),
+        Shape::FreeHand {
+            points,
+            color,
+            style,
+        } => write_polyline(svg, points, *color, style, pixels_per_point),
+        Shape::Highlighter {
+            points,
+            color,
+            style,
+        } => write_polyline(
+            svg,
+            points,
+            color.gamma_multiply(HIGHLIGHTER_OPACITY),
+            style,
+            pixels_per_point,
+        ),
+        Shape::Text {
+            position,
+            content,
+            color,
+            style,
+        } => writeln!(
+            svg,
+            r#"<text x="{:.1}" y="{:.1}" dominant-baseline="hanging" {} {}>{}</text>"#,
+            position.x,
+            position.y,
+            font(style, pixels_per_point),
+            paint("fill", *color),
+            escape(content),
+        ),
+        Shape::Marker {
+            center,
+            number,
+            color,
+            style,
+        } => writeln!(
+            svg,
+            concat!(
+                r#"<circle cx="{x:.1}" cy="{y:.1}" r="{r:.1}" {fill}/>"#,
+                "\n",
+                r#"<text x="{x:.1}" y="{y:.1}" text-anchor="middle" dominant-baseline="central" {font} {text}>{number}</text>"#,
+            ),
+            x = center.x,
+            y = center.y,
+            r = px(marker_radius(style)),
+            fill = paint("fill", *color),
+            font = font(style, pixels_per_point),
+            text = paint("fill", Color32::WHITE),
+            number = number,
+        ),
+        Shape::Callout {
+            position,
+            tail,
+            content,
+            color,
+            style,
+        } => {
+            let bubble = callout_bubble(*position, content, style, pixels_per_point);
+            let background = paint("fill", Color32::from_white_alpha(230));
+            let outline = stroke(*color, style, pixels_per_point, false);
+            if let Some([left, right]) = callout_tail_base(bubble, *tail) {
+                let _ = writeln!(
+                    svg,
+                    r#"<polyline points="{}" {} {}/>"#,
+                    points(&[left, *tail, right]),
+                    background,
+                    outline,
+                );
+            }
+            writeln!(
+                svg,
+                concat!(
+                    r#"<rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" rx="{:.1}" {} {}/>"#,
+                    "\n",
+                    r#"<text x="{:.1}" y="{:.1}" text-anchor="middle" dominant-baseline="central" {} {}>{}</text>"#,
+                ),
+                bubble.min.x,
+                bubble.min.y,
+                bubble.width(),
+                bubble.height(),
+                px(style.font_size) / 2.0,
+                background,
+                outline,
+                bubble.center().x,
+                bubble.center().y,
+                font(style, pixels_per_point),
+                paint("fill", *color),
+                escape(content),
+            )
+        }
+        Shape::Spotlight { rect, color, style } => {
+            // The whole area with the spotlight cut out of it
+            let outline = rect.expand(px(style.width) / 2.0);
+            writeln!(
+                svg,
+                concat!(
+                    r#"<path d="M0 0 H{:.1} V{:.1} H0 Z {}" fill-rule="evenodd" {}/>"#,
+                    "\n",
+                    r#"<rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" fill="none" {}/>"#,
+                ),
+                size.x,
+                size.y,
+                rect_path(*rect),
+                paint("fill", Color32::from_black_alpha(SPOTLIGHT_DIM)),
+                outline.min.x,
+                outline.min.y,
+                outline.width(),
+                outline.height(),
+                stroke(*color, style, pixels_per_point, false),
+            )
+        }
+        // Hidden in the captured frames, there is nothing to draw
+        Shape::Redaction { .. } => Ok(()),
+    };
+}
+
+fn write_polyline(
+    svg: &mut String,
+    line: &[Pos2],
+    color: Color32,
+    style: &Style,
+    pixels_per_point: f32,
+) -> std::fmt::Result {
+    writeln!(
+        svg,
+        r#"<polyline points="{}" fill="none" {}/>"#,
+        points(line),
+        stroke(color, style, pixels_per_point, style.dashed),
+    )
+}
+
+fn points(points: &[Pos2]) -> String {
+    points
+        .iter()
+        .map(|point| format!("{:.1},{:.1}", point.x, point.y))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn rect_path(rect: Rect) -> String {
+    format!(
+        "M{:.1} {:.1} H{:.1} V{:.1} H{:.1} Z",
+        rect.min.x, rect.min.y, rect.max.x, rect.max.y, rect.min.x
+    )
+}
+
+// `fill` or `stroke` attribute with its opacity, SVG colors have no alpha
+fn paint(attribute: &str, color: Color32) -> String {
+    let [r, g, b, a] = color.to_srgba_unmultiplied();
+    format!(
+        r#"{attribute}="rgb({r},{g},{b})" {attribute}-opacity="{:.3}""#,
+        a as f32 / 255.0
+    )
+}
+
+fn fill(color: Color32, style: &Style) -> String {
+    if style.filled {
+        paint("fill", color.gamma_multiply(style.fill_opacity))
+    } else {
+        r#"fill="none""#.to_string()
+    }
+}
+
+// Dashes are as long as on the overlay
+fn stroke(color: Color32, style: &Style, pixels_per_point: f32, dashed: bool) -> String {
+    let width = style.width * pixels_per_point;
+    let mut attributes = format!(
+        r#"{} stroke-width="{:.1}" stroke-linecap="round" stroke-linejoin="round""#,
+        paint("stroke", color),
+        width
+    );
+    if dashed {
+        let _ = write!(
+            attributes,
+            r#" stroke-dasharray="{:.1} {:.1}""#,
+            width * 3.0,
+            width * 2.0
+        );
+    }
+    attributes
+}
+
+fn font(style: &Style, pixels_per_point: f32) -> String {
+    format!(
+        r#"font-family="sans-serif" font-size="{:.1}" font-weight="{}""#,
+        style.font_size * pixels_per_point,
+        if style.bold { "bold" } else { "normal" }
+    )
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,108 @@
+use super::{point_to_line_segment_distance, Tool};
+use eframe::egui::{self, Pos2, Vec2};
+use std::f32::consts::FRAC_PI_4;
+
+// Points of a stroke closer than this to the simplified line are dropped, in points
+const SIMPLIFY_TOLERANCE: f32 = 1.5;
+
+// Length of the pieces of the smoothed curve between two kept points, in points
+const SMOOTH_SPACING: f32 = 4.0;
+
+// The smoothed curve between two kept points has at most this many pieces
+const MAX_SMOOTH_STEPS: f32 = 8.0;
+
+// Nearest crossing of a grid with `spacing` between its lines
+pub fn snap_to_grid(pos: Pos2, spacing: f32) -> Pos2 {
+    egui::pos2(
+        (pos.x / spacing).round() * spacing,
+        (pos.y / spacing).round() * spacing,
+    )
+}
+
+// End of a drag from `start` with Shift held: boxes become squares, lines turn by 45° steps
+pub(super) fn constrain(tool: Tool, start: Pos2, end: Pos2) -> Pos2 {
+    let delta = end - start;
+    match tool {
+        Tool::Rectangle | Tool::Ellipse | Tool::Spotlight | Tool::Redact => {
+            let side = delta.x.abs().max(delta.y.abs());
+            start + egui::vec2(side.copysign(delta.x), side.copysign(delta.y))
+        }
+        Tool::Arrow | Tool::Line | Tool::Callout => {
+            let angle = (delta.angle() / FRAC_PI_4).round() * FRAC_PI_4;
+            start + Vec2::angled(angle) * delta.length()
+        }
+        _ => end,
+    }
+}
+
+// A free hand stroke with the jitter of the mouse removed and fewer points, simplified with
+// Ramer-Douglas-Peucker then rounded with a Catmull-Rom spline through the kept points
+pub fn smooth_stroke(points: &[Pos2], pixels_per_point: f32) -> Vec<Pos2> {
+    let kept = simplify(points, SIMPLIFY_TOLERANCE * pixels_per_point);
+    catmull_rom(&kept, SMOOTH_SPACING * pixels_per_point)
+}
+
+fn simplify(points: &[Pos2], tolerance: f32) -> Vec<Pos2> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+    let last = points.len() - 1;
+    let mut keep = vec![false; points.len()];
+    keep[0] = true;
+    keep[last] = true;
+
+    // Keep the farthest point from the line between the ends of each range, then split there
+    let mut ranges = vec![(0, last)];
+    while let Some((first, last)) = ranges.pop() {
+        let farthest = (first + 1..last)
+            .map(|index| {
+                let distance =
+                    point_to_line_segment_distance(points[index], points[first], points[last]);
+                (index, distance)
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        if let Some((index, distance)) = farthest {
+            if distance > tolerance {
+                keep[index] = true;
+                ranges.push((first, index));
+                ranges.push((index, last));
+            }
+        }
+    }
+
+    points
+        .iter()
+        .zip(keep)
+        .filter_map(|(point, keep)| keep.then_some(*point))
+        .collect()
+}
+
+// Curve through all of `points`, pieces about `spacing` long
+fn catmull_rom(points: &[Pos2], spacing: f32) -> Vec<Pos2> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+    let last = points.len() - 1;
+    let mut curve = vec![points[0]];
+    for index in 0..last {
+        // The ends are repeated to have neighbours on both sides
+        let p0 = points[index.saturating_sub(1)].to_vec2();
+        let p1 = points[index].to_vec2();
+        let p2 = points[index + 1].to_vec2();
+        let p3 = points[(index + 2).min(last)].to_vec2();
+
+        let steps = ((p2 - p1).length() / spacing)
+            .ceil()
+            .clamp(1.0, MAX_SMOOTH_STEPS) as usize;
+        for step in 1..=steps {
+            let t = step as f32 / steps as f32;
+            let point = 0.5
+                * (2.0 * p1
+                    + (p2 - p0) * t
+                    + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t * t
+                    + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t * t * t);
+            curve.push(point.to_pos2());
+        }
+    }
+    curve
+}
@@ -0,0 +1,284 @@
+use super::Shape;
+use eframe::egui::Vec2;
+
+// Change of the annotation list, undone by applying its inverse
+#[derive(Debug, Clone, PartialEq)]
+pub enum Edit {
+    Add(Shape),                          // Appended after the others
+    AddAll(Vec<Shape>),                  // Appended after the others, copies or a loaded file
+    Erase(Vec<(usize, Shape)>),          // Removed shapes with their index, in increasing order
+    Clear(Vec<Shape>),                   // The whole list before it was cleared
+    Replace(Vec<(usize, Shape, Shape)>), // Index, before and after, for resizing and restyling
+    Move { indices: Vec<usize>, offset: Vec2 },
+}
+
+impl Edit {
+    fn apply(&self, shapes: &mut Vec<Shape>) {
+        match self {
+            Edit::Add(shape) => shapes.push(shape.clone()),
+            Edit::AddAll(copies) => shapes.extend(copies.iter().cloned()),
+            Edit::Erase(erased) => {
+                // From the end so the remaining indices stay valid
+                for (index, _) in erased.iter().rev() {
+                    shapes.remove(*index);
+                }
+            }
+            Edit::Clear(_) => shapes.clear(),
+            Edit::Replace(changes) => {
+                for (index, _, after) in changes {
+                    shapes[*index] = after.clone();
+                }
+            }
+            Edit::Move { indices, offset } => translate(shapes, indices, *offset),
+        }
+    }
+
+    fn revert(&self, shapes: &mut Vec<Shape>) {
+        match self {
+            Edit::Add(_) => {
+                shapes.pop();
+            }
+            Edit::AddAll(copies) => shapes.truncate(shapes.len() - copies.len()),
+            Edit::Erase(erased) => {
+                for (index, shape) in erased {
+                    shapes.insert(*index, shape.clone());
+                }
+            }
+            Edit::Clear(previous) => *shapes = previous.clone(),
+            Edit::Replace(changes) => {
+                for (index, before, _) in changes {
+                    shapes[*index] = before.clone();
+                }
+            }
+            Edit::Move { indices, offset } => translate(shapes, indices, -*offset),
+        }
+    }
+}
+
+fn translate(shapes: &mut [Shape], indices: &[usize], offset: Vec2) {
+    for &index in indices {
+        if let Some(shape) = shapes.get_mut(index) {
+            shape.translate(offset);
+        }
+    }
+}
+
+// Undo and redo stacks of the edits made in the overlay
+#[derive(Debug, Default)]
+pub struct History {
+    undo: Vec<Edit>,
+    redo: Vec<Edit>,
+}
+
+impl History {
+    // Apply a new edit, the undone ones can no longer be redone
+    pub fn apply(&mut self, shapes: &mut Vec<Shape>, edit: Edit) {
+        edit.apply(shapes);
+        self.record(edit);
+    }
+
+    // Apply a replacement of the shapes the last edit replaced, as part of that same step,
+    // like each move of a slider while it is dragged. Any other edit is applied as a new one.
+    pub fn apply_merged(&mut self, shapes: &mut Vec<Shape>, edit: Edit) {
+        if let (Some(Edit::Replace(last)), Edit::Replace(changes)) = (self.undo.last_mut(), &edit) {
+            let same_shapes = last.len() == changes.len()
+                && last
+                    .iter()
+                    .zip(changes)
+                    .all(|(last, change)| last.0 == change.0);
+            if same_shapes {
+                edit.apply(shapes);
+                for (last, (_, _, after)) in last.iter_mut().zip(changes) {
+                    last.2 = after.clone();
+                }
+                self.redo.clear();
+                return;
+            }
+        }
+        self.apply(shapes, edit);
+    }
+
+    // Remember an edit already made to the shapes, like a move done while dragging
+    pub fn record(&mut self, edit: Edit) {
+        self.undo.push(edit);
+        self.redo.clear();
+    }
+
+    pub fn undo(&mut self, shapes: &mut Vec<Shape>) -> bool {
+        let Some(edit) = self.undo.pop() else {
+            return false;
+        };
+        edit.revert(shapes);
+        self.redo.push(edit);
+        true
+    }
+
+    pub fn redo(&mut self, shapes: &mut Vec<Shape>) -> bool {
+        let Some(edit) = self.redo.pop() else {
+            return false;
+        };
+        edit.apply(shapes);
+        self.undo.push(edit);
+        true
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::annotation::Style;
+    use eframe::egui::{pos2, vec2, Color32};
+
+    fn arrow(x: f32) -> Shape {
+        Shape::Arrow {
+            start: pos2(x, 0.0),
+            end: pos2(x, 10.0),
+            color: Color32::RED,
+            style: Style::default(),
+        }
+    }
+
+    fn text(content: &str) -> Shape {
+        Shape::Text {
+            position: pos2(0.0, 0.0),
+            content: content.to_string(),
+            color: Color32::RED,
+            style: Style::default(),
+        }
+    }
+
+    #[test]
+    fn undo_and_redo_add() {
+        let mut shapes = Vec::new();
+        let mut history = History::default();
+        history.apply(&mut shapes, Edit::Add(text("a")));
+        history.apply(&mut shapes, Edit::Add(text("b")));
+        assert_eq!(shapes, vec![text("a"), text("b")]);
+
+        assert!(history.undo(&mut shapes));
+        assert_eq!(shapes, vec![text("a")]);
+        assert!(history.redo(&mut shapes));
+        assert_eq!(shapes, vec![text("a"), text("b")]);
+        assert!(!history.can_redo());
+    }
+
+    #[test]
+    fn undo_erase_restores_positions() {
+        let mut shapes = vec![text("a"), text("b"), text("c"), text("d")];
+        let mut history = History::default();
+        let erased = vec![(1, text("b")), (3, text("d"))];
+        history.apply(&mut shapes, Edit::Erase(erased));
+        assert_eq!(shapes, vec![text("a"), text("c")]);
+
+        history.undo(&mut shapes);
+        assert_eq!(shapes, vec![text("a"), text("b"), text("c"), text("d")]);
+        history.redo(&mut shapes);
+        assert_eq!(shapes, vec![text("a"), text("c")]);
+    }
+
+    #[test]
+    fn undo_clear() {
+        let mut shapes = vec![text("a"), arrow(1.0)];
+        let mut history = History::default();
+        let previous = shapes.clone();
+        history.apply(&mut shapes, Edit::Clear(previous));
+        assert!(shapes.is_empty());
+
+        history.undo(&mut shapes);
+        assert_eq!(shapes, vec![text("a"), arrow(1.0)]);
+    }
+
+    #[test]
+    fn undo_recorded_move() {
+        let mut shapes = vec![arrow(0.0), arrow(5.0)];
+        let mut history = History::default();
+        shapes[1].translate(vec2(10.0, 0.0));
+        history.record(Edit::Move {
+            indices: vec![1],
+            offset: vec2(10.0, 0.0),
+        });
+        assert_eq!(shapes, vec![arrow(0.0), arrow(15.0)]);
+
+        history.undo(&mut shapes);
+        assert_eq!(shapes, vec![arrow(0.0), arrow(5.0)]);
+        history.redo(&mut shapes);
+        assert_eq!(shapes, vec![arrow(0.0), arrow(15.0)]);
+    }
+
+    #[test]
+    fn undo_add_all() {
+        let mut shapes = vec![text("a"), text("b")];
+        let mut history = History::default();
+        history.apply(&mut shapes, Edit::AddAll(vec![text("a"), text("b")]));
+        assert_eq!(shapes, vec![text("a"), text("b"), text("a"), text("b")]);
+
+        history.undo(&mut shapes);
+        assert_eq!(shapes, vec![text("a"), text("b")]);
+    }
+
+    #[test]
+    fn undo_and_redo_replace() {
+        let mut shapes = vec![text("a"), arrow(0.0), text("c")];
+        let mut history = History::default();
+        let changes = vec![(1, arrow(0.0), arrow(3.0)), (2, text("c"), text("d"))];
+        history.apply(&mut shapes, Edit::Replace(changes));
+        assert_eq!(shapes, vec![text("a"), arrow(3.0), text("d")]);
+
+        history.undo(&mut shapes);
+        assert_eq!(shapes, vec![text("a"), arrow(0.0), text("c")]);
+        history.redo(&mut shapes);
+        assert_eq!(shapes, vec![text("a"), arrow(3.0), text("d")]);
+    }
+
+    #[test]
+    fn new_edit_drops_redo() {
+        let mut shapes = Vec::new();
+        let mut history = History::default();
+        history.apply(&mut shapes, Edit::Add(text("a")));
+        history.undo(&mut shapes);
+        assert!(history.can_redo());
+
+        history.apply(&mut shapes, Edit::Add(text("b")));
+        assert!(!history.can_redo());
+        assert!(!history.redo(&mut shapes));
+        assert_eq!(shapes, vec![text("b")]);
+    }
+
+    #[test]
+    fn merged_replace_is_undone_at_once() {
+        let mut shapes = vec![arrow(0.0)];
+        let mut history = History::default();
+        history.apply(
+            &mut shapes,
+            Edit::Replace(vec![(0, arrow(0.0), arrow(1.0))]),
+        );
+        history.apply_merged(
+            &mut shapes,
+            Edit::Replace(vec![(0, arrow(1.0), arrow(2.0))]),
+        );
+        assert_eq!(shapes, vec![arrow(2.0)]);
+
+        assert!(history.undo(&mut shapes));
+        assert_eq!(shapes, vec![arrow(0.0)]);
+        assert!(!history.can_undo());
+        assert!(history.redo(&mut shapes));
+        assert_eq!(shapes, vec![arrow(2.0)]);
+    }
+
+    #[test]
+    fn undo_with_empty_history() {
+        let mut shapes = vec![text("a")];
+        let mut history = History::default();
+        assert!(!history.can_undo());
+        assert!(!history.undo(&mut shapes));
+        assert_eq!(shapes, vec![text("a")]);
+    }
+}
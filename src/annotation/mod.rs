@@ -0,0 +1,1834 @@
+use crate::ipc::{self, OverlayCommand, OverlayEvent};
+use eframe::egui::{self, CentralPanel, Color32, Pos2, Rect, Stroke, TopBottomPanel, Vec2};
+use egui::emath::{RectTransform, Rot2};
+use egui::{RichText, StrokeKind};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::mpsc::Receiver;
+use std::time::{Duration, Instant};
+
+pub mod export;
+mod geometry;
+mod history;
+mod style;
+
+use history::{Edit, History};
+pub use style::Style;
+
+// Segments of the outline of ellipses and dashed circles
+const CIRCLE_SEGMENTS: usize = 64;
+
+// Size of the resize handles around the selection, in points
+const HANDLE_SIZE: f32 = 8.0;
+
+// Where the copies of duplicated shapes land, in points from the originals
+const DUPLICATE_OFFSET: f32 = 20.0;
+
+// How much of the color a highlighter stroke keeps, the screen shows through the rest
+const HIGHLIGHTER_OPACITY: f32 = 0.4;
+
+// Darkness of the screen around a spotlight
+const SPOTLIGHT_DIM: u8 = 160;
+
+// Fill of a redaction on the overlay, the captured frames hide what is under it
+const REDACTION_OPACITY: f32 = 0.2;
+
+// Seconds fading ink takes to disappear, at the end of its lifetime
+const FADE_DURATION: f32 = 1.0;
+
+// Coordinates are physical pixels of the annotated monitor. The overlay sends the whole
+// list to the caster each time it changes, and the caster sends it to the receivers.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Shape {
+    Rectangle {
+        rect: Rect,
+        color: Color32,
+        #[serde(default)]
+        style: Style,
+    },
+    Circle {
+        center: Pos2,
+        radius: f32,
+        color: Color32,
+        #[serde(default)]
+        style: Style,
+    },
+    Arrow {
+        start: Pos2,
+        end: Pos2,
+        color: Color32,
+        #[serde(default)]
+        style: Style,
+    },
+    FreeHand {
+        points: Vec<Pos2>,
+        color: Color32,
+        #[serde(default)]
+        style: Style,
+    },
+    Text {
+        position: Pos2,
+        content: String,
+        color: Color32,
+        #[serde(default)]
+        style: Style,
+    },
+    Highlighter {
+        points: Vec<Pos2>,
+        color: Color32,
+        style: Style,
+    },
+    Line {
+        start: Pos2,
+        end: Pos2,
+        color: Color32,
+        style: Style,
+    },
+    Ellipse {
+        rect: Rect, // Bounding box
+        color: Color32,
+        style: Style,
+    },
+    Marker {
+        center: Pos2,
+        number: u32, // Step of a tutorial, one more than the highest already placed
+        color: Color32,
+        style: Style,
+    },
+    Callout {
+        position: Pos2, // Top left corner of the bubble
+        tail: Pos2,     // What the bubble points at
+        content: String,
+        color: Color32,
+        style: Style,
+    },
+    Spotlight {
+        rect: Rect, // Left bright, the rest of the screen is dimmed
+        color: Color32,
+        style: Style,
+    },
+    Redaction {
+        rect: Rect, // Hidden in the captured frames by the caster, never sent as a shape
+        color: Color32,
+        style: Style,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum Tool {
+    #[default]
+    Rectangle,
+    Circle,
+    Arrow,
+    FreeHand,
+    Text,
+    Highlighter,
+    Line,
+    Ellipse,
+    Marker,
+    Callout,
+    Spotlight,
+    Redact,
+    Eraser,
+    Select,
+}
+
+// What dragging with the selection tool does, decided when the drag starts
+#[derive(Debug, Clone)]
+enum SelectDrag {
+    Move(Vec2), // Offset of the selected shapes so far
+    Resize {
+        anchor: Pos2,          // Corner of the selection opposite to the dragged handle
+        from: Rect,            // Bounds of the selection before the drag
+        originals: Vec<Shape>, // Selected shapes before the drag
+    },
+    Band {
+        start: Pos2,
+        end: Pos2,
+    },
+}
+
+pub struct AnnotationApp {
+    commands: Receiver<OverlayCommand>, // From the caster
+    current_tool: Tool,
+    is_drawing: bool,
+    is_restyling: bool, // Dragging in the style menu or the color picker, a single edit
+    drag_start: Option<Pos2>,
+    current_shape: Option<Shape>,
+    annotations: Vec<Shape>,
+    free_hand_points: Vec<Pos2>,
+    current_color: Color32,
+    styles: HashMap<Tool, Style>, // Of the shapes drawn next with each tool
+    show_tutorial: bool,
+    show_text_input: bool,
+    temp_text: String,
+    text_position: Option<Pos2>,
+    callout_tail: Option<Pos2>, // The text being written goes in a callout
+    history: History,
+    selection: Vec<usize>, // Indices in `annotations`, in increasing order
+    select_drag: Option<SelectDrag>,
+    ink_mode: bool,             // Free draw and arrows fade out instead of staying
+    ink_seconds: f32,           // Lifetime of the ink
+    ink: Vec<(Shape, Instant)>, // Fading shapes and when they were drawn, not in the history
+    grid_snap: bool,
+    grid_spacing: f32,      // Between the lines of the grid, in points
+    smooth_free_hand: bool, // Simplify and smooth free draw and highlighter strokes
+    published: Vec<Shape>,  // Last list sent to the caster
+}
+
+impl AnnotationApp {
+    pub fn new(commands: Receiver<OverlayCommand>) -> Self {
+        Self {
+            commands,
+            current_tool: Tool::default(),
+            is_drawing: false,
+            is_restyling: false,
+            drag_start: None,
+            current_shape: None,
+            annotations: Vec::new(),
+            free_hand_points: Vec::new(),
+            current_color: Color32::from_rgba_unmultiplied(255, 0, 0, 255),
+            styles: HashMap::new(),
+            show_tutorial: false,
+            show_text_input: false,
+            temp_text: String::new(),
+            text_position: None,
+            callout_tail: None,
+            history: History::default(),
+            selection: Vec::new(),
+            select_drag: None,
+            ink_mode: false,
+            ink_seconds: 3.0,
+            ink: Vec::new(),
+            grid_snap: false,
+            grid_spacing: 20.0,
+            smooth_free_hand: false,
+            published: Vec::new(),
+        }
+    }
+}
+
+impl eframe::App for AnnotationApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        if ctx.input(|i| i.viewport().close_requested()) {
+            std::process::exit(0);
+        }
+
+        if ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
+            std::process::exit(0);
+        }
+
+        while let Ok(command) = self.commands.try_recv() {
+            match command {
+                OverlayCommand::SetTool(tool) => self.current_tool = tool,
+                OverlayCommand::Clear => self.clear(),
+                OverlayCommand::Close => std::process::exit(0),
+                command => {
+                    let message = format!("Unexpected command: {:?}", command);
+                    ipc::send(&OverlayEvent::Error(message));
+                }
+            }
+        }
+
+        let scale_factor = ctx.pixels_per_point();
+        if !ctx.input(|i| i.pointer.any_down()) {
+            self.is_restyling = false;
+        }
+
+        // The text input has its own undo
+        if !self.show_text_input {
+            let (undo, redo) = ctx.input_mut(|i| {
+                let redo = i.consume_key(egui::Modifiers::COMMAND, egui::Key::Y)
+                    || i.consume_key(
+                        egui::Modifiers::COMMAND | egui::Modifiers::SHIFT,
+                        egui::Key::Z,
+                    );
+                let undo = i.consume_key(egui::Modifiers::COMMAND, egui::Key::Z);
+                (undo, redo)
+            });
+            if undo {
+                self.undo();
+            }
+            if redo {
+                self.redo();
+            }
+
+            let (delete, duplicate) = ctx.input_mut(|i| {
+                let delete =
+                    i.key_pressed(egui::Key::Delete) || i.key_pressed(egui::Key::Backspace);
+                let duplicate = i.consume_key(egui::Modifiers::COMMAND, egui::Key::D);
+                (delete, duplicate)
+            });
+            if delete {
+                self.delete_selection();
+            }
+            if duplicate {
+                self.duplicate_selection(scale_factor);
+            }
+        }
+
+        // Add tool selection panel
+        TopBottomPanel::top("tools").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                if ui
+                    .selectable_label(self.current_tool == Tool::Rectangle, "⬜ Rectangle")
+                    .clicked()
+                {
+                    self.current_tool = Tool::Rectangle;
+                }
+                if ui
+                    .selectable_label(self.current_tool == Tool::Circle, "⭕ Circle")
+                    .clicked()
+                {
+                    self.current_tool = Tool::Circle;
+                }
+                if ui
+                    .selectable_label(self.current_tool == Tool::Arrow, "-> Arrow")
+                    .clicked()
+                {
+                    self.current_tool = Tool::Arrow;
+                }
+                if ui
+                    .selectable_label(self.current_tool == Tool::FreeHand, "🖊 Free Draw")
+                    .clicked()
+                {
+                    self.current_tool = Tool::FreeHand;
+                }
+                if ui
+                    .selectable_label(self.current_tool == Tool::Text, "T Text")
+                    .clicked()
+                {
+                    self.current_tool = Tool::Text;
+                }
+                if ui
+                    .selectable_label(self.current_tool == Tool::Highlighter, "🖍 Highlighter")
+                    .clicked()
+                {
+                    self.current_tool = Tool::Highlighter;
+                }
+                if ui
+                    .selectable_label(self.current_tool == Tool::Line, "╱ Line")
+                    .clicked()
+                {
+                    self.current_tool = Tool::Line;
+                }
+                if ui
+                    .selectable_label(self.current_tool == Tool::Ellipse, "⬭ Ellipse")
+                    .clicked()
+                {
+                    self.current_tool = Tool::Ellipse;
+                }
+                if ui
+                    .selectable_label(self.current_tool == Tool::Marker, "① Marker")
+                    .on_hover_text("Numbered steps, click to place the next one")
+                    .clicked()
+                {
+                    self.current_tool = Tool::Marker;
+                }
+                if ui
+                    .selectable_label(self.current_tool == Tool::Callout, "💬 Callout")
+                    .on_hover_text("Drag from what to point at to where the bubble goes")
+                    .clicked()
+                {
+                    self.current_tool = Tool::Callout;
+                }
+                if ui
+                    .selectable_label(self.current_tool == Tool::Spotlight, "🔦 Spotlight")
+                    .on_hover_text("Dim everything but a rectangle")
+                    .clicked()
+                {
+                    self.current_tool = Tool::Spotlight;
+                }
+                if ui
+                    .selectable_label(self.current_tool == Tool::Redact, "▦ Redact")
+                    .on_hover_text("Blur or pixelate a rectangle in the stream and recordings")
+                    .clicked()
+                {
+                    self.current_tool = Tool::Redact;
+                }
+                if ui
+                    .selectable_label(self.current_tool == Tool::Eraser, "Eraser")//TODO: add icon
+                    .clicked()
+                {
+                    self.current_tool = Tool::Eraser;
+                }
+                if ui
+                    .selectable_label(self.current_tool == Tool::Select, "⬚ Select")
+                    .clicked()
+                {
+                    self.current_tool = Tool::Select;
+                }
+
+                ui.separator();
+
+                // Color picker
+                let color_array = self.current_color.to_array();
+                let mut color_f32 = [
+                    color_array[0] as f32 / 255.0,
+                    color_array[1] as f32 / 255.0,
+                    color_array[2] as f32 / 255.0,
+                    color_array[3] as f32 / 255.0,
+                ];
+
+                let color_response = ui.color_edit_button_rgba_unmultiplied(&mut color_f32);
+
+                self.current_color = Color32::from_rgba_unmultiplied(
+                    (color_f32[0] * 255.0) as u8,
+                    (color_f32[1] * 255.0) as u8,
+                    (color_f32[2] * 255.0) as u8,
+                    (color_f32[3] * 255.0) as u8,
+                );
+                if color_response.changed() {
+                    let color = self.current_color;
+                    self.restyle_selection(|shape| shape.set_color(color));
+                }
+
+                match self.current_tool {
+                    Tool::Eraser => {}
+                    // The style of the first selected shape, applied to all of them
+                    Tool::Select => {
+                        if let Some(&first) = self.selection.first() {
+                            let mut style = self.annotations[first].style().clone();
+                            if style.show_menu(ui, Tool::Select) {
+                                self.restyle_selection(|shape| *shape.style_mut() = style.clone());
+                            }
+                        }
+                    }
+                    tool => {
+                        self.styles
+                            .entry(tool)
+                            .or_insert_with(|| Style::for_tool(tool))
+                            .show_menu(ui, tool);
+                    }
+                }
+
+                ui.separator();
+                ui.checkbox(&mut self.ink_mode, "✨ Ink")
+                    .on_hover_text("Free draw and arrows fade out on their own");
+                if self.ink_mode {
+                    ui.add(
+                        egui::DragValue::new(&mut self.ink_seconds)
+                            .range(1.0..=60.0)
+                            .speed(0.1)
+                            .suffix(" s"),
+                    )
+                    .on_hover_text("Seconds before the ink disappears");
+                }
+
+                ui.separator();
+                ui.checkbox(&mut self.grid_snap, "# Grid")
+                    .on_hover_text("Shapes snap to a grid");
+                if self.grid_snap {
+                    ui.add(
+                        egui::DragValue::new(&mut self.grid_spacing)
+                            .range(5.0..=200.0)
+                            .suffix(" pt"),
+                    )
+                    .on_hover_text("Spacing of the grid");
+                }
+                ui.checkbox(&mut self.smooth_free_hand, "〰 Smooth")
+                    .on_hover_text("Free draw and highlighter strokes are smoothed when finished");
+
+                if !self.selection.is_empty() {
+                    ui.separator();
+                    if ui.button("Delete").clicked() {
+                        self.delete_selection();
+                    }
+                    if ui.button("⧉ Duplicate").on_hover_text("Ctrl+D").clicked() {
+                        self.duplicate_selection(scale_factor);
+                    }
+                }
+
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    // Exit button on the right
+                    if ui.button(RichText::new("❌").size(20.0)).clicked() {
+                        std::process::exit(0);
+                    }
+                    // Tutorial button
+                    if ui.button(RichText::new("❓").size(20.0)).clicked() {
+                        self.show_tutorial = !self.show_tutorial;
+                    }
+                    ui.separator();
+                    if ui.button(RichText::new("🗑 Clear")).clicked() {
+                        self.clear();
+                    }
+                    ui.menu_button("💾 File", |ui| {
+                        if ui.button("Save...").clicked() {
+                            ui.close_menu();
+                            self.save_file();
+                        }
+                        if ui.button("Open...").clicked() {
+                            ui.close_menu();
+                            self.open_file();
+                        }
+                        if ui.button("Export as SVG...").clicked() {
+                            ui.close_menu();
+                            self.export_svg(ctx.screen_rect().size() * scale_factor, scale_factor);
+                        }
+                    });
+                    if ui
+                        .add_enabled(self.history.can_redo(), egui::Button::new("↷ Redo"))
+                        .on_hover_text("Ctrl+Y")
+                        .clicked()
+                    {
+                        self.redo();
+                    }
+                    if ui
+                        .add_enabled(self.history.can_undo(), egui::Button::new("↶ Undo"))
+                        .on_hover_text("Ctrl+Z")
+                        .clicked()
+                    {
+                        self.undo();
+                    }
+                });
+            });
+        });
+
+        // Only the selection tool keeps shapes selected
+        if self.current_tool != Tool::Select {
+            self.selection.clear();
+        }
+
+        // Show tutorial window
+        if self.show_tutorial {
+            egui::Window::new("Tutorial")
+                .collapsible(false)
+                .resizable(false)
+                .show(ctx, |ui| {
+                    ui.label("How to use the annotation tool:");
+                    ui.label("1. Select a drawing tool from the top bar");
+                    ui.label("2. Choose a color using the color picker");
+                    ui.label("3. Click and drag to draw");
+                    ui.label("4. Release to finish the shape");
+                    ui.label("5. With Select, click or drag around shapes to change them");
+                    ui.label("6. Redact hides a rectangle from the stream, not from your screen");
+                    ui.label("\nKeyboard shortcuts:");
+                    ui.label("Ctrl+Z - Undo");
+                    ui.label("Ctrl+Y - Redo");
+                    ui.label("Ctrl+D - Duplicate the selection");
+                    ui.label("Shift - Squares, circles and lines at 45° while drawing");
+                    ui.label("Delete - Delete the selection");
+                    ui.label("ESC - Exit application");
+                    if ui.button("Close").clicked() {
+                        self.show_tutorial = false;
+                    }
+                });
+        }
+
+        CentralPanel::default()
+            .frame(egui::Frame::NONE)
+            .show(ctx, |ui| {
+                let response = ui.allocate_rect(
+                    ui.available_rect_before_wrap(),
+                    egui::Sense::click_and_drag(),
+                );
+                let pointer_pos = response
+                    .interact_pointer_pos()
+                    .map(|pos| egui::pos2(pos.x * scale_factor, pos.y * scale_factor));
+                let shift = ui.input(|i| i.modifiers.shift); // Adds to the selection, constrains shapes
+
+                // Handle text tool separately
+                if self.current_tool == Tool::Text && response.clicked() {
+                    if let Some(pos) = pointer_pos {
+                        self.open_text_input(self.snap(pos, scale_factor), None);
+                    }
+                } else if self.current_tool == Tool::Marker && response.clicked() {
+                    if let Some(pos) = pointer_pos {
+                        let shape = Shape::Marker {
+                            center: self.snap(pos, scale_factor),
+                            number: self.next_marker_number(),
+                            color: self.current_color,
+                            style: self.tool_style(),
+                        };
+                        self.history.apply(&mut self.annotations, Edit::Add(shape));
+                    }
+                } else if self.current_tool == Tool::Select && response.clicked() {
+                    if let Some(pos) = pointer_pos {
+                        self.select_at(pos, scale_factor, shift);
+                    }
+                }
+                // Handle other tools
+                else if response.drag_started() {
+                    if let Some(pos) = pointer_pos {
+                        self.drag_start = Some(self.snap(pos, scale_factor));
+                        self.is_drawing = true;
+                        if matches!(self.current_tool, Tool::FreeHand | Tool::Highlighter) {
+                            self.free_hand_points.clear();
+                            self.free_hand_points.push(pos);
+                        }
+                        if self.current_tool == Tool::Select {
+                            self.select_drag =
+                                Some(self.start_select_drag(pos, scale_factor, shift));
+                        }
+                    }
+                }
+
+                // Handle drawing
+                if self.is_drawing {
+                    if let Some(current_pos) =
+                        pointer_pos.map(|pos| self.shape_end(pos, shift, scale_factor))
+                    {
+                        match self.current_tool {
+                            Tool::Rectangle => {
+                                if let Some(start) = self.drag_start {
+                                    self.current_shape = Some(Shape::Rectangle {
+                                        rect: Rect::from_two_pos(start, current_pos),
+                                        color: self.current_color,
+                                        style: self.tool_style(),
+                                    });
+                                }
+                            }
+                            Tool::Circle => {
+                                if let Some(start) = self.drag_start {
+                                    let radius = (current_pos - start).length();
+                                    self.current_shape = Some(Shape::Circle {
+                                        center: start,
+                                        radius,
+                                        color: self.current_color,
+                                        style: self.tool_style(),
+                                    });
+                                }
+                            }
+                            Tool::Arrow => {
+                                if let Some(start) = self.drag_start {
+                                    self.current_shape = Some(Shape::Arrow {
+                                        start,
+                                        end: current_pos,
+                                        color: self.current_color,
+                                        style: self.tool_style(),
+                                    });
+                                }
+                            }
+                            Tool::FreeHand => {
+                                self.free_hand_points.push(current_pos);
+                                self.current_shape = Some(Shape::FreeHand {
+                                    points: self.free_hand_points.clone(),
+                                    color: self.current_color,
+                                    style: self.tool_style(),
+                                });
+                            }
+                            Tool::Text => {
+                                if !self.show_text_input && response.clicked() {
+                                    if let Some(pos) = pointer_pos {
+                                        self.open_text_input(pos, None);
+                                    }
+                                }
+                            }
+                            Tool::Highlighter => {
+                                self.free_hand_points.push(current_pos);
+                                self.current_shape = Some(Shape::Highlighter {
+                                    points: self.free_hand_points.clone(),
+                                    color: self.current_color,
+                                    style: self.tool_style(),
+                                });
+                            }
+                            Tool::Line => {
+                                if let Some(start) = self.drag_start {
+                                    self.current_shape = Some(Shape::Line {
+                                        start,
+                                        end: current_pos,
+                                        color: self.current_color,
+                                        style: self.tool_style(),
+                                    });
+                                }
+                            }
+                            Tool::Ellipse => {
+                                if let Some(start) = self.drag_start {
+                                    self.current_shape = Some(Shape::Ellipse {
+                                        rect: Rect::from_two_pos(start, current_pos),
+                                        color: self.current_color,
+                                        style: self.tool_style(),
+                                    });
+                                }
+                            }
+                            Tool::Marker => {}
+                            // The text is asked for once the bubble is placed
+                            Tool::Callout => {
+                                if let Some(start) = self.drag_start {
+                                    self.current_shape = Some(Shape::Callout {
+                                        position: current_pos,
+                                        tail: start,
+                                        content: "…".to_string(),
+                                        color: self.current_color,
+                                        style: self.tool_style(),
+                                    });
+                                }
+                            }
+                            Tool::Spotlight => {
+                                if let Some(start) = self.drag_start {
+                                    self.current_shape = Some(Shape::Spotlight {
+                                        rect: Rect::from_two_pos(start, current_pos),
+                                        color: self.current_color,
+                                        style: self.tool_style(),
+                                    });
+                                }
+                            }
+                            Tool::Redact => {
+                                if let Some(start) = self.drag_start {
+                                    self.current_shape = Some(Shape::Redaction {
+                                        rect: Rect::from_two_pos(start, current_pos),
+                                        color: self.current_color,
+                                        style: self.tool_style(),
+                                    });
+                                }
+                            }
+                            Tool::Eraser => {
+                                let erased: Vec<(usize, Shape)> = self
+                                    .annotations
+                                    .iter()
+                                    .enumerate()
+                                    .filter(|(_, shape)| {
+                                        AnnotationApp::shape_contains_point(
+                                            shape,
+                                            current_pos,
+                                            scale_factor,
+                                        )
+                                    })
+                                    .map(|(i, shape)| (i, shape.clone()))
+                                    .collect();
+                                if !erased.is_empty() {
+                                    self.history
+                                        .apply(&mut self.annotations, Edit::Erase(erased));
+                                }
+                            }
+                            Tool::Select => match &mut self.select_drag {
+                                Some(SelectDrag::Move(offset)) => {
+                                    // Physical pixels, like the shapes
+                                    let delta = response.drag_delta() * scale_factor;
+                                    for &index in &self.selection {
+                                        self.annotations[index].translate(delta);
+                                    }
+                                    *offset += delta;
+                                }
+                                Some(SelectDrag::Resize {
+                                    anchor,
+                                    from,
+                                    originals,
+                                }) => {
+                                    let to = Rect::from_two_pos(*anchor, current_pos);
+                                    for (&index, original) in
+                                        self.selection.iter().zip(originals.iter())
+                                    {
+                                        let mut shape = original.clone();
+                                        shape.resize(*from, to);
+                                        self.annotations[index] = shape;
+                                    }
+                                }
+                                Some(SelectDrag::Band { end, .. }) => *end = current_pos,
+                                None => {}
+                            },
+                        }
+                    }
+                }
+
+                // Text input window - Moved outside the main panel
+                if self.show_text_input {
+                    let mut show = true;
+                    egui::Window::new("Write your text")
+                        .open(&mut show)
+                        .collapsible(false)
+                        .resizable(false)
+                        .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                        .show(ctx, |ui| {
+                            ui.vertical_centered_justified(|ui| {
+                                ui.add_space(8.0);
+                                // Text input with larger size
+                                ui.add_sized(
+                                    [200.0, 30.0],
+                                    egui::TextEdit::singleline(&mut self.temp_text)
+                                        .hint_text("Type your text here..."),
+                                );
+                                ui.add_space(8.0);
+
+                                ui.horizontal(|ui| {
+                                    if ui
+                                        .add(
+                                            egui::Button::new(
+                                                RichText::new("Insert")
+                                                    .size(16.0)
+                                                    .color(Color32::WHITE),
+                                            )
+                                            .fill(Color32::from_rgb(0, 150, 0))
+                                            .min_size(egui::vec2(80.0, 30.0)),
+                                        )
+                                        .clicked() && !self.temp_text.is_empty() {
+                                        if let Some(pos) = self.text_position {
+                                            let content = self.temp_text.clone();
+                                            let shape = match self.callout_tail.take() {
+                                                Some(tail) => Shape::Callout {
+                                                    position: pos,
+                                                    tail,
+                                                    content,
+                                                    color: self.current_color,
+                                                    style: self.tool_style(),
+                                                },
+                                                None => Shape::Text {
+                                                    position: pos,
+                                                    content,
+                                                    color: self.current_color,
+                                                    style: self.tool_style(),
+                                                },
+                                            };
+                                            self.history
+                                                .apply(&mut self.annotations, Edit::Add(shape));
+                                        }
+                                        self.show_text_input = false;
+                                    }
+
+                                    if ui
+                                        .add(
+                                            egui::Button::new(
+                                                RichText::new("Cancel")
+                                                    .size(16.0)
+                                                    .color(Color32::WHITE),
+                                            )
+                                            .fill(Color32::from_rgb(150, 0, 0))
+                                            .min_size(egui::vec2(80.0, 30.0)),
+                                        )
+                                        .clicked()
+                                    {
+                                        self.show_text_input = false;
+                                    }
+                                });
+                            });
+                        });
+
+                    if !show {
+                        self.show_text_input = false;
+                    }
+                }
+
+                // Handle drawing end
+                if response.drag_stopped() {
+                    let mut shape = self.current_shape.take();
+                    if self.smooth_free_hand {
+                        if let Some(
+                            Shape::FreeHand { points, .. } | Shape::Highlighter { points, .. },
+                        ) = &mut shape
+                        {
+                            *points = geometry::smooth_stroke(points, scale_factor);
+                        }
+                    }
+                    match shape {
+                        Some(Shape::Callout { position, tail, .. }) => {
+                            self.open_text_input(position, Some(tail));
+                        }
+                        Some(mut shape)
+                            if self.ink_mode
+                                && matches!(
+                                    shape,
+                                    Shape::FreeHand { .. } | Shape::Arrow { .. }
+                                ) =>
+                        {
+                            shape.style_mut().fade_after = Some(self.ink_seconds);
+                            self.ink.push((shape, Instant::now()));
+                        }
+                        Some(shape) => self.history.apply(&mut self.annotations, Edit::Add(shape)),
+                        None => {}
+                    }
+                    if let Some(drag) = self.select_drag.take() {
+                        self.finish_select_drag(drag, scale_factor);
+                    }
+                    self.drag_start = None;
+                    self.is_drawing = false;
+                }
+
+                // Physical pixels of the shapes to points of the overlay
+                let screen = ctx.screen_rect();
+                let to_screen = RectTransform::from_to(
+                    Rect::from_min_size(Pos2::ZERO, screen.size() * scale_factor),
+                    Rect::from_min_size(Pos2::ZERO, screen.size()),
+                );
+
+                // Draw all existing annotations
+                let painter = ui.painter();
+                if self.grid_snap {
+                    draw_grid(painter, screen, self.grid_spacing);
+                }
+                for shape in &self.annotations {
+                    draw_shape(painter, shape, &to_screen, scale_factor);
+                }
+
+                for (shape, drawn) in &self.ink {
+                    if let Some(shape) = shape.faded(drawn.elapsed()) {
+                        draw_shape(painter, &shape, &to_screen, scale_factor);
+                    }
+                }
+
+                // Draw current shape while dragging
+                if let Some(shape) = &self.current_shape {
+                    draw_shape(painter, shape, &to_screen, scale_factor);
+                }
+
+                self.draw_selection(painter, &to_screen, scale_factor);
+            });
+
+        // Expired ink leaves the published list, the fade of the rest is animated
+        self.ink
+            .retain(|(shape, drawn)| shape.faded(drawn.elapsed()).is_some());
+        if !self.ink.is_empty() {
+            ctx.request_repaint_after(Duration::from_millis(30));
+        }
+
+        self.publish();
+    }
+}
+
+impl AnnotationApp {
+    // Not while dragging, the shape being drawn or moved is not in the history yet
+    fn undo(&mut self) {
+        if !self.is_drawing {
+            self.selection.clear();
+            self.history.undo(&mut self.annotations);
+        }
+    }
+
+    fn redo(&mut self) {
+        if !self.is_drawing {
+            self.selection.clear();
+            self.history.redo(&mut self.annotations);
+        }
+    }
+
+    // Everything on the overlay goes, the shapes in a single step that can be undone
+    fn clear(&mut self) {
+        self.ink.clear();
+        if !self.annotations.is_empty() {
+            let previous = self.annotations.clone();
+            self.selection.clear();
+            self.history
+                .apply(&mut self.annotations, Edit::Clear(previous));
+        }
+    }
+
+    // Index of the topmost shape under the pointer
+    fn shape_at(&self, pos: Pos2, scale_factor: f32) -> Option<usize> {
+        self.annotations
+            .iter()
+            .rposition(|shape| AnnotationApp::shape_contains_point(shape, pos, scale_factor))
+    }
+
+    fn selection_bounds(&self, pixels_per_point: f32) -> Option<Rect> {
+        self.selection
+            .iter()
+            .map(|&index| self.annotations[index].bounds(pixels_per_point))
+            .reduce(|a, b| a.union(b))
+    }
+
+    // Select the shape under the pointer, or toggle it when adding to the selection
+    fn select_at(&mut self, pos: Pos2, scale_factor: f32, add: bool) {
+        match self.shape_at(pos, scale_factor) {
+            Some(index) if add => match self.selection.binary_search(&index) {
+                Ok(position) => {
+                    self.selection.remove(position);
+                }
+                Err(position) => self.selection.insert(position, index),
+            },
+            Some(index) => self.selection = vec![index],
+            None if add => {}
+            None => self.selection.clear(),
+        }
+    }
+
+    fn start_select_drag(&mut self, pos: Pos2, scale_factor: f32, add: bool) -> SelectDrag {
+        if let Some(bounds) = self.selection_bounds(scale_factor) {
+            let reach = HANDLE_SIZE * scale_factor;
+            if let Some(handle) = corners(bounds)
+                .into_iter()
+                .find(|corner| corner.distance(pos) <= reach)
+            {
+                return SelectDrag::Resize {
+                    anchor: bounds.min + (bounds.max - handle),
+                    from: bounds,
+                    originals: self
+                        .selection
+                        .iter()
+                        .map(|&index| self.annotations[index].clone())
+                        .collect(),
+                };
+            }
+        }
+
+        match self.shape_at(pos, scale_factor) {
+            Some(index) => {
+                // Dragging a shape outside the selection moves that shape only
+                if let Err(position) = self.selection.binary_search(&index) {
+                    if !add {
+                        self.selection.clear();
+                        self.selection.push(index);
+                    } else {
+                        self.selection.insert(position, index);
+                    }
+                }
+                SelectDrag::Move(Vec2::ZERO)
+            }
+            None => {
+                if !add {
+                    self.selection.clear();
+                }
+                SelectDrag::Band {
+                    start: pos,
+                    end: pos,
+                }
+            }
+        }
+    }
+
+    // The shapes already changed while dragging, only the history is left
+    fn finish_select_drag(&mut self, drag: SelectDrag, pixels_per_point: f32) {
+        match drag {
+            SelectDrag::Move(offset) => {
+                if offset != Vec2::ZERO {
+                    self.history.record(Edit::Move {
+                        indices: self.selection.clone(),
+                        offset,
+                    });
+                }
+            }
+            SelectDrag::Resize { originals, .. } => {
+                let changes: Vec<(usize, Shape, Shape)> = self
+                    .selection
+                    .iter()
+                    .zip(originals)
+                    .filter(|(&index, original)| self.annotations[index] != *original)
+                    .map(|(&index, original)| (index, original, self.annotations[index].clone()))
+                    .collect();
+                if !changes.is_empty() {
+                    self.history.record(Edit::Replace(changes));
+                }
+            }
+            SelectDrag::Band { start, end } => {
+                let band = Rect::from_two_pos(start, end);
+                for (index, shape) in self.annotations.iter().enumerate() {
+                    if shape.bounds(pixels_per_point).intersects(band) {
+                        if let Err(position) = self.selection.binary_search(&index) {
+                            self.selection.insert(position, index);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn delete_selection(&mut self) {
+        if self.is_drawing || self.selection.is_empty() {
+            return;
+        }
+        let erased = self
+            .selection
+            .drain(..)
+            .map(|index| (index, self.annotations[index].clone()))
+            .collect();
+        self.history
+            .apply(&mut self.annotations, Edit::Erase(erased));
+    }
+
+    // Copies land next to the originals and become the selection
+    fn duplicate_selection(&mut self, pixels_per_point: f32) {
+        if self.is_drawing || self.selection.is_empty() {
+            return;
+        }
+        let offset = Vec2::splat(DUPLICATE_OFFSET * pixels_per_point);
+        let copies: Vec<Shape> = self
+            .selection
+            .iter()
+            .map(|&index| {
+                let mut copy = self.annotations[index].clone();
+                copy.translate(offset);
+                copy
+            })
+            .collect();
+        let first = self.annotations.len();
+        self.selection = (first..first + copies.len()).collect();
+        self.history
+            .apply(&mut self.annotations, Edit::AddAll(copies));
+    }
+
+    // Where shapes start and end under the pointer, on the grid when snapping
+    fn snap(&self, pos: Pos2, pixels_per_point: f32) -> Pos2 {
+        let snaps = !matches!(
+            self.current_tool,
+            Tool::FreeHand | Tool::Highlighter | Tool::Eraser | Tool::Select
+        );
+        if self.grid_snap && snaps {
+            geometry::snap_to_grid(pos, self.grid_spacing * pixels_per_point)
+        } else {
+            pos
+        }
+    }
+
+    // End of the shape being dragged, constrained while Shift is held
+    fn shape_end(&self, pos: Pos2, constrain: bool, pixels_per_point: f32) -> Pos2 {
+        let pos = self.snap(pos, pixels_per_point);
+        match self.drag_start {
+            Some(start) if constrain => geometry::constrain(self.current_tool, start, pos),
+            _ => pos,
+        }
+    }
+
+    fn tool_style(&self) -> Style {
+        self.styles
+            .get(&self.current_tool)
+            .cloned()
+            .unwrap_or_else(|| Style::for_tool(self.current_tool))
+    }
+
+    // Ask for the text of a text shape, or of a callout pointing at `callout_tail`
+    fn open_text_input(&mut self, position: Pos2, callout_tail: Option<Pos2>) {
+        self.show_text_input = true;
+        self.text_position = Some(position);
+        self.callout_tail = callout_tail;
+        self.temp_text.clear();
+    }
+
+    // Markers count up from 1, undoing one frees its number again
+    fn next_marker_number(&self) -> u32 {
+        self.annotations
+            .iter()
+            .filter_map(|shape| match shape {
+                Shape::Marker { number, .. } => Some(*number),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0)
+            + 1
+    }
+
+    // The color picker and the style menu apply to the selected shapes too
+    fn restyle_selection(&mut self, restyle: impl Fn(&mut Shape)) {
+        if self.is_drawing {
+            return;
+        }
+        let changes: Vec<(usize, Shape, Shape)> = self
+            .selection
+            .iter()
+            .filter_map(|&index| {
+                let before = self.annotations[index].clone();
+                let mut after = before.clone();
+                restyle(&mut after);
+                (after != before).then_some((index, before, after))
+            })
+            .collect();
+        if changes.is_empty() {
+            return;
+        }
+        // Every step of a drag changes the same shapes, it is undone at once
+        if self.is_restyling {
+            self.history
+                .apply_merged(&mut self.annotations, Edit::Replace(changes));
+        } else {
+            self.history
+                .apply(&mut self.annotations, Edit::Replace(changes));
+        }
+        self.is_restyling = true;
+    }
+
+    // Bounds and resize handles of the selection, the rubber band while dragging one
+    fn draw_selection(
+        &self,
+        painter: &egui::Painter,
+        to_screen: &RectTransform,
+        scale_factor: f32,
+    ) {
+        let color = Color32::from_rgb(0, 150, 255);
+        if let Some(bounds) = self.selection_bounds(scale_factor) {
+            let bounds = to_screen.transform_rect(bounds);
+            painter.rect_stroke(bounds, 0.0, Stroke::new(1.0, color), StrokeKind::Outside);
+            for corner in corners(bounds) {
+                let handle = Rect::from_center_size(corner, Vec2::splat(HANDLE_SIZE));
+                painter.rect_filled(handle, 0.0, Color32::WHITE);
+                painter.rect_stroke(handle, 0.0, Stroke::new(1.0, color), StrokeKind::Inside);
+            }
+        }
+        if let Some(SelectDrag::Band { start, end }) = &self.select_drag {
+            let band = to_screen.transform_rect(Rect::from_two_pos(*start, *end));
+            painter.rect_filled(band, 0.0, color.gamma_multiply(0.15));
+            painter.rect_stroke(band, 0.0, Stroke::new(1.0, color), StrokeKind::Inside);
+        }
+    }
+
+    fn save_file(&self) {
+        let Some(path) = rfd::FileDialog::new()
+            .set_title("Save annotations as...")
+            .set_file_name("annotations.json")
+            .add_filter("Annotations", &["json"])
+            .save_file()
+        else {
+            return;
+        };
+        if let Err(e) = export::save_json(&path, &self.annotations) {
+            let message = format!(
+                "Failed to save the annotations to {}: {}",
+                path.display(),
+                e
+            );
+            ipc::send(&OverlayEvent::Error(message));
+        }
+    }
+
+    // The saved shapes are added to the ones drawn so far, undone as a whole
+    fn open_file(&mut self) {
+        let Some(path) = rfd::FileDialog::new()
+            .set_title("Open annotations...")
+            .add_filter("Annotations", &["json"])
+            .pick_file()
+        else {
+            return;
+        };
+        match export::load_json(&path) {
+            Ok(shapes) if !shapes.is_empty() => {
+                self.selection.clear();
+                self.history
+                    .apply(&mut self.annotations, Edit::AddAll(shapes));
+            }
+            Ok(_) => {}
+            Err(e) => {
+                let message = format!("Failed to open the annotations {}: {}", path.display(), e);
+                ipc::send(&OverlayEvent::Error(message));
+            }
+        }
+    }
+
+    fn export_svg(&self, size: Vec2, pixels_per_point: f32) {
+        let Some(path) = rfd::FileDialog::new()
+            .set_title("Export annotations as...")
+            .set_file_name("annotations.svg")
+            .add_filter("SVG Image", &["svg"])
+            .save_file()
+        else {
+            return;
+        };
+        if let Err(e) = export::save_svg(&path, &self.annotations, size, pixels_per_point) {
+            let message = format!(
+                "Failed to export the annotations to {}: {}",
+                path.display(),
+                e
+            );
+            ipc::send(&OverlayEvent::Error(message));
+        }
+    }
+
+    // Let the caster know the annotations changed, finished shapes only. Ink is sent
+    // unfaded, receivers fade it from when it reaches them.
+    fn publish(&mut self) {
+        let shapes: Vec<Shape> = self
+            .annotations
+            .iter()
+            .chain(self.ink.iter().map(|(shape, _)| shape))
+            .cloned()
+            .collect();
+        if shapes == self.published {
+            return;
+        }
+        ipc::send(&OverlayEvent::Shapes(shapes.clone()));
+        self.published = shapes;
+    }
+
+    fn shape_contains_point(shape: &Shape, point: Pos2, scale_factor: f32) -> bool {
+        let scaled_point = Pos2::new(point.x / scale_factor, point.y / scale_factor);
+
+        match shape {
+            Shape::Rectangle { rect, .. }
+            | Shape::Spotlight { rect, .. }
+            | Shape::Redaction { rect, .. } => {
+                let scaled_rect = Rect::from_min_max(
+                    Pos2::new(rect.min.x / scale_factor, rect.min.y / scale_factor),
+                    Pos2::new(rect.max.x / scale_factor, rect.max.y / scale_factor),
+                );
+                // Add a small margin for easier selection
+                scaled_rect.expand(3.0).contains(scaled_point)
+            }
+            Shape::Circle { center, radius, .. } => {
+                let scaled_point = Pos2::new(point.x / scale_factor, point.y / scale_factor);
+                let scaled_center = Pos2::new(center.x / scale_factor, center.y / scale_factor);
+                scaled_point.distance(scaled_center) <= radius / scale_factor
+            }
+            Shape::Arrow {
+                start, end, style, ..
+            }
+            | Shape::Line {
+                start, end, style, ..
+            } => {
+                let scaled_point = Pos2::new(point.x / scale_factor, point.y / scale_factor);
+                let scaled_start = Pos2::new(start.x / scale_factor, start.y / scale_factor);
+                let scaled_end = Pos2::new(end.x / scale_factor, end.y / scale_factor);
+                let threshold = line_threshold(style); // Distance threshold in pixels
+                point_to_line_segment_distance(scaled_point, scaled_start, scaled_end) < threshold
+            }
+            Shape::FreeHand { points, style, .. } | Shape::Highlighter { points, style, .. } => {
+                let scaled_point = Pos2::new(point.x / scale_factor, point.y / scale_factor);
+                points.windows(2).any(|window| {
+                    if let [p1, p2] = window {
+                        let scaled_p1 = Pos2::new(p1.x / scale_factor, p1.y / scale_factor);
+                        let scaled_p2 = Pos2::new(p2.x / scale_factor, p2.y / scale_factor);
+                        point_to_line_segment_distance(scaled_point, scaled_p1, scaled_p2)
+                            < line_threshold(style)
+                    } else {
+                        false
+                    }
+                })
+            }
+            Shape::Text {
+                position,
+                content,
+                style,
+                ..
+            } => {
+                let scaled_point = Pos2::new(point.x / scale_factor, point.y / scale_factor);
+                let scaled_pos = Pos2::new(position.x / scale_factor, position.y / scale_factor);
+                let text_rect = Rect::from_min_size(scaled_pos, text_box(content, style));
+                text_rect.contains(scaled_point)
+            }
+            Shape::Ellipse { rect, .. } => {
+                let scaled_rect = Rect::from_min_max(
+                    Pos2::new(rect.min.x / scale_factor, rect.min.y / scale_factor),
+                    Pos2::new(rect.max.x / scale_factor, rect.max.y / scale_factor),
+                )
+                .expand(3.0);
+                // Inside when the point is at most one radius away on the stretched axes
+                let offset = (scaled_point - scaled_rect.center()) / (scaled_rect.size() / 2.0);
+                offset.length_sq() <= 1.0
+            }
+            Shape::Marker { center, style, .. } => {
+                let scaled_center = Pos2::new(center.x / scale_factor, center.y / scale_factor);
+                scaled_point.distance(scaled_center) <= marker_radius(style)
+            }
+            Shape::Callout {
+                position,
+                tail,
+                content,
+                style,
+                ..
+            } => {
+                let scaled_pos = Pos2::new(position.x / scale_factor, position.y / scale_factor);
+                let scaled_tail = Pos2::new(tail.x / scale_factor, tail.y / scale_factor);
+                let bubble = callout_bubble(scaled_pos, content, style, 1.0);
+                bubble.contains(scaled_point)
+                    || point_to_line_segment_distance(scaled_point, bubble.center(), scaled_tail)
+                        < line_threshold(style)
+            }
+        }
+    }
+}
+
+impl Shape {
+    // Rectangle around the shape, in physical pixels like the shape
+    pub fn bounds(&self, pixels_per_point: f32) -> Rect {
+        match self {
+            Shape::Rectangle { rect, .. }
+            | Shape::Ellipse { rect, .. }
+            | Shape::Spotlight { rect, .. }
+            | Shape::Redaction { rect, .. } => *rect,
+            Shape::Circle { center, radius, .. } => {
+                Rect::from_center_size(*center, Vec2::splat(radius * 2.0))
+            }
+            Shape::Arrow { start, end, .. } | Shape::Line { start, end, .. } => {
+                Rect::from_two_pos(*start, *end)
+            }
+            Shape::FreeHand { points, .. } | Shape::Highlighter { points, .. } => {
+                Rect::from_points(points)
+            }
+            // The same box as the hit-testing
+            Shape::Text {
+                position,
+                content,
+                style,
+                ..
+            } => Rect::from_min_size(*position, text_box(content, style) * pixels_per_point),
+            Shape::Marker { center, style, .. } => Rect::from_center_size(
+                *center,
+                Vec2::splat(marker_radius(style) * 2.0 * pixels_per_point),
+            ),
+            Shape::Callout {
+                position,
+                tail,
+                content,
+                style,
+                ..
+            } => {
+                let mut bounds = callout_bubble(*position, content, style, pixels_per_point);
+                bounds.extend_with(*tail);
+                bounds
+            }
+        }
+    }
+
+    // Stretch the shape from the bounds `from` to `to`. Circles stay round, text and markers
+    // keep their size.
+    pub fn resize(&mut self, from: Rect, to: Rect) {
+        let scale = egui::vec2(
+            if from.width() > 0.0 {
+                to.width() / from.width()
+            } else {
+                1.0
+            },
+            if from.height() > 0.0 {
+                to.height() / from.height()
+            } else {
+                1.0
+            },
+        );
+        let map = |point: Pos2| to.min + (point - from.min) * scale;
+        match self {
+            Shape::Rectangle { rect, .. }
+            | Shape::Ellipse { rect, .. }
+            | Shape::Spotlight { rect, .. }
+            | Shape::Redaction { rect, .. } => {
+                *rect = Rect::from_two_pos(map(rect.min), map(rect.max))
+            }
+            Shape::Circle { center, radius, .. } => {
+                *center = map(*center);
+                *radius *= (scale.x + scale.y) / 2.0;
+            }
+            Shape::Arrow { start, end, .. } | Shape::Line { start, end, .. } => {
+                *start = map(*start);
+                *end = map(*end);
+            }
+            Shape::FreeHand { points, .. } | Shape::Highlighter { points, .. } => {
+                for point in points {
+                    *point = map(*point);
+                }
+            }
+            Shape::Text { position, .. } => *position = map(*position),
+            Shape::Marker { center, .. } => *center = map(*center),
+            Shape::Callout { position, tail, .. } => {
+                *position = map(*position);
+                *tail = map(*tail);
+            }
+        }
+    }
+
+    pub fn style(&self) -> &Style {
+        match self {
+            Shape::Rectangle { style, .. }
+            | Shape::Circle { style, .. }
+            | Shape::Arrow { style, .. }
+            | Shape::FreeHand { style, .. }
+            | Shape::Text { style, .. }
+            | Shape::Highlighter { style, .. }
+            | Shape::Line { style, .. }
+            | Shape::Ellipse { style, .. }
+            | Shape::Marker { style, .. }
+            | Shape::Callout { style, .. }
+            | Shape::Spotlight { style, .. }
+            | Shape::Redaction { style, .. } => style,
+        }
+    }
+
+    pub fn style_mut(&mut self) -> &mut Style {
+        match self {
+            Shape::Rectangle { style, .. }
+            | Shape::Circle { style, .. }
+            | Shape::Arrow { style, .. }
+            | Shape::FreeHand { style, .. }
+            | Shape::Text { style, .. }
+            | Shape::Highlighter { style, .. }
+            | Shape::Line { style, .. }
+            | Shape::Ellipse { style, .. }
+            | Shape::Marker { style, .. }
+            | Shape::Callout { style, .. }
+            | Shape::Spotlight { style, .. }
+            | Shape::Redaction { style, .. } => style,
+        }
+    }
+
+    pub fn color(&self) -> Color32 {
+        match self {
+            Shape::Rectangle { color, .. }
+            | Shape::Circle { color, .. }
+            | Shape::Arrow { color, .. }
+            | Shape::FreeHand { color, .. }
+            | Shape::Text { color, .. }
+            | Shape::Highlighter { color, .. }
+            | Shape::Line { color, .. }
+            | Shape::Ellipse { color, .. }
+            | Shape::Marker { color, .. }
+            | Shape::Callout { color, .. }
+            | Shape::Spotlight { color, .. }
+            | Shape::Redaction { color, .. } => *color,
+        }
+    }
+
+    // The shape as shown `age` after it was drawn, None once fading ink is gone
+    pub fn faded(&self, age: Duration) -> Option<Shape> {
+        let Some(lifetime) = self.style().fade_after else {
+            return Some(self.clone());
+        };
+        let left = lifetime - age.as_secs_f32();
+        if left <= 0.0 {
+            return None;
+        }
+        let mut shape = self.clone();
+        let opacity = (left / FADE_DURATION.min(lifetime)).min(1.0);
+        shape.set_color(self.color().gamma_multiply(opacity));
+        Some(shape)
+    }
+
+    pub fn set_color(&mut self, new_color: Color32) {
+        match self {
+            Shape::Rectangle { color, .. }
+            | Shape::Circle { color, .. }
+            | Shape::Arrow { color, .. }
+            | Shape::FreeHand { color, .. }
+            | Shape::Text { color, .. }
+            | Shape::Highlighter { color, .. }
+            | Shape::Line { color, .. }
+            | Shape::Ellipse { color, .. }
+            | Shape::Marker { color, .. }
+            | Shape::Callout { color, .. }
+            | Shape::Spotlight { color, .. }
+            | Shape::Redaction { color, .. } => *color = new_color,
+        }
+    }
+
+    pub fn translate(&mut self, offset: Vec2) {
+        match self {
+            Shape::Rectangle { rect, .. }
+            | Shape::Ellipse { rect, .. }
+            | Shape::Spotlight { rect, .. }
+            | Shape::Redaction { rect, .. } => *rect = rect.translate(offset),
+            Shape::Circle { center, .. } | Shape::Marker { center, .. } => *center += offset,
+            Shape::Arrow { start, end, .. } | Shape::Line { start, end, .. } => {
+                *start += offset;
+                *end += offset;
+            }
+            Shape::FreeHand { points, .. } | Shape::Highlighter { points, .. } => {
+                for point in points {
+                    *point += offset;
+                }
+            }
+            Shape::Text { position, .. } => *position += offset,
+            Shape::Callout { position, tail, .. } => {
+                *position += offset;
+                *tail += offset;
+            }
+        }
+    }
+}
+
+// Draw a shape with `to_screen` mapping its coordinates to the painter's. Strokes and text
+// keep their size relative to the shapes, `pixels_per_point` is the one of the annotated screen.
+pub fn draw_shape(
+    painter: &egui::Painter,
+    shape: &Shape,
+    to_screen: &RectTransform,
+    pixels_per_point: f32,
+) {
+    let zoom = to_screen.scale().x * pixels_per_point;
+    let stroke = |style: &Style, color: Color32| Stroke::new(style.width * zoom, color);
+    match shape {
+        Shape::Rectangle { rect, color, style } => {
+            let rect = to_screen.transform_rect(*rect);
+            if style.filled {
+                painter.rect_filled(rect, 0.0, color.gamma_multiply(style.fill_opacity));
+            }
+            if style.dashed {
+                let mut points = corners(rect).to_vec();
+                points.push(rect.left_top());
+                draw_path(painter, points, stroke(style, *color), true);
+            } else {
+                painter.rect_stroke(rect, 0.0, stroke(style, *color), StrokeKind::Outside);
+            }
+        }
+        Shape::Circle {
+            center,
+            radius,
+            color,
+            style,
+        } => {
+            let center = to_screen.transform_pos(*center);
+            let radius = radius * to_screen.scale().x;
+            if style.filled {
+                painter.circle_filled(center, radius, color.gamma_multiply(style.fill_opacity));
+            }
+            if style.dashed {
+                let points = ellipse_points(center, Vec2::splat(radius));
+                draw_path(painter, points, stroke(style, *color), true);
+            } else {
+                painter.circle_stroke(center, radius, stroke(style, *color));
+            }
+        }
+        Shape::Arrow {
+            start,
+            end,
+            color,
+            style,
+        } => {
+            let start = to_screen.transform_pos(*start);
+            let end = to_screen.transform_pos(*end);
+            let stroke = stroke(style, *color);
+            draw_path(painter, vec![start, end], stroke, style.dashed);
+            // The head is always solid
+            if let Some(sides) = arrow_head(start, end, style.arrow_size * zoom) {
+                for side in sides {
+                    painter.line_segment([end, side], stroke);
+                }
+            }
+        }
+        Shape::FreeHand {
+            points,
+            color,
+            style,
+        } => {
+            let points: Vec<Pos2> = points
+                .iter()
+                .map(|point| to_screen.transform_pos(*point))
+                .collect();
+            draw_path(painter, points, stroke(style, *color), style.dashed);
+        }
+        Shape::Text {
+            position,
+            content,
+            color,
+            style,
+        } => {
+            let position = to_screen.transform_pos(*position);
+            let font = egui::FontId::proportional(style.font_size * zoom);
+            // No bold font is bundled
+            if style.bold {
+                painter.text(
+                    position + style.bold_offset() * zoom,
+                    egui::Align2::LEFT_TOP,
+                    content,
+                    font.clone(),
+                    *color,
+                );
+            }
+            painter.text(position, egui::Align2::LEFT_TOP, content, font, *color);
+        }
+        Shape::Highlighter {
+            points,
+            color,
+            style,
+        } => {
+            let points: Vec<Pos2> = points
+                .iter()
+                .map(|point| to_screen.transform_pos(*point))
+                .collect();
+            let stroke = stroke(style, color.gamma_multiply(HIGHLIGHTER_OPACITY));
+            draw_path(painter, points, stroke, style.dashed);
+        }
+        Shape::Line {
+            start,
+            end,
+            color,
+            style,
+        } => {
+            let points = vec![
+                to_screen.transform_pos(*start),
+                to_screen.transform_pos(*end),
+            ];
+            draw_path(painter, points, stroke(style, *color), style.dashed);
+        }
+        Shape::Ellipse { rect, color, style } => {
+            let rect = to_screen.transform_rect(*rect);
+            let points = ellipse_points(rect.center(), rect.size() / 2.0);
+            if style.filled {
+                painter.add(egui::Shape::convex_polygon(
+                    points.clone(),
+                    color.gamma_multiply(style.fill_opacity),
+                    Stroke::NONE,
+                ));
+            }
+            draw_path(painter, points, stroke(style, *color), style.dashed);
+        }
+        Shape::Marker {
+            center,
+            number,
+            color,
+            style,
+        } => {
+            let center = to_screen.transform_pos(*center);
+            painter.circle_filled(center, marker_radius(style) * zoom, *color);
+            painter.text(
+                center,
+                egui::Align2::CENTER_CENTER,
+                number.to_string(),
+                egui::FontId::proportional(style.font_size * zoom),
+                Color32::WHITE,
+            );
+        }
+        Shape::Callout {
+            position,
+            tail,
+            content,
+            color,
+            style,
+        } => {
+            let bubble = to_screen.transform_rect(callout_bubble(
+                *position,
+                content,
+                style,
+                pixels_per_point,
+            ));
+            let tail = to_screen.transform_pos(*tail);
+            let stroke = stroke(style, *color);
+            let fill = Color32::from_white_alpha(230);
+
+            // The tail starts under the bubble, which hides its base
+            if let Some(base) = callout_tail_base(bubble, tail) {
+                painter.add(egui::Shape::convex_polygon(
+                    vec![base[0], tail, base[1]],
+                    fill,
+                    Stroke::NONE,
+                ));
+                painter.line_segment([base[0], tail], stroke);
+                painter.line_segment([base[1], tail], stroke);
+            }
+            let rounding = style.font_size * zoom / 2.0;
+            painter.rect_filled(bubble, rounding, fill);
+            painter.rect_stroke(bubble, rounding, stroke, StrokeKind::Outside);
+            painter.text(
+                bubble.center(),
+                egui::Align2::CENTER_CENTER,
+                content,
+                egui::FontId::proportional(style.font_size * zoom),
+                *color,
+            );
+        }
+        Shape::Spotlight { rect, color, style } => {
+            let rect = to_screen.transform_rect(*rect);
+            let clip = painter.clip_rect();
+            let dim = Color32::from_black_alpha(SPOTLIGHT_DIM);
+            let around = [
+                Rect::from_min_max(clip.min, egui::pos2(clip.max.x, rect.min.y)),
+                Rect::from_min_max(egui::pos2(clip.min.x, rect.max.y), clip.max),
+                Rect::from_min_max(
+                    egui::pos2(clip.min.x, rect.min.y),
+                    egui::pos2(rect.min.x, rect.max.y),
+                ),
+                Rect::from_min_max(
+                    egui::pos2(rect.max.x, rect.min.y),
+                    egui::pos2(clip.max.x, rect.max.y),
+                ),
+            ];
+            for part in around {
+                let part = part.intersect(clip);
+                if part.is_positive() {
+                    painter.rect_filled(part, 0.0, dim);
+                }
+            }
+            painter.rect_stroke(rect, 0.0, stroke(style, *color), StrokeKind::Outside);
+        }
+        // The screen under it can't be hidden here, only marked
+        Shape::Redaction { rect, color, style } => {
+            let rect = to_screen.transform_rect(*rect);
+            painter.rect_filled(rect, 0.0, color.gamma_multiply(REDACTION_OPACITY));
+            let mut points = corners(rect).to_vec();
+            points.push(rect.left_top());
+            draw_path(painter, points, stroke(style, *color), true);
+            painter.text(
+                rect.left_top() + Vec2::splat(style.width * zoom * 2.0),
+                egui::Align2::LEFT_TOP,
+                format!("🔒 {}", style.redaction),
+                egui::FontId::proportional(style.font_size * zoom),
+                *color,
+            );
+        }
+    }
+}
+
+// Ends of the sides of an arrowhead `length` long, 30 degrees from the shaft
+fn arrow_head(start: Pos2, end: Pos2, length: f32) -> Option<[Pos2; 2]> {
+    if start == end {
+        return None;
+    }
+    let back = (start - end).normalized() * length;
+    let rotation = Rot2::from_angle(std::f32::consts::PI / 6.0);
+    Some([end + rotation * back, end + rotation.inverse() * back])
+}
+
+// Corners of the tail of a callout next to the middle of the bubble, none when the tail
+// points inside the bubble
+fn callout_tail_base(bubble: Rect, tail: Pos2) -> Option<[Pos2; 2]> {
+    if bubble.contains(tail) {
+        return None;
+    }
+    let across =
+        (tail - bubble.center()).normalized().rot90() * (bubble.height().min(bubble.width()) / 4.0);
+    Some([bubble.center() + across, bubble.center() - across])
+}
+
+// Closed outline of an ellipse, a circle when both radii are the same
+fn ellipse_points(center: Pos2, radius: Vec2) -> Vec<Pos2> {
+    (0..=CIRCLE_SEGMENTS)
+        .map(|i| {
+            let angle = i as f32 * std::f32::consts::TAU / CIRCLE_SEGMENTS as f32;
+            center + Vec2::angled(angle) * radius
+        })
+        .collect()
+}
+
+// Radius of a numbered marker in points, the number fits inside
+fn marker_radius(style: &Style) -> f32 {
+    style.font_size * 0.9
+}
+
+// Bubble of a callout around its text, in the units of `position`
+fn callout_bubble(position: Pos2, content: &str, style: &Style, pixels_per_point: f32) -> Rect {
+    let padding = Vec2::splat(style.font_size / 2.0);
+    Rect::from_min_size(
+        position,
+        (text_box(content, style) + padding * 2.0) * pixels_per_point,
+    )
+}
+
+// Faint lines the shapes snap to, `screen` in points
+fn draw_grid(painter: &egui::Painter, screen: Rect, spacing: f32) {
+    let stroke = Stroke::new(1.0, Color32::from_white_alpha(40));
+    let mut x = spacing;
+    while x < screen.max.x {
+        painter.vline(x, screen.y_range(), stroke);
+        x += spacing;
+    }
+    let mut y = spacing;
+    while y < screen.max.y {
+        painter.hline(screen.x_range(), y, stroke);
+        y += spacing;
+    }
+}
+
+// Polyline of the outline, the dashes scale with the stroke
+fn draw_path(painter: &egui::Painter, points: Vec<Pos2>, stroke: Stroke, dashed: bool) {
+    if dashed {
+        painter.extend(egui::Shape::dashed_line(
+            &points,
+            stroke,
+            stroke.width * 3.0,
+            stroke.width * 2.0,
+        ));
+    } else {
+        painter.add(egui::Shape::line(points, stroke));
+    }
+}
+
+// Distance from the middle of a line that still hits it, in points
+fn line_threshold(style: &Style) -> f32 {
+    (style.width / 2.0).max(5.0)
+}
+
+// Approximate size of a text in points, without the painter to lay it out
+fn text_box(content: &str, style: &Style) -> Vec2 {
+    let chars = content.chars().count().max(1) as f32;
+    egui::vec2(chars * 0.6, 1.25) * style.font_size
+}
+
+fn corners(rect: Rect) -> [Pos2; 4] {
+    [
+        rect.left_top(),
+        rect.right_top(),
+        rect.right_bottom(),
+        rect.left_bottom(),
+    ]
+}
+
+fn point_to_line_segment_distance(point: Pos2, start: Pos2, end: Pos2) -> f32 {
+    let line = end - start;
+    let len_sq = line.length_sq();
+    if len_sq == 0.0 {
+        return point.distance(start);
+    }
+    let t = ((point - start).dot(line) / len_sq).clamp(0.0, 1.0);
+    let projection = start + line * t;
+    point.distance(projection)
+}
@@ -0,0 +1,123 @@
+use super::Tool;
+use crate::config::MaskStyle;
+use eframe::egui::{self, ComboBox, Slider, Ui};
+use serde::{Deserialize, Serialize};
+
+// How a shape is drawn, sizes in points of the annotated screen
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Style {
+    pub width: f32, // Of the outline
+    pub dashed: bool,
+    pub filled: bool,      // Rectangles and circles
+    pub fill_opacity: f32, // Of the fill, in the color of the outline
+    pub arrow_size: f32,   // Length of the arrowhead
+    pub font_size: f32,
+    pub bold: bool,
+    pub fade_after: Option<f32>, // Seconds before fading ink disappears
+    pub redaction: MaskStyle,    // How the caster hides what is under a redaction
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Self {
+            width: 3.0,
+            dashed: false,
+            filled: false,
+            fill_opacity: 0.3,
+            arrow_size: 15.0,
+            font_size: 16.0,
+            bold: false,
+            fade_after: None,
+            redaction: MaskStyle::Blur,
+        }
+    }
+}
+
+impl Style {
+    // Default of the shapes drawn with `tool`
+    pub(super) fn for_tool(tool: Tool) -> Self {
+        match tool {
+            Tool::Highlighter => Self {
+                width: 16.0,
+                ..Self::default()
+            },
+            Tool::Spotlight | Tool::Redact => Self {
+                width: 1.0,
+                ..Self::default()
+            },
+            _ => Self::default(),
+        }
+    }
+
+    // Toolbar menu with the settings that apply to the shapes of `tool`, all of them for
+    // the selection. Returns true when a setting changed.
+    pub(super) fn show_menu(&mut self, ui: &mut Ui, tool: Tool) -> bool {
+        let outline = !matches!(tool, Tool::Text | Tool::Marker | Tool::Redact);
+        let fill = matches!(
+            tool,
+            Tool::Rectangle | Tool::Circle | Tool::Ellipse | Tool::Select
+        );
+        let arrow = matches!(tool, Tool::Arrow | Tool::Select);
+        let font = matches!(
+            tool,
+            Tool::Text | Tool::Marker | Tool::Callout | Tool::Select
+        );
+        let redaction = matches!(tool, Tool::Redact | Tool::Select);
+
+        let mut changed = false;
+        ui.menu_button("✏ Style", |ui| {
+            if outline {
+                changed |= ui
+                    .add(Slider::new(&mut self.width, 1.0..=20.0).text("Width"))
+                    .changed();
+                changed |= ui.checkbox(&mut self.dashed, "Dashed").changed();
+            }
+            if fill {
+                ui.separator();
+                changed |= ui.checkbox(&mut self.filled, "Filled").changed();
+                changed |= ui
+                    .add_enabled(
+                        self.filled,
+                        Slider::new(&mut self.fill_opacity, 0.0..=1.0).text("Fill opacity"),
+                    )
+                    .changed();
+            }
+            if arrow {
+                ui.separator();
+                changed |= ui
+                    .add(Slider::new(&mut self.arrow_size, 5.0..=60.0).text("Arrowhead"))
+                    .changed();
+            }
+            if font {
+                ui.separator();
+                changed |= ui
+                    .add(Slider::new(&mut self.font_size, 8.0..=72.0).text("Font size"))
+                    .changed();
+                changed |= ui.checkbox(&mut self.bold, "Bold").changed();
+            }
+            if redaction {
+                ui.separator();
+                ComboBox::from_label("Redaction")
+                    .selected_text(self.redaction.to_string())
+                    .show_ui(ui, |ui| {
+                        for style in [MaskStyle::Blur, MaskStyle::Pixelate, MaskStyle::Black] {
+                            changed |= ui
+                                .selectable_value(&mut self.redaction, style, style.to_string())
+                                .changed();
+                        }
+                    });
+            }
+            if ui.button("Reset").clicked() {
+                *self = Style::for_tool(tool);
+                changed = true;
+            }
+        });
+        changed
+    }
+
+    // Text is made bold by drawing it twice, this far apart
+    pub fn bold_offset(&self) -> egui::Vec2 {
+        egui::vec2(self.font_size / 24.0, 0.0)
+    }
+}
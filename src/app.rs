@@ -1,70 +1,91 @@
-use crate::common::CaptureArea;
-use crate::config::Config;
+use crate::annotation::{draw_shape, export, Shape, Tool};
+use crate::chat::{chat_button_label, ChatHistory, ChatMessage, ChatPanel};
+use crate::common::{unix_timestamp_micros, CaptureArea};
+use crate::config::{
+    display_id, CaptureConfig, CaptureRegion, CompositeLayout, Config, CursorMode, MaskStyle,
+    NamedStream, PrivacyMask, PrivacyMode, Redaction,
+};
 use crate::hotkey::{HotkeyAction, HotkeyManager, KeyCombination};
-use crate::receiver::{start_receiving, Receiver};
-use crate::screen_capture::{CapturedFrame, ScreenCapture};
-use crate::sender::{start_streaming, Sender, PORT};
-use crate::video_recorder::VideoRecorder;
+use crate::ipc::{OverlayCommand, OverlayEvent, OverlayProcess};
+use crate::laser_pointer::{draw_pointer, OverlayPointer, PointerOverlay};
+use crate::protocol::{StreamInfo, MAIN_STREAM};
+use crate::screen_capture::{
+    list_windows, CapturedFrame, ScreenCapture, StreamCapture, TrackingState, WindowInfo,
+    VIDEO_CODEC,
+};
+use crate::sender::{start_streaming, LaserPointers, Sender, StreamFlags, PORT};
+use crate::session::ReceiverSession;
+use crate::stats::{format_bitrate, format_millis, format_uptime, StreamStats, HISTORY_LEN};
 use std::collections::VecDeque;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 // use std::os::windows::thread; // Remove this line
-use std::thread;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
-use tokio::sync::oneshot::{channel, error::TryRecvError};
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot::channel;
 use tokio::sync::Notify;
 
 use eframe::egui;
+use egui::emath::RectTransform;
 use egui::{
     CentralPanel, Color32, ColorImage, ComboBox, Context, FontId, Rect, RichText, TextStyle,
     TextureHandle, TopBottomPanel, Ui, Window,
 };
 
-use std::{env};
-use std::process::Command;
-
 use display_info::DisplayInfo;
 use log::{debug, error, info};
 
 pub struct RustreamApp {
     pub config: Arc<Mutex<Config>>,
-    pub received_frames: Arc<Mutex<VecDeque<CapturedFrame>>>, // Queue of frames recived by receiver
     pub stop_notify: Arc<Notify>, // Notify to stop the frame receiving task in receiver or the listen for receiver task in sender
     frame_grabber: ScreenCapture,
-    video_recorder: Option<VideoRecorder>,
     page: PageView,                                       // Enum to track modes
     display_texture: Option<TextureHandle>,               // Texture for the screen capture
     captured_frames: Arc<Mutex<VecDeque<CapturedFrame>>>, // Queue of captured frames
     address_text: String,                                 // Text input for the receiver mode
-    caster_addr: Option<SocketAddr>, // Socket Address defined by user in receiver mode
     streaming_active: bool,
     is_selecting: bool,
     capture_area: Option<CaptureArea>,
     show_config: bool,        // Show config window
     show_hotkey_config: bool, // Show config window
     sender: Option<Arc<tokio::sync::Mutex<Sender>>>,
-    receiver: Option<Arc<tokio::sync::Mutex<Receiver>>>,
     sender_rx: Option<tokio::sync::oneshot::Receiver<Arc<tokio::sync::Mutex<Sender>>>>,
-    receiver_rx: Option<tokio::sync::oneshot::Receiver<Receiver>>,
     socket_created: bool,
-    last_frame_time: Option<std::time::Instant>,
-    frame_times: std::collections::VecDeque<std::time::Duration>,
-    current_fps: f32,
-    is_receiving: bool,
     started_capture: bool,
     hotkey_manager: HotkeyManager,
     editing_hotkey: Option<HotkeyAction>,
     triggered_actions: Vec<HotkeyAction>,
     previous_monitor: usize,
     is_address_valid: bool,
-    host_unreachable: Arc<AtomicBool>,
     is_preview_screen: bool,
     end_of_stream: bool, // Flag to signal the end of the stream in the sender
-    stream_ended: Arc<AtomicBool>, // Flag to signal the end of the stream in the receiver
     is_blank_screen: Arc<AtomicBool>, // Flag to indicate if the screen is blanked
-    is_paused: Arc<AtomicBool>, // Flag to indicate if the stream is paused by receiver
+    is_transmission_paused: Arc<AtomicBool>, // Flag to indicate if the caster paused the transmission
     is_annotation_open: Arc<AtomicBool>, // Flag to indicate if the annotation overlay is open
+    annotation_overlay: Option<OverlayProcess>, // Receives commands while it is open
+    windows: Option<Vec<WindowInfo>>, // Windows that can be followed, listed when the picker is first shown
+    stream_captures: Vec<(u32, NamedStream, StreamCapture)>, // Additional streams, captured while streaming
+    sessions: Vec<ReceiverSession>, // Casters watched in receiver mode, one tile each
+    focused_session: Option<usize>, // Tile shown full size
+    grid_columns: usize,            // Columns of the receiver grid, 0 picks them automatically
+    laser_pointers: LaserPointers,  // Pointers of the receivers while casting
+    pointer_overlay: Option<PointerOverlay>, // Shows them on this screen, started on the first one
+    chat_panel: ChatPanel,
+    caster_chat: Arc<Mutex<ChatHistory>>, // Chat with the receivers while casting, kept across streams
+    annotations: Arc<Mutex<Vec<Shape>>>, // Drawn on the annotation overlay, physical pixels of the monitor
+    annotation_pixels_per_point: f32,    // Of the annotated monitor
+    annotated_frame: Option<CapturedFrame>, // Last frame shown while annotating, for the PNG export
+    config_changed_at: Option<Instant>,  // Settings not saved yet, written once they settle
+}
+
+// Settings are saved once they stop changing, not on every keystroke or slider step
+const CONFIG_SAVE_DELAY: Duration = Duration::from_secs(1);
+
+// Requested from a tile of the receiver grid
+enum TileAction {
+    Focus,
+    Chat, // Focus the tile and open its chat
+    Close,
 }
 
 #[derive(Default, Debug, Copy, Clone, PartialEq)]
@@ -77,25 +98,18 @@ pub enum PageView {
 
 impl RustreamApp {
     pub fn new(_cc: &eframe::CreationContext<'_>) -> Self {
-        let config: Arc<Mutex<Config>> = Arc::new(Mutex::new(Config::default()));
+        let config: Arc<Mutex<Config>> = Arc::new(Mutex::new(Config::load()));
         let frame_grabber: ScreenCapture = ScreenCapture::new(config.clone());
+        let laser_pointers = LaserPointers::new(config.lock().unwrap().laser.allow_pointers);
 
         RustreamApp {
             config,
             frame_grabber,
-            video_recorder: None,
             sender: None,
             sender_rx: None,
             streaming_active: false,
             socket_created: false,
-            receiver: None,
-            receiver_rx: None,
-            last_frame_time: None,
-            frame_times: std::collections::VecDeque::with_capacity(60),
-            current_fps: 0.0,
-            received_frames: Arc::new(Mutex::new(VecDeque::new())),
             stop_notify: Arc::new(Notify::new()),
-            is_receiving: false,
             captured_frames: Arc::new(Mutex::new(VecDeque::new())),
             started_capture: false,
             hotkey_manager: HotkeyManager::new(),
@@ -109,15 +123,26 @@ impl RustreamApp {
             editing_hotkey: None,
             triggered_actions: Vec::new(),
             previous_monitor: 0,
-            caster_addr: None,
             is_address_valid: true,
-            host_unreachable: Arc::new(AtomicBool::new(false)),
             is_preview_screen: true,
             end_of_stream: false,
-            stream_ended: Arc::new(AtomicBool::new(false)),
             is_blank_screen: Arc::new(AtomicBool::new(false)),
-            is_paused: Arc::new(AtomicBool::new(false)),
+            is_transmission_paused: Arc::new(AtomicBool::new(false)),
             is_annotation_open: Arc::new(AtomicBool::new(false)),
+            annotation_overlay: None,
+            windows: None,
+            stream_captures: Vec::new(),
+            sessions: Vec::new(),
+            focused_session: None,
+            grid_columns: 0,
+            laser_pointers,
+            pointer_overlay: None,
+            chat_panel: ChatPanel::default(),
+            caster_chat: Arc::new(Mutex::new(ChatHistory::default())),
+            annotations: Arc::new(Mutex::new(Vec::new())),
+            annotation_pixels_per_point: 1.0,
+            annotated_frame: None,
+            config_changed_at: None,
         }
     }
 
@@ -131,6 +156,7 @@ impl RustreamApp {
         if self.page == PageView::Caster {
             //if we are exiting from caster mode
             self.frame_grabber.stop_capture();
+            self.pointer_overlay = None;
             self.stop_notify.notify_waiters();
             self.end_stream();
             self.sender = None;
@@ -143,13 +169,16 @@ impl RustreamApp {
             drop(frames);
             self.display_texture = None;
             self.capture_area = None;
-            self.video_recorder = None; //dropping video recorder, the video is saved
+            self.is_transmission_paused.store(false, Ordering::SeqCst);
+            self.caster_chat.lock().unwrap().clear();
         } else if self.page == PageView::Receiver {
-            //if we are exiting from receiver mode
-            self.reset_receiving();
+            //if we are exiting from receiver mode, the recordings are saved by their recorders
+            self.sessions.clear();
+            self.focused_session = None;
             self.address_text.clear();
         }
 
+        self.chat_panel.open = false;
         self.page = PageView::default();
     }
 
@@ -259,23 +288,45 @@ impl RustreamApp {
 
                 // Monitor selection
                 let selected_monitor = &mut config.capture.selected_monitor;
+                let whole_desktop = &mut config.capture.whole_desktop;
                 let current_monitor = *selected_monitor;
 
                 ComboBox::from_label("Monitor")
-                    .selected_text(format!("Monitor {}", selected_monitor))
+                    .selected_text(if *whole_desktop {
+                        "Whole Desktop".to_string()
+                    } else {
+                        format!("Monitor {}", selected_monitor)
+                    })
                     .show_ui(ui, |ui| {
                         self.frame_grabber
                             .get_monitors()
                             .iter()
                             .enumerate()
                             .for_each(|(i, m)| {
-                                if ui.selectable_value(selected_monitor, i, m).clicked()
-                                    && current_monitor != self.previous_monitor
+                                if ui
+                                    .selectable_label(!*whole_desktop && *selected_monitor == i, m)
+                                    .clicked()
                                 {
-                                    self.capture_area = None;
-                                    self.previous_monitor = current_monitor;
+                                    *selected_monitor = i;
+                                    if *whole_desktop {
+                                        // The area was relative to the whole desktop
+                                        *whole_desktop = false;
+                                        self.capture_area = None;
+                                    } else if current_monitor != self.previous_monitor {
+                                        self.capture_area = None;
+                                        self.previous_monitor = current_monitor;
+                                    }
                                 }
                             });
+                        if ui
+                            .selectable_label(*whole_desktop, "Whole Desktop")
+                            .on_hover_text("Every monitor stitched together as they are arranged")
+                            .clicked()
+                            && !*whole_desktop
+                        {
+                            *whole_desktop = true;
+                            self.capture_area = None;
+                        }
                     });
 
                 // Capture Area Section
@@ -313,21 +364,304 @@ impl RustreamApp {
 
                 // Handle capture area selection process
                 if self.is_selecting {
-                    self.handle_capture_area_selection(selected_monitor);
+                    let monitor = (!config.capture.whole_desktop)
+                        .then_some(config.capture.selected_monitor);
+                    self.handle_capture_area_selection(monitor);
                     self.is_selecting = false;
                 }
 
+                // Mouse pointer, only captured on X11
+                ui.add_space(10.0);
+                ComboBox::from_label("Mouse pointer")
+                    .selected_text(config.capture.cursor.to_string())
+                    .show_ui(ui, |ui| {
+                        for mode in [
+                            CursorMode::Hidden,
+                            CursorMode::Composited,
+                            CursorMode::Separate,
+                        ] {
+                            ui.selectable_value(&mut config.capture.cursor, mode, mode.to_string());
+                        }
+                    })
+                    .response
+                    .on_hover_text("Separate pointers are drawn by receivers, who can hide them");
+
+                self.render_window_settings(ui, &mut config);
+                self.render_region_settings(ui, &mut config);
+                self.render_stream_settings(ui, &mut config);
+                self.render_mask_settings(ui, &mut config);
+                self.render_slate_settings(ui, &mut config);
+
                 // Apply changes
-                if self.config.lock().unwrap().clone() != config {
+                let current_config = self.config.lock().unwrap().clone();
+                if current_config != config {
                     debug!("Config changed: {:?}", config);
+                    // The additional streams have their own captures
+                    let main_capture = |capture: &CaptureConfig| CaptureConfig {
+                        extra_streams: Vec::new(),
+                        ..capture.clone()
+                    };
+                    let has_capture_changed =
+                        main_capture(&current_config.capture) != main_capture(&config.capture);
                     self.config.lock().unwrap().update(config);
-                    self.frame_grabber.reset_capture();
+                    self.config_changed_at = Some(Instant::now());
+                    if has_capture_changed {
+                        self.frame_grabber.reset_capture();
+                    }
                 }
             });
 
         self.show_config = show_config;
     }
 
+    // Follow a single application window wherever it moves
+    fn render_window_settings(&mut self, ui: &mut Ui, config: &mut Config) {
+        ui.add_space(10.0);
+        ui.separator();
+        ui.label(RichText::new("Application Window").size(16.0));
+
+        let windows = self.windows.get_or_insert_with(|| {
+            list_windows().unwrap_or_else(|e| {
+                error!("Failed to list windows: {}", e);
+                Vec::new()
+            })
+        });
+
+        let mut refresh = false;
+        ui.horizontal(|ui| {
+            let selected_text = config
+                .capture
+                .window
+                .as_ref()
+                .map_or("Whole screen".to_string(), |window| window.title.clone());
+            ComboBox::from_label("Window")
+                .selected_text(selected_text)
+                .width(250.0)
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut config.capture.window, None, "Whole screen");
+                    for window in windows.iter() {
+                        ui.selectable_value(
+                            &mut config.capture.window,
+                            Some(window.clone()),
+                            &window.title,
+                        );
+                    }
+                });
+            refresh = ui.button("🔄").on_hover_text("Refresh the window list").clicked();
+        });
+        if refresh {
+            self.windows = None;
+        }
+
+        if config.capture.window.is_some() {
+            ui.label("Regions and capture area are ignored while following a window");
+        }
+    }
+
+    // Several regions, possibly on different monitors, composited into one stream
+    fn render_region_settings(&mut self, ui: &mut Ui, config: &mut Config) {
+        ui.add_space(10.0);
+        ui.separator();
+        ui.label(RichText::new("Capture Regions").size(16.0));
+
+        if config.capture.regions.is_empty() {
+            ui.label("Add regions to stream several parts of your screens together");
+        } else {
+            let mut removed = None;
+            egui::Grid::new("capture_regions_grid")
+                .num_columns(3)
+                .spacing([20.0, 4.0])
+                .show(ui, |ui| {
+                    for (i, region) in config.capture.regions.iter().enumerate() {
+                        ui.label(format!("{}. Monitor {}", i + 1, region.monitor));
+                        ui.label(format!(
+                            "{}x{} px at {}, {}",
+                            region.area.width, region.area.height, region.area.x, region.area.y
+                        ));
+                        if ui.button("✖").clicked() {
+                            removed = Some(i);
+                        }
+                        ui.end_row();
+                    }
+                });
+            if let Some(i) = removed {
+                config.capture.regions.remove(i);
+            }
+        }
+
+        ui.horizontal(|ui| {
+            if ui
+                .button(format!("Add Region on Monitor {}", config.capture.selected_monitor))
+                .clicked()
+            {
+                self.handle_region_selection(config);
+            }
+            if !config.capture.regions.is_empty() {
+                ui.add_space(10.0);
+                if ui.button("Remove All Regions").clicked() {
+                    config.capture.regions.clear();
+                }
+            }
+        });
+    }
+
+    // Monitors or parts of them published as separate streams, receivers choose what to watch
+    fn render_stream_settings(&mut self, ui: &mut Ui, config: &mut Config) {
+        ui.add_space(10.0);
+        ui.separator();
+        ui.label(RichText::new("Additional Streams").size(16.0));
+
+        if config.capture.extra_streams.is_empty() {
+            ui.label("Publish other monitors as separate streams receivers can switch to");
+        } else {
+            let mut removed = None;
+            egui::Grid::new("extra_streams_grid")
+                .num_columns(3)
+                .spacing([20.0, 4.0])
+                .show(ui, |ui| {
+                    for (i, stream) in config.capture.extra_streams.iter_mut().enumerate() {
+                        ui.add(egui::TextEdit::singleline(&mut stream.name).desired_width(150.0));
+                        ui.label(match stream.area {
+                            Some(area) => format!(
+                                "Monitor {}: {}x{} px at {}, {}",
+                                stream.monitor, area.width, area.height, area.x, area.y
+                            ),
+                            None => format!("Monitor {}", stream.monitor),
+                        });
+                        if ui.button("✖").clicked() {
+                            removed = Some(i);
+                        }
+                        ui.end_row();
+                    }
+                });
+            if let Some(i) = removed {
+                config.capture.extra_streams.remove(i);
+            }
+        }
+
+        let monitor = config.capture.selected_monitor;
+        ui.horizontal(|ui| {
+            if ui.button(format!("Add Monitor {}", monitor)).clicked() {
+                config.capture.extra_streams.push(NamedStream {
+                    name: format!("Monitor {}", monitor),
+                    monitor,
+                    area: None,
+                });
+            }
+            if ui
+                .button(format!("Add Region on Monitor {}", monitor))
+                .clicked()
+            {
+                if let Some(area) = select_area(monitor) {
+                    config.capture.extra_streams.push(NamedStream {
+                        name: format!("Region {}", config.capture.extra_streams.len() + 1),
+                        monitor,
+                        area: Some(area),
+                    });
+                }
+            }
+        });
+    }
+
+    // Regions of the selected monitor that are never streamed
+    fn render_mask_settings(&mut self, ui: &mut Ui, config: &mut Config) {
+        let monitor = config.capture.selected_monitor;
+        let display = self.frame_grabber.display_id(monitor);
+        let mask_count = config.capture.masks_for(&display).len();
+
+        ui.add_space(10.0);
+        ui.separator();
+        ui.label(RichText::new("Privacy Masks").size(16.0));
+        ui.label(match mask_count {
+            0 => "No hidden areas on this monitor".to_string(),
+            1 => "1 hidden area on this monitor".to_string(),
+            n => format!("{} hidden areas on this monitor", n),
+        });
+
+        ui.horizontal(|ui| {
+            if ui.button("Edit Masks").clicked() {
+                self.handle_mask_selection(monitor, config);
+            }
+            if mask_count > 0 {
+                ui.add_space(10.0);
+                if ui.button("Remove Masks").clicked() {
+                    config.capture.masks.retain(|mask| mask.display != display);
+                }
+            }
+        });
+
+        ComboBox::from_label("Mask style")
+            .selected_text(config.capture.mask_style.to_string())
+            .show_ui(ui, |ui| {
+                for style in [MaskStyle::Black, MaskStyle::Blur, MaskStyle::Pixelate] {
+                    ui.selectable_value(&mut config.capture.mask_style, style, style.to_string());
+                }
+            });
+    }
+
+    // What receivers see while the screen is blanked
+    fn render_slate_settings(&mut self, ui: &mut Ui, config: &mut Config) {
+        let slate = &mut config.slate;
+
+        ui.add_space(10.0);
+        ui.separator();
+        ui.label(RichText::new("Blank Screen").size(16.0));
+
+        ComboBox::from_label("Mode")
+            .selected_text(slate.mode.to_string())
+            .show_ui(ui, |ui| {
+                for mode in [PrivacyMode::Slate, PrivacyMode::Blur, PrivacyMode::Pixelate] {
+                    ui.selectable_value(&mut slate.mode, mode, mode.to_string());
+                }
+            });
+
+        match slate.mode {
+            PrivacyMode::Slate => {
+                ui.horizontal(|ui| {
+                    ui.label("Message:");
+                    ui.text_edit_singleline(&mut slate.message);
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("Image:");
+                    let image_name = slate
+                        .image_path
+                        .as_ref()
+                        .and_then(|path| path.file_name())
+                        .map(|name| name.to_string_lossy().into_owned())
+                        .unwrap_or_else(|| "None".to_string());
+                    ui.label(image_name);
+                    if ui.button("📂").clicked() {
+                        if let Some(path) = rfd::FileDialog::new()
+                            .set_title("Choose slate image...")
+                            .add_filter("PNG Image", &["png"])
+                            .pick_file()
+                        {
+                            slate.image_path = Some(path);
+                        }
+                    }
+                    if slate.image_path.is_some() && ui.button("✖").clicked() {
+                        slate.image_path = None;
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("Countdown:");
+                    ui.add(
+                        egui::Slider::new(&mut slate.countdown_minutes, 0..=60).suffix(" min"),
+                    )
+                    .on_hover_text("Time until you are back, 0 to hide the countdown");
+                });
+            }
+            PrivacyMode::Blur | PrivacyMode::Pixelate => {
+                ui.horizontal(|ui| {
+                    ui.label("Strength:");
+                    ui.add(egui::Slider::new(&mut slate.strength, 4..=64).suffix(" px"));
+                });
+            }
+        }
+    }
+
     fn render_hotkey_config(&mut self, ctx: &Context) {
         let mut show_hotkey_config = self.show_hotkey_config;
         if self.triggered_actions.contains(&HotkeyAction::ExitPopup) {
@@ -454,51 +788,46 @@ impl RustreamApp {
     }
 
     // Helper method to handle capture area selection
-    fn handle_capture_area_selection(&mut self, selected_monitor: &usize) {
-        let displays = DisplayInfo::all().unwrap_or_default();
-        let display = match displays.get(*selected_monitor) {
-            Some(d) => d,
-            None => {
-                error!("Monitor not found: {}", selected_monitor);
-                return;
-            }
-        };
+    // The area can cross monitor boundaries when no monitor is given
+    fn handle_capture_area_selection(&mut self, selected_monitor: Option<usize>) {
+        if let Some(event) = run_overlay("selection", selected_monitor, &[]) {
+            self.process_selection_response(event);
+        }
+    }
 
-        info!(
-            "Display: {} ({},{}) ({}x{}) | scale factor: {}",
-            display.name, display.x, display.y, display.width, display.height, display.scale_factor
-        );
+    // Select a new region on the selected monitor and append it to the composited ones
+    fn handle_region_selection(&mut self, config: &mut Config) {
+        let monitor = config.capture.selected_monitor;
+        if let Some(area) = select_area(monitor) {
+            config.capture.regions.push(CaptureRegion { monitor, area });
+        }
+    }
 
-        let output = match Command::new(env::current_exe().unwrap())
-            .arg("--overlay:selection")
-            .arg(display.x.to_string())
-            .arg(display.y.to_string())
-            .arg(display.width.to_string())
-            .arg(display.height.to_string())
-            .arg(display.scale_factor.to_string())
-            .output()
-        {
-            Ok(output) => output,
-            Err(e) => {
-                error!("Failed to execute selection process: {}", e);
-                return;
-            }
+    // Let the user draw the privacy masks of a monitor, replacing the previous ones
+    fn handle_mask_selection(&mut self, monitor: usize, config: &mut Config) {
+        let display = self.frame_grabber.display_id(monitor);
+        let masks = serde_json::to_string(&config.capture.masks_for(&display)).unwrap_or_default();
+        let Some(event) = run_overlay("masks", Some(monitor), &[masks]) else {
+            return;
         };
 
-        if output.status.success() {
-            match std::str::from_utf8(&output.stdout)
-                .map_err(|e| error!("Failed to read stdout: {}", e))
-                .and_then(|stdout| {
-                    debug!("Main process received: {}", stdout);
-                    serde_json::from_str(stdout)
-                        .map_err(|e| error!("Failed to parse JSON response: {}", e))
-                }) {
-                Ok(json_response) => self.process_selection_response(json_response),
-                Err(_) => error!("Failed to process selection response"),
+        match event {
+            OverlayEvent::Masks(areas) => {
+                info!("{} privacy masks on monitor {}", areas.len(), monitor);
+                config.capture.masks.retain(|mask| mask.display != display);
+                config
+                    .capture
+                    .masks
+                    .extend(areas.into_iter().map(|area| PrivacyMask {
+                        display: display.clone(),
+                        area,
+                    }));
             }
-        } else if let Ok(stderr) = std::str::from_utf8(&output.stderr) {
-            if !stderr.is_empty() {
-                error!("Secondary process error: {}", stderr);
+            OverlayEvent::Cancelled => {
+                debug!("User cancelled the mask editing");
+            }
+            event => {
+                error!("Unexpected answer of the masks overlay: {:?}", event);
             }
         }
     }
@@ -557,46 +886,76 @@ impl RustreamApp {
                         });
                 });
 
+                // Playback buffering settings
+                ui.add_space(10.0);
+                ui.separator();
+                ui.label(RichText::new("Playback").size(16.0));
+                ui.add_enabled_ui(!config.playback.low_latency, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Target delay:");
+                        ui.add(
+                            egui::Slider::new(&mut config.playback.target_delay_ms, 0..=1000)
+                                .suffix(" ms"),
+                        )
+                        .on_hover_text("Higher values give smoother playback on unstable networks");
+                    });
+                });
+                ui.checkbox(
+                    &mut config.playback.low_latency,
+                    "Lowest latency (show the newest frame, no buffering)",
+                );
+                ui.checkbox(
+                    &mut config.playback.show_cursor,
+                    "Show the presenter's pointer",
+                );
+                ui.add_enabled_ui(config.playback.show_cursor, |ui| {
+                    ui.checkbox(&mut config.playback.highlight_clicks, "Highlight clicks");
+                });
+                ui.checkbox(
+                    &mut config.playback.show_annotations,
+                    "Show the presenter's annotations",
+                );
+
+                // Shown to the caster and the other receivers while pointing
+                ui.add_space(10.0);
+                ui.separator();
+                ui.label(RichText::new("Laser Pointer").size(16.0));
+                let shortcut = self
+                    .hotkey_manager
+                    .get_shortcut_text(&HotkeyAction::LaserPointer)
+                    .unwrap_or_default();
+                ui.label(format!("Hold {} over the stream to point at it", shortcut));
+                ui.horizontal(|ui| {
+                    ui.label("Name:");
+                    ui.text_edit_singleline(&mut config.profile.name);
+                    ui.color_edit_button_srgb(&mut config.laser.color);
+                });
+
+                ui.add_space(10.0);
+                ui.separator();
+                ui.label(RichText::new("Chat").size(16.0));
+                ui.checkbox(
+                    &mut config.chat.save_with_recordings,
+                    "Save the chat next to recordings",
+                );
+
                 // Apply changes if the config has changed
-                let has_config_changed: bool = self.config.lock().unwrap().clone() != config;
-                if has_config_changed {
+                let current_config = self.config.lock().unwrap().clone();
+                if current_config != config {
+                    // Playback, names and chat settings don't affect the capture
+                    let has_capture_changed = current_config.capture != config.capture
+                        || current_config.video != config.video;
                     self.config.lock().unwrap().update(config);
-                    self.frame_grabber.reset_capture();
+                    self.config_changed_at = Some(Instant::now());
+                    if has_capture_changed {
+                        self.frame_grabber.reset_capture();
+                    }
                 }
             });
 
         self.show_config = show_config;
     }
 
-    fn render_recording_controls(&mut self, ui: &mut Ui) {
-        if let Some(video_recorder) = &self.video_recorder {
-            let recording = video_recorder.is_recording();
-            let finalizing = video_recorder.is_finalizing();
-
-            if finalizing {
-                ui.spinner();
-                ui.label("Finalizing video...");
-                return;
-            }
-
-            if self.action_button(
-                ui,
-                if recording {
-                    "⏹ Stop Recording"
-                } else {
-                    "⏺ Start Recording"
-                },
-                HotkeyAction::StartRecording,
-            ) {
-                if recording {
-                    self.stop_recording();
-                } else {
-                    self.start_recording();
-                }
-            }
-        }
-    }
-
     fn caster_page(&mut self, ctx: &Context, ui: &mut egui::Ui) {
         ui.vertical_centered(|ui| {
             ui.horizontal(|ui| {
@@ -645,40 +1004,90 @@ impl RustreamApp {
                     self.is_blank_screen.store(new, Ordering::SeqCst);
                 }
 
-                if self.action_button(ui, "🖊 Annotation", HotkeyAction::Annotation) {
-                    let selected_monitor = self.config.lock().unwrap().capture.selected_monitor;
-                    let displays = DisplayInfo::all().unwrap_or_default();
-
-                    match displays.get(selected_monitor) {
-                        Some(display) => {
-                            let mut child = Command::new(env::current_exe().unwrap())
-                                .arg("--overlay:annotation")
-                                .arg(display.x.to_string())
-                                .arg(display.y.to_string())
-                                .arg(display.width.to_string())
-                                .arg(display.height.to_string())
-                                .arg(display.scale_factor.to_string())
-                                .spawn()
-	                            .expect("Failed to spawn annotation overlay process");
-
-	                            self.is_annotation_open = Arc::new(AtomicBool::new(true));
-
-                                let is_open = self.is_annotation_open.clone();
-                                thread::spawn(move || {
-                                    let _ = child.wait();                   
-                                    is_open.store(false, Ordering::SeqCst);                  
-                                 });
-                        }
-                        None => {
-                            error!("Monitor not found: {}", selected_monitor);
-                        }
+                if self.action_button(
+                    ui,
+                    if !self.is_transmission_paused.load(Ordering::SeqCst) {
+                        "⏸ Pause Transmission"
+                    } else {
+                        "▶ Resume Transmission"
+                    },
+                    HotkeyAction::TogglePause,
+                ) {
+                    self.is_transmission_paused.fetch_xor(true, Ordering::SeqCst);
+                }
+
+                let annotating = self.is_annotation_open.load(Ordering::SeqCst);
+                if !annotating {
+                    // Reap the overlay once it exited
+                    self.annotation_overlay = None;
+                }
+                if self.action_button(
+                    ui,
+                    if annotating {
+                        "🖊 Close Annotation"
+                    } else {
+                        "🖊 Annotation"
+                    },
+                    HotkeyAction::Annotation,
+                ) {
+                    if annotating {
+                        self.send_to_annotation_overlay(OverlayCommand::Close);
+                    } else {
+                        self.open_annotation_overlay();
                     }
-    }
+                }
+
+                if annotating {
+                    if ui
+                        .button("▦ Redact")
+                        .on_hover_text("Switch the overlay to the redaction tool")
+                        .clicked()
+                    {
+                        self.send_to_annotation_overlay(OverlayCommand::SetTool(Tool::Redact));
+                    }
+                    if ui.button("🗑 Clear Annotations").clicked() {
+                        self.send_to_annotation_overlay(OverlayCommand::Clear);
+                    }
+                }
 
+                if annotating
+                    && ui
+                        .add_enabled(
+                            self.annotated_frame.is_some()
+                                && !self.streamed_annotations().is_empty(),
+                            egui::Button::new("🖼 Export PNG"),
+                        )
+                        .on_hover_text("Save the current frame with the annotations drawn on it")
+                        .clicked()
+                {
+                    self.export_annotated_frame();
+                }
 
                 if self.action_button(ui, "🖥 Display Settings", HotkeyAction::ToggleSettings) {
                     self.show_config = !self.show_config;
                 }
+
+                // Layout of the composited regions, applied to the next captured frame
+                let mut config = self.config.lock().unwrap();
+                if config.capture.regions.len() > 1 {
+                    let layout = &mut config.capture.layout;
+                    let previous_layout = *layout;
+                    ComboBox::from_id_salt("composite_layout")
+                        .selected_text(format!("▦ {}", layout))
+                        .show_ui(ui, |ui| {
+                            for option in [
+                                CompositeLayout::SideBySide,
+                                CompositeLayout::Grid,
+                                CompositeLayout::PictureInPicture,
+                            ] {
+                                ui.selectable_value(layout, option, option.to_string());
+                            }
+                        });
+                    if *layout != previous_layout {
+                        self.config_changed_at = Some(Instant::now());
+                    }
+                }
+                drop(config);
                 ui.add_space(50.0);
             });
         });
@@ -704,8 +1113,22 @@ impl RustreamApp {
 
                 drop(frames);
 
-                match self.capture_area {
+                // Composited regions and windows are already cropped by the capture thread
+                let has_regions = {
+                    let capture = &self.config.lock().unwrap().capture;
+                    !capture.regions.is_empty() || capture.window.is_some()
+                };
+                match self.capture_area.filter(|_| !has_regions) {
                     Some(area) => {
+                        // Selected on another frame size, e.g. before a monitor changed
+                        let area = CaptureArea::new_with_safeguards(
+                            area.x,
+                            area.y,
+                            area.width,
+                            area.height,
+                            display_frame.width,
+                            display_frame.height,
+                        );
                         display_frame = display_frame
                             .view(
                                 area.x as u32,
@@ -725,9 +1148,11 @@ impl RustreamApp {
                     if self.sender.is_none() && !self.socket_created {
                         let (tx, rx) = channel();
                         self.socket_created = true;
+                        let laser_pointers = self.laser_pointers.clone();
+                        let chat = self.caster_chat.clone();
 
                         tokio::spawn(async move {
-                            let sender = Sender::new().await;
+                            let sender = Sender::new(laser_pointers, chat).await;
                             let _ = tx.send(Arc::new(tokio::sync::Mutex::new(sender)));
                         });
 
@@ -747,38 +1172,85 @@ impl RustreamApp {
                         }
                     }
 
+                    self.sync_stream_captures();
+
                     // Send frame if we have a sender
                     if let Some(sender) = &self.sender {
                         //i redo the check to extract the sender from Option<Sender>
-                        let sender_clone = sender.clone();
-                        let clone_frame = display_frame.clone();
-                        let stop_notify = self.stop_notify.clone();
-                        let is_blank_clone = self.is_blank_screen.clone();
-                        let is_open = self.is_annotation_open.clone();
+                        let flags = StreamFlags {
+                            is_blank_screen: self.is_blank_screen.clone(),
+                            is_annotation_open: self.is_annotation_open.clone(),
+                            is_transmission_paused: self.is_transmission_paused.clone(),
+                        };
+                        let slate = self.config.lock().unwrap().slate.clone();
 
-                        tokio::spawn(async move {
-                            if let Err(e) = start_streaming(
-                                sender_clone,
-                                clone_frame,
-                                stop_notify,
-                                is_blank_clone,
-                                is_open,
-                            )
-                            .await
-                            {
-                                eprintln!("Error sending frame: {}", e);
+                        // The additional streams, captured at their own pace
+                        let mut frames = vec![(MAIN_STREAM, display_frame.clone())];
+                        for (id, _, capture) in &self.stream_captures {
+                            if let Some(frame) = capture.pop_frame() {
+                                frames.push((*id, frame));
+                            }
+                        }
+
+                        let mut streams = vec![StreamInfo {
+                            id: MAIN_STREAM,
+                            name: "Main".to_string(),
+                        }];
+                        streams.extend(self.stream_captures.iter().map(|(id, stream, _)| {
+                            StreamInfo {
+                                id: *id,
+                                name: stream.name.clone(),
                             }
+                        }));
+                        let annotations = self.streamed_annotations();
+                        let pixels_per_point = self.annotation_pixels_per_point;
+                        let sender_clone = sender.clone();
+                        tokio::spawn(async move {
+                            let mut sender = sender_clone.lock().await;
+                            sender.publish_streams(streams).await;
+                            sender
+                                .publish_annotations(annotations, pixels_per_point)
+                                .await;
                         });
+
+                        for (stream_id, frame) in frames {
+                            let sender_clone = sender.clone();
+                            let stop_notify = self.stop_notify.clone();
+                            let flags = flags.clone();
+                            let slate = slate.clone();
+
+                            tokio::spawn(async move {
+                                if let Err(e) = start_streaming(
+                                    sender_clone,
+                                    frame,
+                                    stream_id,
+                                    stop_notify,
+                                    flags,
+                                    slate,
+                                )
+                                .await
+                                {
+                                    eprintln!("Error sending frame: {}", e);
+                                }
+                            });
+                        }
                     }
                 }
                 // Send the END_STREAM message and clear sender if streaming is stopped
                 else if self.end_of_stream {
+                    self.stream_captures.clear();
                     self.end_stream();
                     self.sender = None;
                     self.sender_rx = None;
                     self.socket_created = false;
                 }
 
+                if self.is_annotation_open.load(Ordering::SeqCst) {
+                    self.annotated_frame = Some(display_frame.clone());
+                } else {
+                    self.annotated_frame = None;
+                }
+
                 // Convert to ColorImage for display
                 let image: ColorImage = egui::ColorImage::from_rgba_unmultiplied(
                     [display_frame.width, display_frame.height],
@@ -813,6 +1285,42 @@ impl RustreamApp {
 
     fn render_streaming_info(&mut self, ui: &mut egui::Ui, ctx: &Context) {
         ui.horizontal(|ui| {
+            // State of the followed window
+            match self.frame_grabber.window_state() {
+                TrackingState::Minimized => {
+                    ui.colored_label(Color32::ORANGE, "Window minimized: holding the last frame");
+                }
+                TrackingState::Closed => {
+                    ui.colored_label(Color32::RED, "Window closed: holding the last frame");
+                }
+                TrackingState::Idle | TrackingState::Following => {}
+            }
+
+            // Receivers point at the stream, shown on this screen and to the other receivers
+            let mut allow_pointers = self.laser_pointers.enabled.load(Ordering::SeqCst);
+            if ui
+                .checkbox(&mut allow_pointers, "Laser pointers")
+                .on_hover_text("Let receivers point at the stream")
+                .changed()
+            {
+                self.laser_pointers
+                    .enabled
+                    .store(allow_pointers, Ordering::SeqCst);
+                let mut config = self.config.lock().unwrap();
+                config.laser.allow_pointers = allow_pointers;
+                self.config_changed_at = Some(Instant::now());
+            }
+            let pointing = self.laser_pointers.active.lock().unwrap().len();
+            if allow_pointers && pointing > 0 {
+                ui.label(format!("🔦 {} pointing", pointing));
+            }
+
+            // The shortcut focuses the chat instead of toggling it, see render_chat_panel
+            let unread = self.caster_chat.lock().unwrap().unread();
+            if ui.button(chat_button_label(unread)).clicked() {
+                self.chat_panel.open = !self.chat_panel.open;
+            }
+
             ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
                 // Live indicator
                 let is_streaming = self.streaming_active;
@@ -822,7 +1330,11 @@ impl RustreamApp {
                     } else {
                         Color32::BLACK
                     };
-                    (Color32::RED, text_color, "LIVE")
+                    if self.is_transmission_paused.load(Ordering::SeqCst) {
+                        (Color32::ORANGE, text_color, "PAUSED")
+                    } else {
+                        (Color32::RED, text_color, "LIVE")
+                    }
                 } else {
                     (Color32::GRAY, Color32::GRAY, "LIVE")
                 };
@@ -854,347 +1366,1081 @@ impl RustreamApp {
     }
 
     pub fn receiver_page(&mut self, ctx: &Context, _ui: &mut Ui) {
-        if self.video_recorder.is_none() {
-            // initialize video recorder
-            self.video_recorder = Some(VideoRecorder::new(self.config.clone()));
-        }
-
-         //self.show_fps_counter(ctx);
         // Render the recording settings window if it's open
         self.render_recording_settings(ctx);
 
-        egui::CentralPanel::default().show(ctx, |ui| {
-            ui.vertical_centered(|ui| {
-                if !self.is_receiving {
-                    ui.label(RichText::new("Enter the Sender's IP Address").size(15.0));
-                    ui.add_space(10.0);
-
-                    let connect_button = egui::Button::new(
-                        egui::RichText::new("Connect")
-                            .color(egui::Color32::WHITE)
-                            .size(15.0),
-                    )
-                    .fill(egui::Color32::from_rgb(0, 200, 0))
-                    .min_size(egui::vec2(60.0, 30.0));
+        let playback = self.config.lock().unwrap().playback.clone();
+        for session in &mut self.sessions {
+            session.update(ctx, &playback);
+        }
+        if !self.sessions.is_empty() {
+            ctx.request_repaint();
+        }
 
-                    ui.style_mut().text_styles.insert(
-                        TextStyle::Body,
-                        FontId::new(15.0, egui::FontFamily::Proportional),
-                    );
-                    ui.add_sized(
-                        egui::vec2(300.0, 30.0), // Width: 300, Height: 30
-                        egui::TextEdit::singleline(&mut self.address_text).frame(true),
-                    );
-                    ui.add_space(20.0);
+        egui::CentralPanel::default().show(ctx, |ui| {
+            if self.sessions.is_empty() {
+                self.render_connect_page(ui);
+                return;
+            }
 
-                    //if connect button is clicked
-                    if ui
-                        .add_enabled(!self.address_text.trim().is_empty(), connect_button)
-                        .clicked()
-                        || self.triggered_actions.contains(&HotkeyAction::Connect)
-                    {
-                        //check if inserted address is valid
-                        if let Ok(addr) = self.address_text.parse::<Ipv4Addr>() {
-                            self.is_address_valid = true;
-                            let caster_addr = SocketAddr::new(IpAddr::V4(addr), PORT);
-                            self.caster_addr = Some(caster_addr);
+            self.render_connect_bar(ui);
+            ui.add_space(10.0);
+            match self.active_session() {
+                Some(index) => self.render_session_view(ctx, ui, index),
+                None => self.render_session_grid(ctx, ui),
+            }
+        });
+    }
 
-                            //clear the previous frame queue to prevent frames from previous streaming from being displayed
-                            let mut frames = self.received_frames.lock().unwrap();
-                            frames.clear();
-                            drop(frames);
+    // Shown until the first caster is added
+    fn render_connect_page(&mut self, ui: &mut Ui) {
+        ui.vertical_centered(|ui| {
+            ui.label(RichText::new("Enter the Sender's IP Address").size(15.0));
+            ui.add_space(10.0);
 
-                            let (tx, rx) = channel();
-                            let host_unreachable = self.host_unreachable.clone();
+            let connect_button = egui::Button::new(
+                egui::RichText::new("Connect")
+                    .color(egui::Color32::WHITE)
+                    .size(15.0),
+            )
+            .fill(egui::Color32::from_rgb(0, 200, 0))
+            .min_size(egui::vec2(60.0, 30.0));
+
+            ui.style_mut().text_styles.insert(
+                TextStyle::Body,
+                FontId::new(15.0, egui::FontFamily::Proportional),
+            );
+            ui.add_sized(
+                egui::vec2(300.0, 30.0), // Width: 300, Height: 30
+                egui::TextEdit::singleline(&mut self.address_text).frame(true),
+            );
+            ui.add_space(20.0);
+
+            //if connect button is clicked
+            if ui
+                .add_enabled(!self.address_text.trim().is_empty(), connect_button)
+                .clicked()
+                || self.triggered_actions.contains(&HotkeyAction::Connect)
+            {
+                self.connect_to_caster();
+            }
+            //show Invalid IP Address message if the address is not valid
+            if !self.is_address_valid {
+                ui.add_space(20.0);
+                ui.label(
+                    RichText::new("Invalid IP Address")
+                        .color(Color32::RED)
+                        .size(15.0),
+                );
+            }
+        });
+    }
 
-                            // Initialize receiver
-                            tokio::spawn(async move {
-                                match Receiver::new(caster_addr).await {
-                                    Ok(receiver) => {
-                                        let _ = tx.send(receiver);
-                                    }
-                                    Err(e) => {
-                                        eprintln!("Error initializing receiver: {}", e);
-                                        host_unreachable.store(true, Ordering::SeqCst);
-                                    }
-                                }
-                            });
+    // Add more casters to the grid and change its layout
+    fn render_connect_bar(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut self.address_text)
+                    .hint_text("Sender's IP Address")
+                    .desired_width(200.0),
+            );
+            if self.action_button(ui, "➕ Add Caster", HotkeyAction::Connect)
+                && !self.address_text.trim().is_empty()
+            {
+                self.connect_to_caster();
+            }
+            if !self.is_address_valid {
+                ui.label(RichText::new("Invalid IP Address").color(Color32::RED));
+            }
 
-                            //store rx to poll it later to see if initialization completed, since the channel sender is async
-                            self.receiver_rx = Some(rx);
-                            self.is_receiving = true;
-                        } else {
-                            self.is_address_valid = false;
-                        }
-                    }
-                    //show Invalid IP Address message if the address is not valid
-                    if !self.is_address_valid {
-                        ui.add_space(20.0);
-                        ui.label(
-                            RichText::new("Invalid IP Address")
-                                .color(Color32::RED)
-                                .size(15.0),
-                        );
-                    }
-                } else {
-                    //receiving already started
-                    // Show Stop, Start Recording and Recording Settings buttons
-                    ui.horizontal(|ui| {
-                        // Stop button
-                        let stop_button = egui::Button::new(
-                            egui::RichText::new("Stop")
-                                .color(egui::Color32::WHITE)
-                                .size(15.0),
-                        )
-                        .fill(egui::Color32::from_rgb(200, 0, 0))
-                        .min_size(egui::vec2(60.0, 30.0));
-                        ui.add_space(10.0);
-                        //Stop button
-                        if ui.add(stop_button).clicked() {
-                            self.reset_receiving();
-                        }
-
-                        // Pause button
-                        ui.add_space(10.0);
-                        if self.action_button(
-                            ui,
-                            if self.is_paused.load(Ordering::SeqCst) {
-                                "▶ Resume Streaming"
+            if self.focused_session.is_none() && self.sessions.len() > 1 {
+                ui.add_space(20.0);
+                ui.label("Columns:");
+                ui.add(
+                    egui::DragValue::new(&mut self.grid_columns)
+                        .range(0..=self.sessions.len())
+                        .custom_formatter(|n, _| {
+                            if n == 0.0 {
+                                "Auto".to_string()
                             } else {
-                                "⏸ Pause Streaming"
-                            },
-                            HotkeyAction::TogglePause,
-                        ) {
-                            self.is_paused
-                                .store(!self.is_paused.load(Ordering::SeqCst), Ordering::SeqCst);
-                        }
+                                n.to_string()
+                            }
+                        }),
+                );
+            }
 
-                        //  Recording Settings button
-                        ui.add_space(10.0);
-                        self.render_recording_controls(ui);
-
-                        ui.add_space(5.0);
-                        if self.action_button(
-                            ui,
-                            "⚙ Recording Settings",
-                            HotkeyAction::ToggleSettings,
-                        ) {
-                            self.show_config = !self.show_config;
-                        }
-                    });
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                if self.action_button(ui, "⚙ Recording Settings", HotkeyAction::ToggleSettings) {
+                    self.show_config = !self.show_config;
                 }
+            });
+        });
+    }
 
-                // Show Host Unreachable message if the host is unreachable
-                if self.host_unreachable.load(Ordering::SeqCst) {
-                    ui.add_space(20.0);
-                    ui.label(
-                        RichText::new("Host Unreachable")
-                            .color(Color32::RED)
-                            .size(20.0),
-                    );
+    // Add a tile for the typed caster, a caster already watched is focused instead
+    fn connect_to_caster(&mut self) {
+        let Ok(addr) = self.address_text.trim().parse::<Ipv4Addr>() else {
+            self.is_address_valid = false;
+            return;
+        };
+        self.is_address_valid = true;
+        let caster_addr = SocketAddr::new(IpAddr::V4(addr), PORT);
 
-                    let mut frames = self.received_frames.lock().unwrap();
-                    frames.clear();
-                    self.display_texture = None;
+        match self
+            .sessions
+            .iter()
+            .position(|session| session.caster_addr == caster_addr)
+        {
+            Some(index) => self.focused_session = Some(index),
+            None => self
+                .sessions
+                .push(ReceiverSession::connect(caster_addr, self.config.clone())),
+        }
+        self.address_text.clear();
+    }
+
+    // The session the toolbar and the hotkeys act on: the focused one, or the only one
+    fn active_session(&self) -> Option<usize> {
+        self.focused_session
+            .or((self.sessions.len() == 1).then_some(0))
+    }
+
+    fn close_session(&mut self, index: usize) {
+        self.sessions.remove(index);
+        self.focused_session = match self.focused_session {
+            Some(focused) if focused == index => None,
+            Some(focused) if focused > index => Some(focused - 1),
+            focused => focused,
+        };
+    }
+
+    // Full size view of a single caster
+    fn render_session_view(&mut self, ctx: &Context, ui: &mut Ui, index: usize) {
+        ui.vertical_centered(|ui| {
+            let mut close = false;
+            ui.horizontal(|ui| {
+                // Back to the grid
+                if self.sessions.len() > 1
+                    && (ui
+                        .button(RichText::new("⬅ Back to Grid").size(15.0))
+                        .clicked()
+                        || self.triggered_actions.contains(&HotkeyAction::ExitPopup))
+                {
+                    self.focused_session = None;
                 }
 
-                // Show Stream Ended message
-                if self.stream_ended.load(Ordering::SeqCst) {
-                    ui.add_space(ui.available_size().y * 0.40);
-                    ui.label(RichText::new("End Of The Stream").size(30.0));
+                // Stop button
+                let stop_button = egui::Button::new(
+                    egui::RichText::new("Stop")
+                        .color(egui::Color32::WHITE)
+                        .size(15.0),
+                )
+                .fill(egui::Color32::from_rgb(200, 0, 0))
+                .min_size(egui::vec2(60.0, 30.0));
+                ui.add_space(10.0);
+                if ui.add(stop_button).clicked() {
+                    close = true;
+                }
 
-                    let mut frames = self.received_frames.lock().unwrap();
-                    frames.clear();
-                    self.display_texture = None;
+                // Pause button
+                ui.add_space(10.0);
+                let is_paused = self.sessions[index].is_paused.load(Ordering::SeqCst);
+                if self.action_button(
+                    ui,
+                    if is_paused {
+                        "▶ Resume Streaming"
+                    } else {
+                        "⏸ Pause Streaming"
+                    },
+                    HotkeyAction::TogglePause,
+                ) {
+                    self.sessions[index].toggle_pause();
+                }
+
+                // Mute button, the caster stops sending frames
+                ui.add_space(10.0);
+                let session = &mut self.sessions[index];
+                if ui
+                    .button(
+                        RichText::new(if session.is_muted {
+                            "🔊 Unmute"
+                        } else {
+                            "🔇 Mute"
+                        })
+                        .size(15.0),
+                    )
+                    .clicked()
+                {
+                    session.is_muted = !session.is_muted;
+                }
+
+                // Statistics overlay button
+                ui.add_space(10.0);
+                let show_stats = self.sessions[index].show_stats;
+                if self.action_button(
+                    ui,
+                    if show_stats {
+                        "📊 Hide Statistics"
+                    } else {
+                        "📊 Show Statistics"
+                    },
+                    HotkeyAction::ToggleStatistics,
+                ) {
+                    self.sessions[index].show_stats = !show_stats;
                 }
 
-                // Show Pause message
-                if self.is_paused.load(Ordering::SeqCst) {
-                    ui.label(RichText::new("Paused").size(30.0));
+                // Recording button
+                ui.add_space(10.0);
+                if self.sessions[index].is_finalizing() {
+                    ui.spinner();
+                    ui.label("Finalizing video...");
+                } else if self.action_button(
+                    ui,
+                    if self.sessions[index].is_recording() {
+                        "⏹ Stop Recording"
+                    } else {
+                        "⏺ Start Recording"
+                    },
+                    HotkeyAction::StartRecording,
+                ) {
+                    self.sessions[index].toggle_recording();
                 }
 
-                // Check if we have a pending receiver initialization
-                if let Some(mut rx) = self.receiver_rx.take() {
-                    // take consumes the receiver_rx
+                // Chat with the caster and the other receivers
+                ui.add_space(10.0);
+                let unread = self.sessions[index].chat.lock().unwrap().unread();
+                if ui
+                    .button(RichText::new(chat_button_label(unread)).size(15.0))
+                    .clicked()
+                {
+                    self.chat_panel.open = !self.chat_panel.open;
+                }
+            });
+            if close {
+                self.close_session(index);
+                return;
+            }
 
-                    // Try to receive the receiver
-                    match rx.try_recv() {
-                        Ok(receiver) => {
-                            println!("Receiver initialized successfully");
-                            self.receiver = Some(Arc::new(tokio::sync::Mutex::new(receiver)));
-                        }
-                        Err(TryRecvError::Empty) => {
-                            // Put the channel receiver back if we haven't received yet
-                            self.receiver_rx = Some(rx);
-                        }
-                        Err(TryRecvError::Closed) => {
-                            ui.label(
-                                RichText::new("Failed to receive receiver from channel")
-                                    .color(Color32::RED),
-                            );
-                            self.receiver_rx = None;
-                        }
+            let is_pointing = self
+                .hotkey_manager
+                .is_held(ctx, &HotkeyAction::LaserPointer)
+                && !ctx.wants_keyboard_input();
+            let (laser, name) = {
+                let config = self.config.lock().unwrap();
+                (config.laser.clone(), config.profile.name.clone())
+            };
+            let session = &mut self.sessions[index];
+            Self::render_stream_picker(ui, session);
+            Self::render_session_status(ui, session, 1.0);
+
+            let max_size = ui.available_rect_before_wrap().size();
+            match Self::render_session_image(ctx, ui, session, max_size) {
+                Some(image_rect) => {
+                    if session.show_stats {
+                        Self::render_stats_overlay(ctx, session, image_rect);
                     }
+
+                    // Point at the stream while the key is held, not over side by side streams
+                    let position = ctx
+                        .pointer_hover_pos()
+                        .filter(|pos| {
+                            is_pointing
+                                && session.stream_textures.is_empty()
+                                && image_rect.contains(*pos)
+                        })
+                        .map(|pos| {
+                            let fraction = (pos - image_rect.min) / image_rect.size();
+                            (fraction.x, fraction.y)
+                        });
+                    session.point_at(position, &laser, &name);
                 }
+                None => session.point_at(None, &laser, &name),
+            }
+        });
+    }
 
-                // Start/continue receiving if we have a receiver
-                if let Some(receiver) = &mut self.receiver {
-                    //i redo the check to extract the sender from Option<Sender>
+    // Every caster in a tile, the grid follows the size of the window
+    fn render_session_grid(&mut self, ctx: &Context, ui: &mut Ui) {
+        let count = self.sessions.len();
+        let columns = match self.grid_columns {
+            0 => (count as f64).sqrt().ceil() as usize,
+            columns => columns.min(count),
+        }
+        .max(1);
+        let rows = count.div_ceil(columns);
+
+        let available = self.get_preview_screen_rect(ui).size();
+        let spacing = ui.spacing().item_spacing;
+        let tile_size = egui::vec2(
+            (available.x - spacing.x * (columns - 1) as f32) / columns as f32,
+            (available.y - spacing.y * (rows - 1) as f32) / rows as f32,
+        );
 
-                    let receiver_clone = receiver.clone();
-                    let rcv_frames = self.received_frames.clone();
-                    let stop_notify = self.stop_notify.clone();
-                    let host_unreachable = self.host_unreachable.clone();
-                    let stream_ended = self.stream_ended.clone();
-                    let is_paused_clone = self.is_paused.clone();
+        let mut focus = None;
+        let mut close = None;
+        for row in 0..rows {
+            ui.horizontal(|ui| {
+                for index in row * columns..((row + 1) * columns).min(count) {
+                    ui.allocate_ui_with_layout(
+                        tile_size,
+                        egui::Layout::top_down(egui::Align::Center),
+                        |ui| {
+                            ui.set_min_size(tile_size);
+                            match Self::render_tile(ctx, ui, &mut self.sessions[index]) {
+                                Some(TileAction::Focus) => focus = Some(index),
+                                Some(TileAction::Chat) => {
+                                    focus = Some(index);
+                                    self.chat_panel.focus();
+                                }
+                                Some(TileAction::Close) => close = Some(index),
+                                None => {}
+                            }
+                        },
+                    );
+                }
+            });
+        }
 
-                    tokio::spawn(async move {
-                        let mut receiver = receiver_clone.lock().await;
-
-                        //start receiving only if it's the first time
-                        if !receiver.started_receiving {
-                            receiver.started_receiving = true;
-                            drop(receiver); //drop the lock before starting the receiving task
-                            start_receiving(
-                                rcv_frames,
-                                receiver_clone,
-                                stop_notify,
-                                host_unreachable,
-                                stream_ended,
-                                is_paused_clone,
-                            )
-                            .await;
-                        }
-                    });
+        if let Some(index) = focus {
+            self.focused_session = Some(index);
+        }
+        if let Some(index) = close {
+            self.close_session(index);
+        }
+    }
 
-                    // Retrieve the latest frame from the queue
-                    let frame = {
-                        //in this way the lock is released immediately
-                        let mut frames = self.received_frames.lock().unwrap();
-                        // Pop back to minimize latency
-                        // to have a sense of continuous stream, use pop_front
-                        let curr_frame = frames.pop_back();
-
-                        if frames.len() >= 7 {
-                            println!("Received_Frames len: {}, dropping frames", frames.len());
-                            frames.clear();
-                        }
+    fn render_tile(ctx: &Context, ui: &mut Ui, session: &mut ReceiverSession) -> Option<TileAction> {
+        let mut action = None;
+        egui::Frame::group(ui.style()).show(ui, |ui| {
+            ui.horizontal(|ui| {
+                ui.add(egui::TextEdit::singleline(&mut session.label).desired_width(120.0));
+                if session.is_recording() {
+                    ui.colored_label(Color32::RED, "⏺ REC");
+                }
 
-                        curr_frame
-                    };
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    if ui.button("✖").on_hover_text("Disconnect").clicked() {
+                        action = Some(TileAction::Close);
+                    }
+                    if ui.button("⛶").on_hover_text("Focus").clicked() {
+                        action = Some(TileAction::Focus);
+                    }
+                    let unread = session.chat.lock().unwrap().unread();
+                    if ui
+                        .button(chat_button_label(unread))
+                        .on_hover_text("Chat with this caster")
+                        .clicked()
+                    {
+                        action = Some(TileAction::Chat);
+                    }
+                    if session.is_finalizing() {
+                        ui.spinner();
+                    } else if ui
+                        .button(if session.is_recording() { "⏹" } else { "⏺" })
+                        .on_hover_text("Record this caster")
+                        .clicked()
+                    {
+                        session.toggle_recording();
+                    }
+                    if ui
+                        .button(if session.is_muted { "🔇" } else { "🔊" })
+                        .on_hover_text("Stop receiving frames from this caster")
+                        .clicked()
+                    {
+                        session.is_muted = !session.is_muted;
+                    }
+                    let is_paused = session.is_paused.load(Ordering::SeqCst);
+                    if ui
+                        .button(if is_paused { "▶" } else { "⏸" })
+                        .on_hover_text("Pause")
+                        .clicked()
+                    {
+                        session.toggle_pause();
+                    }
+                });
+            });
 
-                    if let Some(frame) = frame {
-                        if let Some(video_recorder) = &mut self.video_recorder {
-                            if video_recorder.is_recording() {
-                                // Start video recording
-                                video_recorder.record_frame(&frame);
-                            }
-                        }
+            Self::render_session_status(ui, session, 0.6);
+            let max_size = ui.available_size();
+            if let Some(image_rect) = Self::render_session_image(ctx, ui, session, max_size) {
+                // Double click a tile to watch it full size
+                let response = ui.interact(
+                    image_rect,
+                    egui::Id::new(("session_tile", session.caster_addr)),
+                    egui::Sense::click(),
+                );
+                if response.double_clicked() {
+                    action = Some(TileAction::Focus);
+                }
+            }
+        });
+        action
+    }
+
+    // Connection problems and local pause, scaled down in the tiles
+    fn render_session_status(ui: &mut Ui, session: &ReceiverSession, scale: f32) {
+        if session.host_unreachable.load(Ordering::SeqCst) {
+            ui.add_space(20.0 * scale);
+            ui.label(
+                RichText::new("Host Unreachable")
+                    .color(Color32::RED)
+                    .size(20.0 * scale),
+            );
+        } else if session.stream_ended.load(Ordering::SeqCst) {
+            ui.add_space(ui.available_size().y * 0.40);
+            ui.label(RichText::new("End Of The Stream").size(30.0 * scale));
+        } else if session.display_texture.is_none() {
+            // Add a loading indicator while waiting for receiver initialization
+            ui.add_space(40.0 * scale);
+            ui.add_sized(egui::vec2(30.0, 30.0), egui::Spinner::new()); // Show a spinner while connecting
+            ui.label(RichText::new("Connecting to sender...").size(15.0));
+        }
+
+        if session.is_paused.load(Ordering::SeqCst) {
+            ui.label(RichText::new("Paused").size(30.0 * scale));
+        } else if session.is_muted {
+            ui.label(RichText::new("Muted").size(30.0 * scale));
+        }
+    }
+
+    // Frames of the session with the caster's overlays, returns the area they cover
+    fn render_session_image(
+        ctx: &Context,
+        ui: &mut Ui,
+        session: &ReceiverSession,
+        max_size: egui::Vec2,
+    ) -> Option<Rect> {
+        let texture = session.display_texture.as_ref()?;
+        let image_rect = if session.stream_textures.is_empty() {
+            let image_rect = ui.add(egui::Image::new(texture).max_size(max_size)).rect;
+            Self::render_annotations(ui, session, texture, image_rect);
+            Self::render_cursor(ui, session, texture, image_rect);
+            Self::render_laser_pointers(ui, session, image_rect);
+            image_rect
+        } else {
+            Self::render_side_by_side(ui, session, texture, max_size)
+        };
+        Self::render_slate_overlay(ctx, session, image_rect);
+        if session.paused_by_caster.load(Ordering::SeqCst) {
+            Self::render_paused_badge(ctx, session, image_rect);
+        }
+        Some(image_rect)
+    }
+
+    // Shapes drawn by the caster, crisp at any size since they are not part of the video
+    fn render_annotations(
+        ui: &Ui,
+        session: &ReceiverSession,
+        frame: &TextureHandle,
+        image_rect: Rect,
+    ) {
+        let to_screen = RectTransform::from_to(
+            Rect::from_min_size(egui::Pos2::ZERO, frame.size_vec2()),
+            image_rect,
+        );
+        let painter = ui.painter_at(image_rect);
+        let annotations = session.annotations();
+        if annotations
+            .iter()
+            .any(|annotation| annotation.shape.style().fade_after.is_some())
+        {
+            // Animate the fading ink
+            ui.ctx().request_repaint_after(Duration::from_millis(30));
+        }
+        for annotation in annotations {
+            draw_shape(
+                &painter,
+                &annotation.shape,
+                &to_screen,
+                annotation.pixels_per_point,
+            );
+        }
+    }
+
+    // Pointer of the caster over the watched stream, scaled like the frame
+    fn render_cursor(ui: &Ui, session: &ReceiverSession, frame: &TextureHandle, image_rect: Rect) {
+        let (Some(position), Some((_, cursor))) =
+            (session.cursor_position(), &session.cursor_texture)
+        else {
+            return;
+        };
+
+        // The pointer moves between frames, faster than the UI otherwise repaints
+        ui.ctx().request_repaint_after(Duration::from_millis(30));
 
-                        // Convert to ColorImage for display
-                        let image = egui::ColorImage::from_rgba_unmultiplied(
-                            [frame.width, frame.height],
-                            &frame.rgba_data,
+        let scale = image_rect.width() / frame.size_vec2().x.max(1.0);
+        let hotspot = image_rect.min + egui::vec2(position.x, position.y) * scale;
+        let painter = ui.painter_at(image_rect);
+
+        if position.pressed && session.highlight_clicks {
+            painter.circle(
+                hotspot,
+                16.0,
+                Color32::from_rgba_unmultiplied(255, 200, 0, 60),
+                egui::Stroke::new(2.0, Color32::from_rgb(255, 200, 0)),
+            );
+        }
+
+        let (hot_x, hot_y) = session.cursor_hotspot();
+        let cursor_rect = Rect::from_min_size(
+            hotspot - egui::vec2(hot_x, hot_y) * scale,
+            cursor.size_vec2() * scale,
+        );
+        painter.image(
+            cursor.id(),
+            cursor_rect,
+            Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0)),
+            Color32::WHITE,
+        );
+    }
+
+    // Laser pointers of the receivers over the watched stream, this one included
+    fn render_laser_pointers(ui: &Ui, session: &ReceiverSession, image_rect: Rect) {
+        let painter = ui.painter_at(image_rect);
+        for pointer in session.laser_pointers().iter().chain(&session.own_pointer) {
+            let position = image_rect.min + egui::vec2(pointer.x, pointer.y) * image_rect.size();
+            draw_pointer(&painter, position, pointer.color, &pointer.name);
+        }
+    }
+
+    // Choose which of the caster's streams to watch, or show all of them side by side
+    fn render_stream_picker(ui: &mut Ui, session: &mut ReceiverSession) {
+        let streams = session.available_streams.lock().unwrap().clone();
+        if streams.len() <= 1 {
+            return;
+        }
+
+        ui.add_space(5.0);
+        ui.horizontal(|ui| {
+            let selected_text = streams
+                .iter()
+                .find(|stream| stream.id == session.watched_stream)
+                .map_or(String::new(), |stream| stream.name.clone());
+            ComboBox::from_label("Stream")
+                .selected_text(selected_text)
+                .show_ui(ui, |ui| {
+                    for stream in &streams {
+                        ui.selectable_value(&mut session.watched_stream, stream.id, &stream.name);
+                    }
+                });
+            ui.add_space(10.0);
+            ui.checkbox(&mut session.side_by_side, "Side by side");
+        });
+    }
+
+    // Every subscribed stream scaled to share the space, returns the area they cover
+    fn render_side_by_side(
+        ui: &mut Ui,
+        session: &ReceiverSession,
+        watched: &TextureHandle,
+        max_size: egui::Vec2,
+    ) -> Rect {
+        let streams = session.available_streams.lock().unwrap().clone();
+        let name = |id: u32| {
+            streams
+                .iter()
+                .find(|stream| stream.id == id)
+                .map_or(String::new(), |stream| stream.name.clone())
+        };
+
+        let mut textures = vec![(name(session.watched_stream), watched)];
+        let mut others: Vec<_> = session.stream_textures.iter().collect();
+        others.sort_by_key(|(id, _)| **id);
+        for (id, texture) in others {
+            textures.push((name(*id), texture));
+        }
+
+        let max_size = egui::vec2(max_size.x / textures.len() as f32, max_size.y);
+        ui.horizontal(|ui| {
+            let mut covered = Rect::NOTHING;
+            for (name, texture) in textures {
+                let rect = ui
+                    .vertical(|ui| {
+                        ui.label(name);
+                        ui.add(egui::Image::new(texture).max_size(max_size));
+                    })
+                    .response
+                    .rect;
+                covered = covered.union(rect);
+            }
+            covered
+        })
+        .inner
+    }
+
+    // Message and countdown of the slate, drawn over the rendered background and image
+    fn render_slate_overlay(ctx: &Context, session: &ReceiverSession, image_rect: Rect) {
+        let slate = session.received_slate.lock().unwrap();
+        let Some(slate) = slate.as_ref() else {
+            return;
+        };
+
+        let painter = ctx.layer_painter(egui::LayerId::new(
+            egui::Order::Foreground,
+            egui::Id::new("slate_overlay"),
+        ));
+        let font_size = (image_rect.height() / 12.0).max(14.0);
+        let mut text_pos = egui::pos2(
+            image_rect.center().x,
+            image_rect.top() + image_rect.height() * 0.75,
+        );
+
+        if !slate.message.is_empty() {
+            painter.text(
+                text_pos,
+                egui::Align2::CENTER_CENTER,
+                &slate.message,
+                FontId::proportional(font_size),
+                Color32::WHITE,
+            );
+            text_pos.y += font_size * 1.5;
+        }
+
+        if slate.countdown_until != 0 {
+            let stats = session.stream_stats.lock().unwrap();
+            let until = stats
+                .to_local_time(slate.countdown_until)
+                .unwrap_or(slate.countdown_until);
+            let remaining = Duration::from_micros(until.saturating_sub(unix_timestamp_micros()));
+            let secs = remaining.as_secs();
+            painter.text(
+                text_pos,
+                egui::Align2::CENTER_CENTER,
+                format!("{:02}:{:02}", secs / 60, secs % 60),
+                FontId::monospace(font_size * 0.8),
+                Color32::LIGHT_GRAY,
+            );
+        }
+    }
+
+    // Badge shown over the frozen frame while the caster paused the transmission
+    fn render_paused_badge(ctx: &Context, session: &ReceiverSession, image_rect: Rect) {
+        egui::Area::new(egui::Id::new(("paused_badge", session.caster_addr)))
+            .fixed_pos(image_rect.right_top() + egui::vec2(-8.0, 8.0))
+            .pivot(egui::Align2::RIGHT_TOP)
+            .order(egui::Order::Foreground)
+            .interactable(false)
+            .show(ctx, |ui| {
+                egui::Frame::new()
+                    .fill(Color32::from_rgba_unmultiplied(200, 120, 0, 220))
+                    .corner_radius(4.0)
+                    .inner_margin(egui::Margin::symmetric(10, 6))
+                    .show(ui, |ui| {
+                        ui.label(
+                            RichText::new("⏸ Paused by presenter")
+                                .color(Color32::WHITE)
+                                .size(16.0)
+                                .strong(),
                         );
+                    });
+            });
+    }
 
-                        // Update texture in memory
-                        if let Some(ref mut texture) = self.display_texture {
-                            texture.set(image, egui::TextureOptions::default());
-                            //println!("texture updated");
-                        } else {
-                            self.display_texture = Some(ctx.load_texture(
-                                "display_texture",
-                                image,
-                                egui::TextureOptions::default(),
+    // Statistics drawn on top of the received stream
+    fn render_stats_overlay(ctx: &Context, session: &ReceiverSession, image_rect: Rect) {
+        let stats = session.stream_stats.lock().unwrap();
+
+        egui::Area::new(egui::Id::new("stats_overlay"))
+            .fixed_pos(image_rect.left_top() + egui::vec2(8.0, 8.0))
+            .order(egui::Order::Foreground)
+            .show(ctx, |ui| {
+                egui::Frame::new()
+                    .fill(Color32::from_black_alpha(180))
+                    .corner_radius(4.0)
+                    .inner_margin(6.0)
+                    .show(ui, |ui| {
+                        let resolution = stats
+                            .resolution()
+                            .map(|(w, h)| format!("{}x{}", w, h))
+                            .unwrap_or_else(|| "-".to_string());
+
+                        egui::Grid::new("stats_overlay_grid")
+                            .num_columns(2)
+                            .spacing([12.0, 2.0])
+                            .show(ui, |ui| {
+                                let mut row = |label: &str, value: String| {
+                                    ui.label(RichText::new(label).color(Color32::LIGHT_GRAY));
+                                    ui.label(RichText::new(value).color(Color32::WHITE).monospace());
+                                    ui.end_row();
+                                };
+                                row("FPS", format!("{:.1}", session.current_fps));
+                                row("Bitrate", format_bitrate(stats.bitrate_bps()));
+                                row("Resolution", resolution);
+                                row("Codec", VIDEO_CODEC.to_string());
+                                row("Latency", format_millis(stats.latency()));
+                                row("RTT", format_millis(stats.rtt()));
+                                row("Decode", format_millis(stats.decode_time()));
+                                row("Received", stats.frames_received().to_string());
+                                row(
+                                    "Dropped / Late",
+                                    format!("{} / {}", stats.frames_dropped(), stats.frames_late()),
+                                );
+                                row("Queue depth", stats.queue_depth().to_string());
+                                row("Uptime", format_uptime(stats.uptime()));
+                            });
+
+                        ui.add_space(4.0);
+                        Self::render_stats_graph(ui, &stats);
+                        ui.add_space(4.0);
+
+                        if ui.button("📋 Copy diagnostics").clicked() {
+                            let caster = session.caster_addr.to_string();
+                            ctx.copy_text(stats.diagnostics_report(
+                                session.current_fps,
+                                VIDEO_CODEC,
+                                Some(&caster),
                             ));
-                            //println!("texture loaded");
                         }
+                    });
+            });
+    }
 
-                        // Update FPS counter
-                        self.update_fps_counter();
-                    } else {
-                        // Add a loading indicator while waiting for receiver initialization
-                        if self.display_texture.is_none()
-                            && !self.host_unreachable.load(Ordering::SeqCst)
-                            && !self.stream_ended.load(Ordering::SeqCst)
-                        {
-                            ui.add_space(40.0);
-                            ui.add_sized(egui::vec2(30.0, 30.0), egui::Spinner::new()); // Show a spinner while connecting
-                            ui.label(RichText::new("Connecting to sender...").size(15.0));
-                        }
-                    }
-                    ctx.request_repaint();
+    // Rolling graph of the last minute, each series is scaled to its own maximum
+    fn render_stats_graph(ui: &mut Ui, stats: &StreamStats) {
+        let (response, painter) =
+            ui.allocate_painter(egui::vec2(240.0, 60.0), egui::Sense::hover());
+        let rect = response.rect;
+        painter.rect_filled(rect, 2.0, Color32::from_black_alpha(120));
+
+        let history = stats.history();
+        let step = rect.width() / (HISTORY_LEN - 1) as f32;
+        let series: [(Vec<f32>, Color32); 3] = [
+            (history.iter().map(|s| s.fps).collect(), Color32::GREEN),
+            (
+                history.iter().map(|s| s.bitrate_bps as f32).collect(),
+                Color32::LIGHT_BLUE,
+            ),
+            (
+                history
+                    .iter()
+                    .map(|s| s.latency.map_or(0.0, |l| l.as_secs_f32()))
+                    .collect(),
+                Color32::ORANGE,
+            ),
+        ];
+
+        for (values, color) in series {
+            let max = values.iter().cloned().fold(0.0, f32::max);
+            if max <= 0.0 {
+                continue;
+            }
+            // Newest sample on the right edge
+            let offset = (HISTORY_LEN - values.len()) as f32 * step;
+            let points: Vec<egui::Pos2> = values
+                .iter()
+                .enumerate()
+                .map(|(i, v)| {
+                    egui::pos2(
+                        rect.left() + offset + i as f32 * step,
+                        rect.bottom() - (v / max) * (rect.height() - 4.0) - 2.0,
+                    )
+                })
+                .collect();
+            painter.add(egui::Shape::line(points, egui::Stroke::new(1.5, color)));
+        }
+
+        ui.horizontal(|ui| {
+            ui.label(RichText::new("FPS").color(Color32::GREEN).small());
+            ui.label(RichText::new("Bitrate").color(Color32::LIGHT_BLUE).small());
+            ui.label(RichText::new("Latency").color(Color32::ORANGE).small());
+        });
+    }
+
+    // Start the captures of the additional streams, restarted when their sources change
+    fn sync_stream_captures(&mut self) {
+        let streams = self.config.lock().unwrap().capture.extra_streams.clone();
+        let same_sources = self.stream_captures.len() == streams.len()
+            && self
+                .stream_captures
+                .iter()
+                .zip(&streams)
+                .all(|((_, current, _), stream)| {
+                    (current.monitor, current.area) == (stream.monitor, stream.area)
+                });
+
+        if same_sources {
+            // A renamed stream keeps its capture
+            for ((_, current, _), stream) in self.stream_captures.iter_mut().zip(streams) {
+                current.name = stream.name;
+            }
+            return;
+        }
+
+        // New ids, a receiver watching a replaced stream falls back to the main one
+        let first_id = self
+            .stream_captures
+            .iter()
+            .map(|(id, _, _)| id + 1)
+            .max()
+            .unwrap_or(MAIN_STREAM + 1);
+        self.stream_captures = streams
+            .into_iter()
+            .zip(first_id..)
+            .map(|(stream, id)| {
+                let capture = StreamCapture::start(
+                    stream.clone(),
+                    self.config.clone(),
+                    self.frame_grabber.redactions(),
+                );
+                (id, stream, capture)
+            })
+            .collect();
+    }
+
+    // The overlay sends its shapes whenever they change, until it is closed. Redactions
+    // are applied to the captured frames instead of being streamed as shapes.
+    fn open_annotation_overlay(&mut self) {
+        let selected_monitor = self.config.lock().unwrap().capture.selected_monitor;
+        let displays = DisplayInfo::all().unwrap_or_default();
+        let Some(display) = displays.get(selected_monitor) else {
+            error!("Monitor not found: {}", selected_monitor);
+            return;
+        };
+
+        // The overlay in physical pixels, like its shapes
+        let screen = Rect::from_min_size(
+            egui::Pos2::ZERO,
+            egui::vec2(display.width as f32, display.height as f32) * display.scale_factor,
+        );
+        let is_open = Arc::new(AtomicBool::new(true));
+        let display_id = display_id(display);
+        let annotations = self.annotations.clone();
+        let redactions = self.frame_grabber.redactions();
+        let on_event = {
+            let is_open = is_open.clone();
+            move |event| match event {
+                OverlayEvent::Shapes(shapes) => {
+                    let (hidden, shapes): (Vec<_>, Vec<_>) = shapes
+                        .into_iter()
+                        .partition(|shape| matches!(shape, Shape::Redaction { .. }));
+                    *redactions.lock().unwrap() = hidden
+                        .iter()
+                        .filter_map(|shape| redaction(shape, &display_id, screen))
+                        .collect();
+                    *annotations.lock().unwrap() = shapes;
                 }
-                // Update texture in UI
-                if let Some(texture) = self.display_texture.as_ref() {
-                    ui.add(
-                        egui::Image::new(texture).max_size(self.get_preview_screen_rect(ui).size()),
-                    );
+                OverlayEvent::Error(message) => error!("Annotation overlay: {}", message),
+                // Closing the overlay removes the annotations and the redactions
+                OverlayEvent::Closed => {
+                    annotations.lock().unwrap().clear();
+                    redactions.lock().unwrap().clear();
+                    is_open.store(false, Ordering::SeqCst);
                 }
-            });
+                event => error!("Unexpected event of the annotation overlay: {:?}", event),
+            }
+        };
+
+        let args = [
+            display.x.to_string(),
+            display.y.to_string(),
+            display.width.to_string(),
+            display.height.to_string(),
+            display.scale_factor.to_string(),
+        ];
+        match OverlayProcess::spawn("annotation", &args, on_event) {
+            Ok(overlay) => self.annotation_overlay = Some(overlay),
+            Err(e) => {
+                error!("Failed to spawn annotation overlay process: {}", e);
+                return;
+            }
+        }
+        self.is_annotation_open = is_open;
+        self.annotation_pixels_per_point = display.scale_factor;
+    }
+
+    fn send_to_annotation_overlay(&mut self, command: OverlayCommand) {
+        if let Some(overlay) = &mut self.annotation_overlay {
+            overlay.send(&command);
+        }
+    }
+
+    // Annotations in pixels of the main stream. Composited regions and windows don't map
+    // to the annotated monitor, their annotations only reach the receivers in the video.
+    fn streamed_annotations(&self) -> Vec<Shape> {
+        let capture = self.config.lock().unwrap().capture.clone();
+        if capture.whole_desktop || !capture.regions.is_empty() || capture.window.is_some() {
+            return Vec::new();
+        }
+
+        let offset = self.capture_area.map_or(egui::Vec2::ZERO, |area| {
+            egui::vec2(area.x as f32, area.y as f32)
         });
+        let mut shapes = self.annotations.lock().unwrap().clone();
+        for shape in &mut shapes {
+            shape.translate(-offset);
+        }
+        shapes
     }
 
-    fn end_stream(&mut self) {
-        if let Some(sender) = &self.sender {
-            let sender_clone = sender.clone();
+    // The annotations flattened onto the frame being streamed, as PNG
+    fn export_annotated_frame(&self) {
+        let Some(frame) = &self.annotated_frame else {
+            return;
+        };
+        let Some(image) = image::RgbaImage::from_raw(
+            frame.width as u32,
+            frame.height as u32,
+            frame.rgba_data.clone(),
+        ) else {
+            return;
+        };
+        let Some(path) = rfd::FileDialog::new()
+            .set_title("Export annotated frame as...")
+            .set_file_name("annotations.png")
+            .add_filter("PNG Image", &["png"])
+            .save_file()
+        else {
+            return;
+        };
 
-            tokio::spawn(async move {
-                let sender = sender_clone.lock().await;
-                sender.end_stream().await;
-            });
+        let shapes = self.streamed_annotations();
+        let result = export::flatten(image, &shapes, self.annotation_pixels_per_point)
+            .and_then(|image| Ok(image.save(&path)?));
+        match result {
+            Ok(_) => info!("Annotated frame exported to {}", path.display()),
+            Err(e) => error!("Failed to export the annotated frame: {}", e),
+        }
+    }
 
-            self.end_of_stream = false; //reset the flag
+    // Show the pointers of the receivers on this screen, over the area being streamed
+    fn update_pointer_overlay(&mut self) {
+        let enabled = self.streaming_active && self.laser_pointers.enabled.load(Ordering::SeqCst);
+        let pointers: Vec<OverlayPointer> = self
+            .laser_pointers
+            .active
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|pointer| enabled && pointer.stream_id == MAIN_STREAM)
+            .map(|pointer| OverlayPointer {
+                x: pointer.x,
+                y: pointer.y,
+                color: pointer.color,
+                name: pointer.name.clone(),
+            })
+            .collect();
+
+        // Started on the first pointer, it stays until the stream or the pointers are stopped
+        if !enabled || (pointers.is_empty() && self.pointer_overlay.is_none()) {
+            self.pointer_overlay = None;
+            return;
+        }
+        let Some(geometry) = self.pointer_overlay_geometry() else {
+            return;
+        };
+        if self
+            .pointer_overlay
+            .as_ref()
+            .is_some_and(|overlay| overlay.geometry() != geometry)
+        {
+            self.pointer_overlay = None;
+        }
+        if self.pointer_overlay.is_none() {
+            self.pointer_overlay = PointerOverlay::spawn(geometry);
+        }
+        if let Some(overlay) = &mut self.pointer_overlay {
+            if !overlay.show(pointers) {
+                self.pointer_overlay = None;
+            }
         }
     }
 
-    fn reset_receiving(&mut self) {
-        self.stop_notify.notify_waiters();
-        self.host_unreachable.store(false, Ordering::SeqCst);
-        self.receiver = None;
-        self.receiver_rx = None;
-        self.display_texture = None;
-        self.is_receiving = false;
-        let mut frames = self.received_frames.lock().unwrap();
-        frames.clear();
-        self.last_frame_time = None;
-        self.frame_times.clear();
-        self.current_fps = 0.0;
-        self.video_recorder = None;
-        self.stream_ended.store(false, Ordering::SeqCst);
+    // Overlay arguments covering the streamed area. Composited regions and windows
+    // don't map to a single area, their pointers are spread over the whole monitor.
+    fn pointer_overlay_geometry(&self) -> Option<Vec<String>> {
+        let capture = self.config.lock().unwrap().capture.clone();
+        let displays = DisplayInfo::all().unwrap_or_default();
+        let display = if capture.whole_desktop {
+            desktop_bounds(&displays)?
+        } else {
+            displays.get(capture.selected_monitor)?.clone()
+        };
+
+        let mut geometry = vec![
+            display.x.to_string(),
+            display.y.to_string(),
+            display.width.to_string(),
+            display.height.to_string(),
+            display.scale_factor.to_string(),
+        ];
+        let is_composited = !capture.regions.is_empty() || capture.window.is_some();
+        if let Some(area) = self.capture_area.filter(|_| !is_composited) {
+            geometry.extend([area.x, area.y, area.width, area.height].map(|v| v.to_string()));
+        }
+        if capture.whole_desktop {
+            geometry.push("--span-desktop".to_string());
+        }
+        Some(geometry)
     }
 
-    fn update_fps_counter(&mut self) {
-        let now = std::time::Instant::now();
-        if let Some(last_frame_time) = self.last_frame_time {
-            let frame_time = now.duration_since(last_frame_time);
-            self.frame_times.push_back(frame_time);
+    // Chat of the caster page, or of the session shown full size on the receiver page
+    fn render_chat_panel(&mut self, ctx: &Context) {
+        if self.triggered_actions.contains(&HotkeyAction::FocusChat) {
+            self.chat_panel.focus();
+        }
 
-            // Keep only last 60 frame times for moving average
-            if self.frame_times.len() > 60 {
-                self.frame_times.pop_front();
-            }
+        let mut name = self.config.lock().unwrap().profile.name.clone();
+        let previous_name = name.clone();
+        match self.page {
+            PageView::Caster => {
+                let mut chat = self.caster_chat.lock().unwrap();
+                let sent = self
+                    .chat_panel
+                    .show(ctx, &mut chat, &mut name, self.sender.is_some());
+                if let (Some(text), Some(sender)) = (sent, &self.sender) {
+                    let message = ChatMessage {
+                        name: name.clone(),
+                        timestamp: unix_timestamp_micros(),
+                        text,
+                        is_own: true,
+                    };
+                    let packet = message.to_packet();
+                    chat.push(message);
 
-            // Calculate average FPS
-            if !self.frame_times.is_empty() {
-                let avg_frame_time: std::time::Duration =
-                    self.frame_times.iter().sum::<std::time::Duration>()
-                        / self.frame_times.len() as u32;
-                self.current_fps = 1.0 / avg_frame_time.as_secs_f32();
+                    let sender = sender.clone();
+                    tokio::spawn(async move {
+                        sender.lock().await.send_chat(packet).await;
+                    });
+                }
             }
+            PageView::Receiver => {
+                let Some(index) = self.active_session() else {
+                    return;
+                };
+                let session = &mut self.sessions[index];
+                let can_send = session.is_connected();
+                let chat = session.chat.clone();
+                let sent =
+                    self.chat_panel
+                        .show(ctx, &mut chat.lock().unwrap(), &mut name, can_send);
+                if let Some(text) = sent {
+                    session.send_chat(&name, text);
+                }
+            }
+            PageView::HomePage => return,
         }
-        self.last_frame_time = Some(now);
-    }
 
-    fn start_recording(&mut self) {
-        if let Some(video_recorder) = &mut self.video_recorder {
-            video_recorder.start();
+        if name != previous_name {
+            self.config.lock().unwrap().profile.name = name;
+            self.config_changed_at = Some(Instant::now());
         }
     }
 
-    fn stop_recording(&mut self) {
-        if let Some(video_recorder) = &mut self.video_recorder {
-            video_recorder.stop(Some(self.current_fps as u32));
+    fn save_config(&mut self) {
+        self.config.lock().unwrap().save();
+        self.config_changed_at = None;
+    }
+
+    fn end_stream(&mut self) {
+        if let Some(sender) = &self.sender {
+            let sender_clone = sender.clone();
+
+            tokio::spawn(async move {
+                let sender = sender_clone.lock().await;
+                sender.end_stream().await;
+            });
+
+            self.end_of_stream = false; //reset the flag
         }
     }
 
@@ -1287,40 +2533,160 @@ impl RustreamApp {
          });
      }*/
 
-    fn process_selection_response(&mut self, json_response: serde_json::Value) {
-        match json_response["status"].as_str() {
-            Some("success") => {
-                if let Some(data) = json_response.get("data") {
-                    // Parse capture area data with detailed error handling
-                    let capture_area = serde_json::from_value(data.clone()).unwrap_or_else(|e| {
-                        error!("Failed to parse capture area data: {}", e);
-                        error!("Possible struct mismatch between SecondaryApp and main process");
-                        error!(
-                            "Expected format: {{x: usize, y: usize, width: usize, height: usize}}"
-                        );
-                        None
-                    });
-                    self.capture_area = capture_area;
-                }
-            }
-            Some("cancelled") => {
+    fn process_selection_response(&mut self, event: OverlayEvent) {
+        match event {
+            OverlayEvent::Area(area) => self.capture_area = Some(area),
+            OverlayEvent::Cancelled => {
                 debug!("User cancelled the capture operation");
             }
-            _ => {
-                error!("Unknown status in response");
+            event => {
+                error!("Unexpected answer of the selection overlay: {:?}", event);
+            }
+        }
+    }
+}
+
+// Pixels of the monitor hidden by a redaction drawn on the annotation overlay, clipped to
+// `screen` since masks that don't fit cover the whole frame
+fn redaction(shape: &Shape, display: &str, screen: Rect) -> Option<Redaction> {
+    let Shape::Redaction { rect, style, .. } = shape else {
+        return None;
+    };
+    // Whole pixels around the redaction, partly covered ones are hidden too
+    let rect = rect.intersect(screen);
+    let rect = Rect::from_min_max(rect.min.floor(), rect.max.ceil());
+    if !rect.is_positive() {
+        return None;
+    }
+    Some(Redaction {
+        display: display.to_string(),
+        area: CaptureArea {
+            x: rect.min.x as usize,
+            y: rect.min.y as usize,
+            width: rect.width() as usize,
+            height: rect.height() as usize,
+        },
+        style: style.redaction,
+    })
+}
+
+// Run an overlay process on the given monitor, or the whole desktop, and wait for its answer
+fn run_overlay(mode: &str, monitor: Option<usize>, extra_args: &[String]) -> Option<OverlayEvent> {
+    let displays = DisplayInfo::all().unwrap_or_default();
+    let display = match monitor {
+        Some(monitor) => match displays.get(monitor) {
+            Some(d) => d.clone(),
+            None => {
+                error!("Monitor not found: {}", monitor);
+                return None;
+            }
+        },
+        None => match desktop_bounds(&displays) {
+            Some(d) => d,
+            None => {
+                error!("No monitors found");
+                return None;
             }
+        },
+    };
+
+    info!(
+        "Display: {} ({},{}) ({}x{}) | scale factor: {}",
+        display.name, display.x, display.y, display.width, display.height, display.scale_factor
+    );
+
+    let mut args = vec![
+        display.x.to_string(),
+        display.y.to_string(),
+        display.width.to_string(),
+        display.height.to_string(),
+        display.scale_factor.to_string(),
+    ];
+    args.extend_from_slice(extra_args);
+    if monitor.is_none() {
+        args.push("--span-desktop".to_string());
+    }
+
+    let (answer, answered) = std::sync::mpsc::channel();
+    let _overlay = match OverlayProcess::spawn(mode, &args, move |event| {
+        let _ = answer.send(event);
+    }) {
+        Ok(overlay) => overlay,
+        Err(e) => {
+            error!("Failed to execute {} process: {}", mode, e);
+            return None;
+        }
+    };
+
+    // The first event is the answer, the overlay exits right after it
+    match answered.recv() {
+        Ok(OverlayEvent::Closed) | Err(_) => {
+            error!("The {} overlay exited without answering", mode);
+            None
+        }
+        Ok(OverlayEvent::Error(message)) => {
+            error!("The {} overlay failed: {}", mode, message);
+            None
+        }
+        Ok(event) => {
+            debug!("Main process received: {:?}", event);
+            Some(event)
         }
     }
 }
 
+// Let the user select an area of a monitor with the selection overlay
+fn select_area(monitor: usize) -> Option<CaptureArea> {
+    match run_overlay("selection", Some(monitor), &[])? {
+        OverlayEvent::Area(area) => Some(area),
+        OverlayEvent::Cancelled => {
+            debug!("User cancelled the area selection");
+            None
+        }
+        event => {
+            error!("Unexpected answer of the selection overlay: {:?}", event);
+            None
+        }
+    }
+}
+
+// Bounding box of every monitor, with the largest scale factor like the stitched frame
+fn desktop_bounds(displays: &[DisplayInfo]) -> Option<DisplayInfo> {
+    let primary = displays
+        .iter()
+        .find(|d| d.is_primary)
+        .or(displays.first())?;
+    let scale_factor = displays.iter().map(|d| d.scale_factor).fold(1.0, f32::max);
+    let left = displays.iter().map(|d| d.x).min()?;
+    let top = displays.iter().map(|d| d.y).min()?;
+    let right = displays.iter().map(|d| d.x + d.width as i32).max()?;
+    let bottom = displays.iter().map(|d| d.y + d.height as i32).max()?;
+
+    Some(DisplayInfo {
+        name: "Whole Desktop".to_string(),
+        x: left,
+        y: top,
+        width: (right - left) as u32,
+        height: (bottom - top) as u32,
+        scale_factor,
+        ..primary.clone()
+    })
+}
+
 impl eframe::App for RustreamApp {
     fn update(&mut self, ctx: &Context, _frame: &mut eframe::Frame) {
         if let Some(action) = self.hotkey_manager.handle_input(ctx) {
-            self.triggered_actions.push(action);
+            // Letters typed in a text field, like the chat, are not shortcuts
+            if !ctx.wants_keyboard_input()
+                || matches!(action, HotkeyAction::ExitPopup | HotkeyAction::FocusChat)
+            {
+                self.triggered_actions.push(action);
+            }
         }
         TopBottomPanel::top("header").show(ctx, |ui| {
             self.render_header(ctx, ui);
         });
+        self.render_chat_panel(ctx);
 
         CentralPanel::default().show(ctx, |ui| match self.page {
             PageView::HomePage => self.home_page(ui),
@@ -1331,6 +2697,7 @@ impl eframe::App for RustreamApp {
         });
 
         if self.page == PageView::Caster {
+            self.update_pointer_overlay();
             TopBottomPanel::bottom("streaming_info").show(ctx, |ui| {
                 ui.add_space(2.5);
                 self.render_streaming_info(ui, ctx);
@@ -1339,6 +2706,19 @@ impl eframe::App for RustreamApp {
 
         self.triggered_actions.clear();
 
+        if self
+            .config_changed_at
+            .is_some_and(|changed| changed.elapsed() >= CONFIG_SAVE_DELAY)
+        {
+            self.save_config();
+        }
+
         ctx.request_repaint_after(Duration::from_millis(300));
     }
+
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        if self.config_changed_at.is_some() {
+            self.save_config();
+        }
+    }
 }
@@ -1,8 +1,7 @@
 use crate::common::CaptureArea;
+use crate::ipc::{self, OverlayEvent};
 use eframe::egui::{self, CentralPanel, Color32, Pos2, Rect, RichText}; // Add RichText import
 use egui::StrokeKind;
-use serde_json::json;
-use std::io::Write;
 
 #[derive(Default)]
 pub struct AreaCaptureApp {
@@ -18,15 +17,13 @@ impl eframe::App for AreaCaptureApp {
 
         // Handle Alt+F4 and window close events
         if ctx.input(|i| i.viewport().close_requested()) {
-            println!("{{\"status\": \"cancelled\"}}\n");
-            std::io::stdout().flush().unwrap();
+            ipc::send(&OverlayEvent::Cancelled);
             std::process::exit(0);
         }
 
         // Add Esc key handling
         if ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
-            println!("{{\"status\": \"cancelled\"}}\n");
-            std::io::stdout().flush().unwrap();
+            ipc::send(&OverlayEvent::Cancelled);
             std::process::exit(0);
         }
 
@@ -148,16 +145,7 @@ impl eframe::App for AreaCaptureApp {
                                                 display_height,
                                             );
 
-                                            let output_with_status = json!({
-                                                "status": "success",
-                                                "data": output
-                                            });
-
-                                            println!(
-                                                "{}\n",
-                                                serde_json::to_string(&output_with_status).unwrap()
-                                            );
-                                            std::io::stdout().flush().unwrap();
+                                            ipc::send(&OverlayEvent::Area(output));
 
                                             self.capture_area = Some(output);
                                             self.show_popup = false;
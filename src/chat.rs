@@ -0,0 +1,218 @@
+use crate::protocol::ChatPacket;
+
+use eframe::egui::{self, Color32, Context, Key, RichText, ScrollArea};
+
+// Longest message accepted from the input, longer ones are cut
+const MAX_MESSAGE_LEN: usize = 1000;
+
+// Message of the chat between the caster and its receivers
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChatMessage {
+    pub name: String,
+    pub timestamp: u64, // Microseconds since UNIX epoch, clock of the author
+    pub text: String,
+    pub is_own: bool, // Written on this machine
+}
+
+impl ChatMessage {
+    pub fn received(packet: ChatPacket) -> Self {
+        Self {
+            name: packet.name,
+            timestamp: packet.timestamp,
+            text: packet.text,
+            is_own: false,
+        }
+    }
+
+    pub fn to_packet(&self) -> ChatPacket {
+        ChatPacket {
+            timestamp: self.timestamp,
+            name: self.name.clone(),
+            text: self.text.clone(),
+        }
+    }
+}
+
+// Messages exchanged during the session, shared between the network tasks and the UI
+#[derive(Debug, Default)]
+pub struct ChatHistory {
+    messages: Vec<ChatMessage>,
+    unread: usize, // Received while the panel was closed
+}
+
+impl ChatHistory {
+    pub fn push(&mut self, message: ChatMessage) {
+        if !message.is_own {
+            self.unread += 1;
+        }
+        self.messages.push(message);
+    }
+
+    pub fn messages(&self) -> &[ChatMessage] {
+        &self.messages
+    }
+
+    pub fn unread(&self) -> usize {
+        self.unread
+    }
+
+    pub fn mark_read(&mut self) {
+        self.unread = 0;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.messages.clear();
+        self.unread = 0;
+    }
+
+    // Plain text copy of the chat, saved next to the recordings
+    pub fn transcript(&self) -> String {
+        self.messages
+            .iter()
+            .map(|message| {
+                format!(
+                    "[{} UTC] {}: {}\n",
+                    format_clock(message.timestamp),
+                    message.name,
+                    message.text
+                )
+            })
+            .collect()
+    }
+}
+
+// Hours and minutes of a timestamp, in UTC since there is no time zone database around
+pub fn format_clock(timestamp: u64) -> String {
+    let minutes = timestamp / 60_000_000;
+    format!("{:02}:{:02}", minutes / 60 % 24, minutes % 60)
+}
+
+// Button label toggling the chat panel, with the messages not read yet
+pub fn chat_button_label(unread: usize) -> String {
+    if unread > 0 {
+        format!("💬 Chat ({})", unread)
+    } else {
+        "💬 Chat".to_string()
+    }
+}
+
+// Collapsible side panel, the same on the caster and receiver pages
+#[derive(Debug, Default)]
+pub struct ChatPanel {
+    pub open: bool,
+    draft: String,
+    focus_requested: bool,
+}
+
+impl ChatPanel {
+    fn input_id() -> egui::Id {
+        egui::Id::new("chat_input")
+    }
+
+    // Open the panel and move the keyboard focus to the input
+    pub fn focus(&mut self) {
+        self.open = true;
+        self.focus_requested = true;
+    }
+
+    // Must be shown before the central panel. Returns the text the user sent, if any.
+    // Without `can_send` the history is shown but the input is disabled.
+    pub fn show(
+        &mut self,
+        ctx: &Context,
+        history: &mut ChatHistory,
+        name: &mut String,
+        can_send: bool,
+    ) -> Option<String> {
+        if !self.open {
+            return None;
+        }
+        history.mark_read();
+
+        let mut sent = None;
+        egui::SidePanel::right("chat_panel")
+            .resizable(true)
+            .default_width(260.0)
+            .min_width(180.0)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label(RichText::new("Chat").size(16.0));
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        if ui.button("✖").on_hover_text("Hide the chat").clicked() {
+                            self.open = false;
+                        }
+                    });
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Name:");
+                    ui.add(egui::TextEdit::singleline(name).desired_width(f32::INFINITY));
+                });
+                ui.separator();
+
+                // The input stays at the bottom, the messages fill the rest
+                egui::TopBottomPanel::bottom("chat_input_panel")
+                    .show_separator_line(false)
+                    .show_inside(ui, |ui| {
+                        ui.add_space(4.0);
+                        ui.add_enabled_ui(can_send, |ui| {
+                            let response = ui.add(
+                                egui::TextEdit::singleline(&mut self.draft)
+                                    .id(Self::input_id())
+                                    .char_limit(MAX_MESSAGE_LEN)
+                                    .hint_text(if can_send {
+                                        "Write a message"
+                                    } else {
+                                        "Not connected"
+                                    })
+                                    .desired_width(f32::INFINITY),
+                            );
+                            if self.focus_requested {
+                                response.request_focus();
+                                self.focus_requested = false;
+                            }
+                            if response.lost_focus() && ui.input(|i| i.key_pressed(Key::Enter)) {
+                                let text = self.draft.trim().to_string();
+                                if !text.is_empty() {
+                                    sent = Some(text);
+                                }
+                                self.draft.clear();
+                                // Ready for the next message
+                                response.request_focus();
+                            }
+                        });
+                    });
+
+                ScrollArea::vertical()
+                    .auto_shrink([false, false])
+                    .stick_to_bottom(true)
+                    .show(ui, |ui| {
+                        if history.is_empty() {
+                            ui.label(RichText::new("No messages yet").color(Color32::GRAY));
+                        }
+                        for message in history.messages() {
+                            let color = if message.is_own {
+                                Color32::LIGHT_BLUE
+                            } else {
+                                Color32::LIGHT_GREEN
+                            };
+                            ui.horizontal_wrapped(|ui| {
+                                ui.label(
+                                    RichText::new(format_clock(message.timestamp))
+                                        .small()
+                                        .color(Color32::GRAY),
+                                )
+                                .on_hover_text("UTC");
+                                ui.label(RichText::new(&message.name).strong().color(color));
+                            });
+                            ui.label(&message.text);
+                            ui.add_space(4.0);
+                        }
+                    });
+            });
+        sent
+    }
+}
@@ -1,8 +1,17 @@
 use egui::Rect;
 use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 pub type RgbaBuffer = Vec<u8>;
 
+// Wall clock time in microseconds, used to timestamp frames across machines
+pub fn unix_timestamp_micros() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_micros() as u64)
+        .unwrap_or(0)
+}
+
 #[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
 pub struct CaptureArea {
     pub x: usize,
@@ -1,20 +1,76 @@
 use crate::common::CaptureArea;
+use crate::screen_capture::WindowInfo;
+use display_info::DisplayInfo;
+use log::{debug, error};
+use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
-#[derive(Debug, Clone, Default, PartialEq)]
+const CONFIG_FILE: &str = "config.json";
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
 pub struct Config {
     pub video: VideoConfig,
     pub capture: CaptureConfig,
+    pub playback: PlaybackConfig,
+    pub slate: SlateConfig,
+    pub laser: LaserConfig,
+    pub profile: ProfileConfig,
+    pub chat: ChatConfig,
 }
 
 impl Config {
     pub fn update(&mut self, new_config: Config) {
         self.video = new_config.video;
         self.capture = new_config.capture;
+        self.playback = new_config.playback;
+        self.slate = new_config.slate;
+        self.laser = new_config.laser;
+        self.profile = new_config.profile;
+        self.chat = new_config.chat;
+    }
+
+    // Settings saved by a previous session, or the defaults
+    pub fn load() -> Self {
+        let Some(path) = config_path() else {
+            return Self::default();
+        };
+
+        match std::fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                error!("Invalid config file {}: {}", path.display(), e);
+                Self::default()
+            }),
+            Err(_) => Self::default(), // First run
+        }
+    }
+
+    pub fn save(&self) {
+        let Some(path) = config_path() else {
+            error!("No config directory available, settings will not be saved");
+            return;
+        };
+
+        let result = path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| {
+                let contents = serde_json::to_string_pretty(self)?;
+                std::fs::write(&path, contents)
+            });
+        match result {
+            Ok(_) => debug!("Config saved to {}", path.display()),
+            Err(e) => error!("Failed to save config to {}: {}", path.display(), e),
+        }
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+fn config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("rustream").join(CONFIG_FILE))
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
 pub struct VideoConfig {
     pub output_path: PathBuf,
     pub fps: u32,
@@ -45,8 +101,246 @@ impl Default for VideoConfig {
     }
 }
 
-#[derive(Debug, Clone, Default, PartialEq)]
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
 pub struct CaptureConfig {
+    #[serde(skip)] // Monitors may change between sessions
     pub selected_monitor: usize,
+    #[serde(skip)]
+    pub whole_desktop: bool, // Every monitor stitched together, instead of the selected one
+    #[serde(skip)]
     pub capture_area: Option<CaptureArea>,
+    pub masks: Vec<PrivacyMask>,
+    pub mask_style: MaskStyle,
+    #[serde(skip)]
+    pub regions: Vec<CaptureRegion>, // When not empty, composited instead of the selected monitor
+    #[serde(skip)]
+    pub window: Option<WindowInfo>, // Followed wherever it moves, takes precedence over regions
+    #[serde(skip)]
+    pub extra_streams: Vec<NamedStream>, // Published next to the main stream, receivers choose
+    pub layout: CompositeLayout,
+    pub cursor: CursorMode,
+}
+
+impl CaptureConfig {
+    // Masks in the coordinates of the given monitor, by its display_id
+    pub fn masks_for(&self, display: &str) -> Vec<CaptureArea> {
+        self.masks
+            .iter()
+            .filter(|mask| mask.display == display)
+            .map(|mask| mask.area)
+            .collect()
+    }
+
+    // Everything hidden on the given monitor, the masks and the redactions, with their style
+    pub fn hidden_areas(
+        &self,
+        display: &str,
+        redactions: &[Redaction],
+    ) -> Vec<(CaptureArea, MaskStyle)> {
+        let masks = self
+            .masks_for(display)
+            .into_iter()
+            .map(|area| (area, self.mask_style));
+        let redactions = redactions
+            .iter()
+            .filter(|redaction| redaction.display == display)
+            .map(|redaction| (redaction.area, redaction.style));
+        masks.chain(redactions).collect()
+    }
+}
+
+// Part of a monitor, composited with the other regions into the streamed frame
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct CaptureRegion {
+    pub monitor: usize,
+    pub area: CaptureArea, // Physical pixels, relative to the monitor
+}
+
+// Additional stream of a whole monitor or a part of it
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct NamedStream {
+    pub name: String,
+    pub monitor: usize,
+    pub area: Option<CaptureArea>, // The whole monitor if not set
+}
+
+// How multiple capture regions are arranged in the streamed frame
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+pub enum CompositeLayout {
+    #[default]
+    SideBySide,
+    Grid,
+    PictureInPicture, // The first region fills the frame, the others are small insets
+}
+
+impl std::fmt::Display for CompositeLayout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CompositeLayout::SideBySide => write!(f, "Side by side"),
+            CompositeLayout::Grid => write!(f, "Grid"),
+            CompositeLayout::PictureInPicture => write!(f, "Picture in picture"),
+        }
+    }
+}
+
+// How the mouse pointer of the caster reaches the receivers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+pub enum CursorMode {
+    Hidden,
+    Composited, // Drawn into the frames, recordings of the caster show it too
+    #[default]
+    Separate, // Sent apart from the frames, receivers draw it and may hide it
+}
+
+impl std::fmt::Display for CursorMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CursorMode::Hidden => write!(f, "Hidden"),
+            CursorMode::Composited => write!(f, "In the video"),
+            CursorMode::Separate => write!(f, "Separate"),
+        }
+    }
+}
+
+// Region of a monitor that never leaves this machine
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct PrivacyMask {
+    pub display: String, // Saved by display_id, monitor indexes change between sessions
+    pub area: CaptureArea, // Physical pixels, relative to the monitor
+}
+
+// Name and geometry of a monitor, the same in every session until it is moved or resized
+pub fn display_id(display: &DisplayInfo) -> String {
+    format!(
+        "{} {}x{}+{}+{}",
+        display.name, display.width, display.height, display.x, display.y
+    )
+}
+
+// Region hidden while the annotation overlay is open, each in its own style. Kept apart
+// from the config, the settings window writes back a copy of it taken earlier.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Redaction {
+    pub display: String,   // By display_id, like the masks
+    pub area: CaptureArea, // Physical pixels, relative to the monitor
+    pub style: MaskStyle,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+pub enum MaskStyle {
+    #[default]
+    Black,
+    Blur,
+    Pixelate,
+}
+
+impl std::fmt::Display for MaskStyle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MaskStyle::Black => write!(f, "Black"),
+            MaskStyle::Blur => write!(f, "Blur"),
+            MaskStyle::Pixelate => write!(f, "Pixelate"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PlaybackConfig {
+    pub target_delay_ms: u64, // Buffering added by the receiver to smooth out network jitter
+    pub low_latency: bool,    // Skip buffering and always show the newest frame
+    pub show_cursor: bool,    // Draw the pointer of the caster when it is sent apart
+    pub highlight_clicks: bool, // Ring around the pointer while the caster holds the button
+    pub show_annotations: bool, // Draw the shapes the caster sends as vector data
+}
+
+impl Default for PlaybackConfig {
+    fn default() -> Self {
+        Self {
+            target_delay_ms: 100,
+            low_latency: false,
+            show_cursor: true,
+            highlight_clicks: true,
+            show_annotations: true,
+        }
+    }
+}
+
+// What receivers see while the screen is hidden
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+pub enum PrivacyMode {
+    #[default]
+    Slate,    // Stop streaming and show the slate
+    Blur,     // Keep streaming, blurred beyond recognition
+    Pixelate, // Keep streaming, in large blocks
+}
+
+impl std::fmt::Display for PrivacyMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PrivacyMode::Slate => write!(f, "Slate"),
+            PrivacyMode::Blur => write!(f, "Blur"),
+            PrivacyMode::Pixelate => write!(f, "Pixelate"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SlateConfig {
+    pub mode: PrivacyMode,
+    pub message: String,
+    pub image_path: Option<PathBuf>, // PNG shown in the middle of the slate
+    pub countdown_minutes: u32,      // 0 disables the countdown
+    pub strength: u32,               // Blur radius or pixel block size
+}
+
+impl Default for SlateConfig {
+    fn default() -> Self {
+        Self {
+            mode: PrivacyMode::default(),
+            message: "Be right back".to_string(),
+            image_path: None,
+            countdown_minutes: 0,
+            strength: 24,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct LaserConfig {
+    pub color: [u8; 3],
+    pub allow_pointers: bool, // As caster, show the pointers of the receivers
+}
+
+impl Default for LaserConfig {
+    fn default() -> Self {
+        Self {
+            color: [255, 40, 40],
+            allow_pointers: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ProfileConfig {
+    pub name: String, // Shown to the others next to the laser pointer and the chat messages
+}
+
+impl Default for ProfileConfig {
+    fn default() -> Self {
+        let name = std::env::var("USER")
+            .or_else(|_| std::env::var("USERNAME"))
+            .unwrap_or_else(|_| "Viewer".to_string());
+        Self { name }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ChatConfig {
+    pub save_with_recordings: bool, // Write the chat next to the video when a recording stops
 }
@@ -14,6 +14,9 @@ pub enum HotkeyAction {
     ToggleHotkeyMenu,
     Connect,
     TogglePause,
+    ToggleStatistics,
+    LaserPointer, // Held down, not toggled
+    FocusChat,
 }
 
 impl HotkeyAction {
@@ -144,6 +147,33 @@ impl HotkeyManager {
             },
             HotkeyAction::Home,
         );
+        self.default_shortcuts.insert(
+            KeyCombination {
+                ctrl: false,
+                shift: false,
+                alt: false,
+                key: Key::I,
+            },
+            HotkeyAction::ToggleStatistics,
+        );
+        self.default_shortcuts.insert(
+            KeyCombination {
+                ctrl: false,
+                shift: false,
+                alt: false,
+                key: Key::L,
+            },
+            HotkeyAction::LaserPointer,
+        );
+        self.default_shortcuts.insert(
+            KeyCombination {
+                ctrl: false,
+                shift: false,
+                alt: true,
+                key: Key::C,
+            },
+            HotkeyAction::FocusChat,
+        );
 
         self.default_shortcuts.insert(
             KeyCombination {
@@ -231,6 +261,19 @@ impl HotkeyManager {
         None
     }
 
+    // Whether the shortcut of the action is being held down right now
+    pub fn is_held(&self, ctx: &egui::Context, action: &HotkeyAction) -> bool {
+        let Some((combination, _)) = self.shortcuts.iter().find(|(_, a)| *a == action) else {
+            return false;
+        };
+        ctx.input(|i| {
+            i.key_down(combination.key)
+                && i.modifiers.command == combination.ctrl
+                && i.modifiers.shift == combination.shift
+                && i.modifiers.alt == combination.alt
+        })
+    }
+
     pub fn register_shortcut(&mut self, combination: KeyCombination, action: HotkeyAction) {
         // Remove existing binding for this action
         self.shortcuts.retain(|_, a| a != &action);
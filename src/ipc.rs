@@ -0,0 +1,134 @@
+use crate::annotation::{Shape, Tool};
+use crate::common::CaptureArea;
+use crate::laser_pointer::OverlayPointer;
+use eframe::egui;
+use log::error;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+// Overlays are processes of this executable started by the main app. It writes commands
+// to their stdin and reads events from their stdout, one JSON message per line.
+
+// Sent by the main app to an overlay
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum OverlayCommand {
+    SetTool(Tool),                 // Annotation overlay
+    Clear,                         // Annotation overlay, it can still be undone there
+    Pointers(Vec<OverlayPointer>), // Laser pointer overlay, the ones to show
+    Close,
+}
+
+// Sent by an overlay to the main app
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum OverlayEvent {
+    Shapes(Vec<Shape>),      // Annotations, the whole list each time it changes
+    Area(CaptureArea),       // Selected on the selection overlay
+    Masks(Vec<CaptureArea>), // Privacy masks, all of them once confirmed
+    Cancelled,               // Closed by the user without an answer
+    Error(String),
+    Closed, // The overlay exited, added by the main app when its output ends
+}
+
+// Overlay process started by the main app, killed when dropped
+pub struct OverlayProcess {
+    child: Child,
+    stdin: ChildStdin,
+}
+
+impl OverlayProcess {
+    // Start the overlay `mode` with its arguments. Its events are passed to `on_event` from
+    // another thread, Closed being the last one.
+    pub fn spawn(
+        mode: &str,
+        args: &[String],
+        mut on_event: impl FnMut(OverlayEvent) + Send + 'static,
+    ) -> std::io::Result<Self> {
+        let mut child = Command::new(env::current_exe()?)
+            .arg(format!("--overlay:{}", mode))
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+            let _ = child.kill();
+            return Err(std::io::Error::other("Overlay started without stdio"));
+        };
+
+        let mode = mode.to_string();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                match serde_json::from_str(&line) {
+                    Ok(event) => on_event(event),
+                    Err(e) => error!("Invalid message from the {} overlay: {}", mode, e),
+                }
+            }
+            on_event(OverlayEvent::Closed);
+        });
+
+        Ok(Self { child, stdin })
+    }
+
+    // Returns false if the overlay is gone, e.g. closed by the window manager
+    pub fn send(&mut self, command: &OverlayCommand) -> bool {
+        let Ok(line) = serde_json::to_string(command) else {
+            return true;
+        };
+        writeln!(self.stdin, "{}", line)
+            .and_then(|_| self.stdin.flush())
+            .is_ok()
+    }
+}
+
+impl Drop for OverlayProcess {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+// Commands of the main app to the running overlay, read by another thread that repaints `ctx`
+// for each of them. Close is received as well when the main app goes away.
+pub fn commands(ctx: egui::Context) -> Receiver<OverlayCommand> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            match serde_json::from_str(&line) {
+                Ok(command) => {
+                    if sender.send(command).is_err() {
+                        return;
+                    }
+                    ctx.request_repaint();
+                }
+                Err(e) => error!("Invalid command from the main app: {}", e),
+            }
+        }
+        let _ = sender.send(OverlayCommand::Close);
+        ctx.request_repaint();
+    });
+    receiver
+}
+
+// Event of the running overlay to the main app
+pub fn send(event: &OverlayEvent) {
+    let line = match serde_json::to_string(event) {
+        Ok(line) => line,
+        Err(e) => {
+            error!("Failed to serialize an overlay event: {}", e);
+            return;
+        }
+    };
+    let mut stdout = std::io::stdout().lock();
+    if let Err(e) = writeln!(stdout, "{}", line).and_then(|_| stdout.flush()) {
+        error!("Failed to send an overlay event: {}", e);
+    }
+}
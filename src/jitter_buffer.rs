@@ -0,0 +1,123 @@
+use crate::screen_capture::CapturedFrame;
+
+use std::collections::{BTreeMap, VecDeque};
+use std::time::Duration;
+
+// Frames beyond this are dropped, oldest first, to bound memory and latency
+const MAX_BUFFERED_FRAMES: usize = 60;
+// Transit time samples used to estimate the fastest path from capture to decoded frame
+const TRANSIT_SAMPLES: usize = 64;
+
+// Playout buffer of the receiver: decoded frames are reordered by sequence number
+// and shown at a steady pace, target_delay after the fastest observed transit time.
+// All timestamps are microseconds, frames carry the caster clock and arrivals the
+// receiver clock: the unknown offset between the two cancels out in the transit time.
+#[derive(Debug)]
+pub struct JitterBuffer {
+    frames: BTreeMap<u64, CapturedFrame>,
+    immediate: Option<CapturedFrame>, // Frames generated without a capture timestamp, e.g. the slate
+    target_delay: Duration,
+    low_latency: bool, // Show the newest frame as soon as it is decoded
+    transit_samples: VecDeque<i64>,
+    last_presented: Option<u64>,
+    dropped: usize, // Skipped or evicted since the last take_dropped
+    late: usize,    // Arrived after a newer frame was shown, since the last take_late
+}
+
+impl Default for JitterBuffer {
+    fn default() -> Self {
+        Self::new(Duration::from_millis(100), false)
+    }
+}
+
+impl JitterBuffer {
+    pub fn new(target_delay: Duration, low_latency: bool) -> Self {
+        Self {
+            frames: BTreeMap::new(),
+            immediate: None,
+            target_delay,
+            low_latency,
+            transit_samples: VecDeque::with_capacity(TRANSIT_SAMPLES),
+            last_presented: None,
+            dropped: 0,
+            late: 0,
+        }
+    }
+
+    pub fn configure(&mut self, target_delay: Duration, low_latency: bool) {
+        self.target_delay = target_delay;
+        self.low_latency = low_latency;
+    }
+
+    pub fn push(&mut self, frame: CapturedFrame, arrival: u64) {
+        // Generated frames replace the stream, what is still buffered would be shown on top of them
+        if frame.capture_timestamp == 0 {
+            self.dropped += self.frames.len();
+            self.frames.clear();
+            self.immediate = Some(frame);
+            return;
+        }
+
+        // Showing it now would make the video go backwards
+        if self.last_presented.is_some_and(|last| frame.sequence <= last) {
+            self.late += 1;
+            return;
+        }
+
+        self.transit_samples
+            .push_back(arrival as i64 - frame.capture_timestamp as i64);
+        if self.transit_samples.len() > TRANSIT_SAMPLES {
+            self.transit_samples.pop_front();
+        }
+
+        self.frames.insert(frame.sequence, frame);
+        while self.frames.len() > MAX_BUFFERED_FRAMES {
+            self.frames.pop_first();
+            self.dropped += 1;
+        }
+    }
+
+    // Returns the frame to show at `now`, if any. Older frames whose time has passed are skipped.
+    pub fn pop_ready(&mut self, now: u64) -> Option<CapturedFrame> {
+        if let Some(frame) = self.immediate.take() {
+            return Some(frame);
+        }
+
+        let ready_sequence = if self.low_latency {
+            self.frames.keys().next_back().copied()
+        } else {
+            self.frames
+                .values()
+                .take_while(|frame| self.playout_time(frame).is_some_and(|at| at <= now))
+                .last()
+                .map(|frame| frame.sequence)
+        }?;
+
+        // Keep the frames after the ready one, everything before it is too old to be shown
+        let newer = self.frames.split_off(&(ready_sequence + 1));
+        let mut older = std::mem::replace(&mut self.frames, newer);
+        let frame = older.remove(&ready_sequence);
+        self.dropped += older.len();
+        self.last_presented = Some(ready_sequence);
+        frame
+    }
+
+    // Local time at which a frame should be shown
+    fn playout_time(&self, frame: &CapturedFrame) -> Option<u64> {
+        let min_transit = self.transit_samples.iter().min()?;
+        let at = frame.capture_timestamp as i64 + min_transit + self.target_delay.as_micros() as i64;
+        Some(at.max(0) as u64)
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn take_dropped(&mut self) -> usize {
+        std::mem::take(&mut self.dropped)
+    }
+
+    pub fn take_late(&mut self) -> usize {
+        std::mem::take(&mut self.late)
+    }
+}
@@ -0,0 +1,133 @@
+use crate::ipc::{OverlayCommand, OverlayProcess};
+use eframe::egui::{self, CentralPanel, Color32, FontId, Pos2, Rect, Stroke};
+use log::error;
+use serde::{Deserialize, Serialize};
+use std::sync::mpsc::Receiver;
+
+const POINTER_RADIUS: f32 = 7.0;
+
+// Pointer of a receiver as drawn by the overlay, a fraction of the captured area
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OverlayPointer {
+    pub x: f32,
+    pub y: f32,
+    pub color: [u8; 3],
+    pub name: String,
+}
+
+// Transparent overlay drawing the pointers of the receivers on the caster screen.
+// The caster sends the visible pointers each time they change, and closes the
+// overlay when it stops streaming or disables them.
+pub struct LaserPointerApp {
+    commands: Receiver<OverlayCommand>,
+    pointers: Vec<OverlayPointer>,
+    area: Rect, // Captured area, physical pixels relative to the overlay
+}
+
+impl LaserPointerApp {
+    pub fn new(commands: Receiver<OverlayCommand>, area: Rect) -> Self {
+        Self {
+            commands,
+            pointers: Vec::new(),
+            area,
+        }
+    }
+}
+
+impl eframe::App for LaserPointerApp {
+    fn clear_color(&self, _visuals: &egui::Visuals) -> [f32; 4] {
+        [0.0, 0.0, 0.0, 0.0]
+    }
+
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        while let Ok(command) = self.commands.try_recv() {
+            match command {
+                OverlayCommand::Pointers(pointers) => self.pointers = pointers,
+                OverlayCommand::Close => std::process::exit(0),
+                command => error!("Unexpected command for the laser pointers: {:?}", command),
+            }
+        }
+
+        let scale_factor = ctx.pixels_per_point();
+        let area = Rect::from_min_max(
+            (self.area.min.to_vec2() / scale_factor).to_pos2(),
+            (self.area.max.to_vec2() / scale_factor).to_pos2(),
+        );
+
+        CentralPanel::default()
+            .frame(egui::Frame::NONE)
+            .show(ctx, |ui| {
+                let painter = ui.painter();
+                for pointer in &self.pointers {
+                    let position = area.min + egui::vec2(pointer.x, pointer.y) * area.size();
+                    draw_pointer(painter, position, pointer.color, &pointer.name);
+                }
+            });
+    }
+}
+
+// A glowing dot with the name of the viewer next to it, shared with the receivers
+pub fn draw_pointer(painter: &egui::Painter, position: Pos2, color: [u8; 3], name: &str) {
+    let [r, g, b] = color;
+    let color = Color32::from_rgb(r, g, b);
+
+    painter.circle_filled(
+        position,
+        POINTER_RADIUS * 2.0,
+        Color32::from_rgba_unmultiplied(r, g, b, 60),
+    );
+    painter.circle(
+        position,
+        POINTER_RADIUS,
+        color,
+        Stroke::new(1.5, Color32::WHITE),
+    );
+
+    if name.is_empty() {
+        return;
+    }
+    let galley =
+        painter.layout_no_wrap(name.to_string(), FontId::proportional(13.0), Color32::WHITE);
+    let label = Rect::from_min_size(
+        position + egui::vec2(POINTER_RADIUS * 2.0, POINTER_RADIUS),
+        galley.size() + egui::vec2(8.0, 4.0),
+    );
+    painter.rect_filled(label, 3.0, color.gamma_multiply(0.85));
+    painter.galley(label.min + egui::vec2(4.0, 2.0), galley, Color32::WHITE);
+}
+
+// Overlay process started by the caster while receivers are pointing
+pub struct PointerOverlay {
+    process: OverlayProcess,
+    geometry: Vec<String>, // Arguments it was started with, it is restarted when they change
+    shown: Vec<OverlayPointer>,
+}
+
+impl PointerOverlay {
+    // The geometry is the one of the other overlays, followed by the captured area
+    pub fn spawn(geometry: Vec<String>) -> Option<Self> {
+        let process = OverlayProcess::spawn("pointers", &geometry, |_| {})
+            .map_err(|e| error!("Failed to spawn laser pointer overlay: {}", e))
+            .ok()?;
+
+        Some(Self {
+            process,
+            geometry,
+            shown: Vec::new(),
+        })
+    }
+
+    pub fn geometry(&self) -> &[String] {
+        &self.geometry
+    }
+
+    // Returns false if the overlay is gone, e.g. closed by the window manager
+    pub fn show(&mut self, pointers: Vec<OverlayPointer>) -> bool {
+        if pointers == self.shown {
+            return true;
+        }
+
+        self.shown = pointers.clone();
+        self.process.send(&OverlayCommand::Pointers(pointers))
+    }
+}
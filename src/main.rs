@@ -4,17 +4,26 @@ use std::env;
 mod annotation;
 mod app;
 mod area_selection;
+mod chat;
 mod common;
 mod config;
 mod hotkey;
+mod ipc;
+mod jitter_buffer;
+mod laser_pointer;
+mod mask_selection;
+mod protocol;
 mod receiver;
 mod screen_capture;
 mod sender;
+mod session;
+mod stats;
 mod video_recorder;
 
 use app::RustreamApp;
 use area_selection::AreaCaptureApp;
-use egui::{Pos2, Vec2, ViewportBuilder, X11WindowType};
+use mask_selection::MaskSelectionApp;
+use egui::{Pos2, Rect, Vec2, ViewportBuilder, WindowLevel, X11WindowType};
 
 use env_logger::Env;
 use log::{error, LevelFilter};
@@ -34,6 +43,8 @@ async fn main() {
 
     let args: Vec<String> = env::args().collect();
     let is_overlay = args.iter().any(|arg| arg.contains("overlay"));
+    // The overlay covers every monitor, maximizing would shrink it to a single one
+    let spans_desktop = args.iter().any(|arg| arg == "--span-desktop");
     let mode = args
         .iter()
         .find(|arg| arg.starts_with("--overlay:"))
@@ -89,7 +100,7 @@ async fn main() {
         viewport: ViewportBuilder {
             transparent: Some(true),
             fullscreen: Some(false),
-            maximized: Some(true),
+            maximized: Some(!spans_desktop),
             decorations: Some(false),
             position: Some(Pos2::new(window_x / scale_factor, window_y / scale_factor)),
             title: Some(APP_TITLE.to_string()),
@@ -111,11 +122,65 @@ async fn main() {
                 )
                 .expect("Failed to run Area Selection Overlay");
             }
+            "masks" => {
+                // Masks already defined on the monitor, so they can be edited
+                let masks = args
+                    .get(7)
+                    .and_then(|s| serde_json::from_str(s).ok())
+                    .unwrap_or_default();
+                eframe::run_native(
+                    "Privacy Masks",
+                    overlay_options,
+                    Box::new(|_cc| Ok(Box::new(MaskSelectionApp::new(masks)))),
+                )
+                .expect("Failed to run Privacy Masks Overlay");
+            }
+            "pointers" => {
+                // Captured area the pointers are relative to, the whole overlay if not given
+                let area: Vec<f32> = args
+                    .iter()
+                    .skip(7)
+                    .take(4)
+                    .filter_map(|s| s.parse().ok())
+                    .collect();
+                let area = match area[..] {
+                    [x, y, w, h] => Rect::from_min_size(Pos2::new(x, y), Vec2::new(w, h)),
+                    _ => Rect::from_min_size(
+                        Pos2::ZERO,
+                        Vec2::new(width * scale_factor, height * scale_factor),
+                    ),
+                };
+                // Shown on top of everything without ever getting in the way of the mouse
+                let options = NativeOptions {
+                    viewport: ViewportBuilder {
+                        mouse_passthrough: Some(true),
+                        window_level: Some(WindowLevel::AlwaysOnTop),
+                        taskbar: Some(false),
+                        ..overlay_options.viewport
+                    },
+                    ..overlay_options
+                };
+                eframe::run_native(
+                    "Laser Pointers",
+                    options,
+                    Box::new(move |cc| {
+                        Ok(Box::new(laser_pointer::LaserPointerApp::new(
+                            ipc::commands(cc.egui_ctx.clone()),
+                            area,
+                        )))
+                    }),
+                )
+                .expect("Failed to run Laser Pointer Overlay");
+            }
             "annotation" => {
                 eframe::run_native(
                     "Annotation",
                     overlay_options,
-                    Box::new(|_cc| Ok(Box::new(annotation::AnnotationApp::default()))),
+                    Box::new(|cc| {
+                        Ok(Box::new(annotation::AnnotationApp::new(ipc::commands(
+                            cc.egui_ctx.clone(),
+                        ))))
+                    }),
                 )
                 .expect("Failed to run Annotation Overlay");
             }
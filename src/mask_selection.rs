@@ -0,0 +1,174 @@
+use crate::common::CaptureArea;
+use crate::ipc::{self, OverlayEvent};
+use eframe::egui::{self, CentralPanel, Color32, Pos2, Rect, RichText};
+use egui::StrokeKind;
+
+// Overlay used to draw the privacy masks of a monitor, all coordinates are physical pixels
+#[derive(Default)]
+pub struct MaskSelectionApp {
+    masks: Vec<CaptureArea>,
+    drag_start: Option<Pos2>,
+    new_mask: Option<Rect>,
+}
+
+impl MaskSelectionApp {
+    pub fn new(masks: Vec<CaptureArea>) -> Self {
+        Self {
+            masks,
+            ..Default::default()
+        }
+    }
+
+    fn confirm(&self) -> ! {
+        ipc::send(&OverlayEvent::Masks(self.masks.clone()));
+        std::process::exit(0);
+    }
+
+    fn cancel(&self) -> ! {
+        ipc::send(&OverlayEvent::Cancelled);
+        std::process::exit(0);
+    }
+}
+
+impl eframe::App for MaskSelectionApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        // Handle Alt+F4, window close events and Esc key
+        if ctx.input(|i| i.viewport().close_requested() || i.key_pressed(egui::Key::Escape)) {
+            self.cancel();
+        }
+
+        if ctx.input(|i| i.key_pressed(egui::Key::Enter)) {
+            self.confirm();
+        }
+
+        let scale_factor = ctx.pixels_per_point();
+
+        // Get display info
+        let display_size = ctx.screen_rect().size();
+        let display_width = (display_size.x * scale_factor).round() as usize;
+        let display_height = (display_size.y * scale_factor).round() as usize;
+
+        // Add tutorial window
+        egui::Window::new("Tutorial")
+            .fixed_pos([10.0, 10.0])
+            .title_bar(false)
+            .frame(egui::Frame::new().fill(egui::Color32::from_rgba_unmultiplied(0, 0, 0, 180)))
+            .show(ctx, |ui| {
+                ui.colored_label(
+                    egui::Color32::WHITE,
+                    "How to hide areas of the screen:\n\
+                     1. Click and drag to add a mask\n\
+                     2. Right click a mask to remove it\n\
+                     3. Press Enter or click Done to save the masks\n\
+                     Press ESC at any time to exit without saving",
+                );
+                ui.add_space(5.0);
+                ui.horizontal(|ui| {
+                    if ui
+                        .button(RichText::new("Done").color(Color32::WHITE))
+                        .clicked()
+                    {
+                        self.confirm();
+                    }
+                    if ui
+                        .button(RichText::new("Clear all").color(Color32::WHITE))
+                        .clicked()
+                    {
+                        self.masks.clear();
+                    }
+                    if ui
+                        .button(RichText::new("Cancel").color(Color32::WHITE))
+                        .clicked()
+                    {
+                        self.cancel();
+                    }
+                });
+            });
+
+        CentralPanel::default()
+            .frame(egui::Frame::new().fill(Color32::TRANSPARENT))
+            .show(ctx, |ui| {
+                let response = ui.allocate_rect(
+                    ui.available_rect_before_wrap(),
+                    egui::Sense::click_and_drag(),
+                );
+
+                let to_screen = |area: &CaptureArea| {
+                    Rect::from_min_size(
+                        egui::pos2(area.x as f32, area.y as f32) / scale_factor,
+                        egui::vec2(area.width as f32, area.height as f32) / scale_factor,
+                    )
+                };
+
+                // Remove the topmost mask under the pointer
+                if response.secondary_clicked() {
+                    if let Some(pos) = response.interact_pointer_pos() {
+                        if let Some(index) = self
+                            .masks
+                            .iter()
+                            .rposition(|mask| to_screen(mask).contains(pos))
+                        {
+                            self.masks.remove(index);
+                        }
+                    }
+                }
+
+                // Handle drag start
+                if response.drag_started_by(egui::PointerButton::Primary) {
+                    if let Some(pos) = response.interact_pointer_pos() {
+                        self.drag_start = Some(egui::pos2(pos.x * scale_factor, pos.y * scale_factor));
+                    }
+                }
+
+                // Handle dragging
+                if let Some(start) = self.drag_start {
+                    if let Some(current) = response.interact_pointer_pos() {
+                        let screen_pos =
+                            egui::pos2(current.x * scale_factor, current.y * scale_factor);
+                        self.new_mask = Some(egui::Rect::from_two_pos(start, screen_pos));
+                    }
+                }
+
+                // Add the mask when the drag ends, ignoring accidental clicks
+                if response.drag_stopped() {
+                    if let Some(rect) = self.new_mask.take() {
+                        if rect.width() >= 4.0 && rect.height() >= 4.0 {
+                            self.masks.push(CaptureArea::from_rect_safe(
+                                rect,
+                                display_width,
+                                display_height,
+                            ));
+                        }
+                    }
+                    self.drag_start = None;
+                }
+
+                // Draw the masks as they will look in the stream
+                let painter = ui.painter();
+                for mask in &self.masks {
+                    let rect = to_screen(mask);
+                    painter.rect_filled(rect, 0.0, Color32::from_black_alpha(200));
+                    painter.rect_stroke(
+                        rect,
+                        0.0,
+                        egui::Stroke::new(2.0, Color32::from_rgba_unmultiplied(255, 0, 0, 160)),
+                        StrokeKind::Outside,
+                    );
+                }
+
+                if let Some(rect) = self.new_mask {
+                    let display_rect = egui::Rect::from_min_max(
+                        egui::pos2(rect.min.x / scale_factor, rect.min.y / scale_factor),
+                        egui::pos2(rect.max.x / scale_factor, rect.max.y / scale_factor),
+                    );
+                    painter.rect_filled(display_rect, 0.0, Color32::from_black_alpha(120));
+                    painter.rect_stroke(
+                        display_rect,
+                        0.0,
+                        egui::Stroke::new(3.0, Color32::from_rgba_unmultiplied(255, 0, 0, 128)),
+                        StrokeKind::Outside,
+                    );
+                }
+            });
+    }
+}
@@ -0,0 +1,462 @@
+use crate::annotation::Shape;
+
+use std::io;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+// Every message on the wire is a 4 byte ASCII tag, followed by the payload
+// length as a big endian u32 and then the payload itself.
+pub const TAG_FRAME: [u8; 4] = *b"FRME";
+pub const TAG_SLATE: [u8; 4] = *b"SLAT";
+pub const TAG_END: [u8; 4] = *b"END\0";
+pub const TAG_KEYFRAME_REQUEST: [u8; 4] = *b"KEYR";
+pub const TAG_PING: [u8; 4] = *b"PING";
+pub const TAG_PONG: [u8; 4] = *b"PONG";
+pub const TAG_PAUSE: [u8; 4] = *b"PAUS";
+pub const TAG_RESUME: [u8; 4] = *b"RESM";
+pub const TAG_STREAM_LIST: [u8; 4] = *b"STRM";
+pub const TAG_SUBSCRIBE: [u8; 4] = *b"SUBS";
+pub const TAG_CURSOR: [u8; 4] = *b"CURS";
+pub const TAG_CURSOR_SHAPE: [u8; 4] = *b"CSHP";
+pub const TAG_POINTER: [u8; 4] = *b"LASR";
+pub const TAG_POINTERS_ALLOWED: [u8; 4] = *b"LAOK";
+pub const TAG_CHAT: [u8; 4] = *b"CHAT";
+pub const TAG_ANNOTATION: [u8; 4] = *b"ANNO";
+pub const TAG_ANNOTATION_REMOVE: [u8; 4] = *b"ANRM";
+pub const TAG_ANNOTATIONS_CLEAR: [u8; 4] = *b"ANCL";
+
+// Stream sent to every receiver that didn't choose any other
+pub const MAIN_STREAM: u32 = 0;
+
+const HEADER_SIZE: usize = 8;
+const FRAME_HEADER_SIZE: usize = 21; // Stream id, sequence, capture timestamp and keyframe flag
+const SLATE_HEADER_SIZE: usize = 20; // Width, height, countdown and message length
+const CURSOR_SIZE: usize = 25; // Stream id, position, flags, shape serial and capture timestamp
+const CURSOR_SHAPE_HEADER_SIZE: usize = 20; // Serial, size and hotspot
+const POINTER_HEADER_SIZE: usize = 20; // Viewer and stream ids, position, visibility and color
+const CHAT_HEADER_SIZE: usize = 12; // Timestamp and name length
+const ANNOTATION_HEADER_SIZE: usize = 8; // Id and pixels per point
+// Refuse to allocate absurd payloads if the stream gets out of sync
+const MAX_PAYLOAD_SIZE: usize = 64 * 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FramePacket {
+    pub stream_id: u32,         // Stream the frame belongs to, see StreamInfo
+    pub sequence: u64,          // Sequence number assigned at capture time, per stream
+    pub capture_timestamp: u64, // Microseconds since UNIX epoch, caster clock
+    pub is_keyframe: bool,      // An IDR frame, decodable without any previous frame
+    pub data: Vec<u8>,
+}
+
+// A named stream published by the caster, e.g. one per monitor
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamInfo {
+    pub id: u32,
+    pub name: String,
+}
+
+// Pointer of the caster, sent next to the frames and drawn by the receivers
+#[derive(Debug, Clone, PartialEq)]
+pub struct CursorPacket {
+    pub stream_id: u32,
+    pub x: i32,                 // Hotspot position in the pixels of the stream
+    pub y: i32,
+    pub visible: bool,          // False while the pointer is outside of the stream
+    pub pressed: bool,          // Primary button held down
+    pub shape_serial: u32,      // See CursorShapePacket
+    pub capture_timestamp: u64, // Microseconds since UNIX epoch, caster clock
+}
+
+// Image of the pointer, sent on connection and whenever it changes
+#[derive(Debug, Clone, PartialEq)]
+pub struct CursorShapePacket {
+    pub serial: u32,
+    pub width: u32,
+    pub height: u32,
+    pub hot_x: u32, // Hotspot, relative to the top left corner of the image
+    pub hot_y: u32,
+    pub rgba: Vec<u8>,
+}
+
+// Laser pointer of a receiver, shown by the caster and relayed to the other receivers
+#[derive(Debug, Clone, PartialEq)]
+pub struct PointerPacket {
+    pub viewer_id: u32, // Assigned by the caster, receivers send 0
+    pub stream_id: u32,
+    pub x: f32,         // Fraction of the width of the stream, from the left
+    pub y: f32,         // Fraction of the height of the stream, from the top
+    pub visible: bool,  // False once the viewer releases the key
+    pub color: [u8; 3],
+    pub name: String,   // Name of the viewer, shown next to the pointer
+}
+
+// Chat message, the caster relays the ones of a receiver to the others
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChatPacket {
+    pub timestamp: u64, // Microseconds since UNIX epoch, clock of the author
+    pub name: String,   // Display name of the author
+    pub text: String,
+}
+
+// Shape drawn by the caster over the main stream, sent as vector data so receivers draw it crisp
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnnotationPacket {
+    pub id: u32,               // Assigned by the caster, replaces the shape with the same id
+    pub pixels_per_point: f32, // Of the caster screen, strokes and text scale with it
+    pub shape: Shape,          // In pixels of the stream, JSON encoded
+}
+
+// Shown by receivers instead of the stream while the caster hides the screen
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlatePacket {
+    pub width: u32, // Resolution of the stream, the slate keeps its aspect ratio
+    pub height: u32,
+    pub countdown_until: u64, // Microseconds since UNIX epoch, caster clock, 0 without countdown
+    pub message: String,
+    pub image: Vec<u8>, // PNG encoded, empty without image
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    // Caster -> Receiver
+    Frame(FramePacket),
+    Slate(SlatePacket),
+    EndOfStream,
+    // Streams the receiver can subscribe to, sent on connection and whenever they change
+    StreamList(Vec<StreamInfo>),
+    Cursor(CursorPacket),
+    CursorShape(CursorShapePacket),
+    // Whether the caster shows laser pointers, sent on connection and whenever it changes
+    PointersAllowed(bool),
+    Annotation(AnnotationPacket),
+    RemoveAnnotation(u32),
+    ClearAnnotations,
+    // The caster paused the transmission, receivers keep showing the last frame
+    Paused,
+    Resumed,
+    // Answer to a ping, all timestamps are in microseconds since UNIX epoch
+    Pong {
+        ping_sent: u64,     // Receiver clock, copied from the ping
+        ping_received: u64, // Caster clock
+        pong_sent: u64,     // Caster clock
+    },
+    // Receiver -> Caster
+    KeyframeRequest,
+    // Streams the receiver wants to get frames of
+    Subscribe(Vec<u32>),
+    Ping {
+        sent: u64, // Receiver clock
+    },
+    // Both ways, the caster relays the pointer of a receiver to the others
+    Pointer(PointerPacket),
+    Chat(ChatPacket),
+}
+
+impl Message {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let (tag, payload) = match self {
+            Message::Frame(frame) => {
+                let mut payload = Vec::with_capacity(FRAME_HEADER_SIZE + frame.data.len());
+                payload.extend_from_slice(&frame.stream_id.to_be_bytes());
+                payload.extend_from_slice(&frame.sequence.to_be_bytes());
+                payload.extend_from_slice(&frame.capture_timestamp.to_be_bytes());
+                payload.push(frame.is_keyframe as u8);
+                payload.extend_from_slice(&frame.data);
+                (TAG_FRAME, payload)
+            }
+            Message::Slate(slate) => {
+                let mut payload =
+                    Vec::with_capacity(SLATE_HEADER_SIZE + slate.message.len() + slate.image.len());
+                payload.extend_from_slice(&slate.width.to_be_bytes());
+                payload.extend_from_slice(&slate.height.to_be_bytes());
+                payload.extend_from_slice(&slate.countdown_until.to_be_bytes());
+                payload.extend_from_slice(&(slate.message.len() as u32).to_be_bytes());
+                payload.extend_from_slice(slate.message.as_bytes());
+                payload.extend_from_slice(&slate.image);
+                (TAG_SLATE, payload)
+            }
+            Message::EndOfStream => (TAG_END, Vec::new()),
+            Message::StreamList(streams) => {
+                let mut payload = Vec::new();
+                payload.extend_from_slice(&(streams.len() as u32).to_be_bytes());
+                for stream in streams {
+                    payload.extend_from_slice(&stream.id.to_be_bytes());
+                    payload.extend_from_slice(&(stream.name.len() as u32).to_be_bytes());
+                    payload.extend_from_slice(stream.name.as_bytes());
+                }
+                (TAG_STREAM_LIST, payload)
+            }
+            Message::Cursor(cursor) => {
+                let mut payload = Vec::with_capacity(CURSOR_SIZE);
+                payload.extend_from_slice(&cursor.stream_id.to_be_bytes());
+                payload.extend_from_slice(&cursor.x.to_be_bytes());
+                payload.extend_from_slice(&cursor.y.to_be_bytes());
+                payload.push(cursor.visible as u8 | (cursor.pressed as u8) << 1);
+                payload.extend_from_slice(&cursor.shape_serial.to_be_bytes());
+                payload.extend_from_slice(&cursor.capture_timestamp.to_be_bytes());
+                (TAG_CURSOR, payload)
+            }
+            Message::CursorShape(shape) => {
+                let mut payload = Vec::with_capacity(CURSOR_SHAPE_HEADER_SIZE + shape.rgba.len());
+                payload.extend_from_slice(&shape.serial.to_be_bytes());
+                payload.extend_from_slice(&shape.width.to_be_bytes());
+                payload.extend_from_slice(&shape.height.to_be_bytes());
+                payload.extend_from_slice(&shape.hot_x.to_be_bytes());
+                payload.extend_from_slice(&shape.hot_y.to_be_bytes());
+                payload.extend_from_slice(&shape.rgba);
+                (TAG_CURSOR_SHAPE, payload)
+            }
+            Message::PointersAllowed(allowed) => (TAG_POINTERS_ALLOWED, vec![*allowed as u8]),
+            Message::Annotation(annotation) => {
+                let shape = serde_json::to_vec(&annotation.shape).expect("Shapes always serialize");
+                let mut payload = Vec::with_capacity(ANNOTATION_HEADER_SIZE + shape.len());
+                payload.extend_from_slice(&annotation.id.to_be_bytes());
+                payload.extend_from_slice(&annotation.pixels_per_point.to_be_bytes());
+                payload.extend_from_slice(&shape);
+                (TAG_ANNOTATION, payload)
+            }
+            Message::RemoveAnnotation(id) => (TAG_ANNOTATION_REMOVE, id.to_be_bytes().to_vec()),
+            Message::ClearAnnotations => (TAG_ANNOTATIONS_CLEAR, Vec::new()),
+            Message::Paused => (TAG_PAUSE, Vec::new()),
+            Message::Resumed => (TAG_RESUME, Vec::new()),
+            Message::Pong {
+                ping_sent,
+                ping_received,
+                pong_sent,
+            } => {
+                let mut payload = Vec::with_capacity(24);
+                payload.extend_from_slice(&ping_sent.to_be_bytes());
+                payload.extend_from_slice(&ping_received.to_be_bytes());
+                payload.extend_from_slice(&pong_sent.to_be_bytes());
+                (TAG_PONG, payload)
+            }
+            Message::KeyframeRequest => (TAG_KEYFRAME_REQUEST, Vec::new()),
+            Message::Subscribe(streams) => {
+                let mut payload = Vec::with_capacity(4 + streams.len() * 4);
+                payload.extend_from_slice(&(streams.len() as u32).to_be_bytes());
+                for id in streams {
+                    payload.extend_from_slice(&id.to_be_bytes());
+                }
+                (TAG_SUBSCRIBE, payload)
+            }
+            Message::Ping { sent } => (TAG_PING, sent.to_be_bytes().to_vec()),
+            Message::Pointer(pointer) => {
+                let mut payload = Vec::with_capacity(POINTER_HEADER_SIZE + pointer.name.len());
+                payload.extend_from_slice(&pointer.viewer_id.to_be_bytes());
+                payload.extend_from_slice(&pointer.stream_id.to_be_bytes());
+                payload.extend_from_slice(&pointer.x.to_be_bytes());
+                payload.extend_from_slice(&pointer.y.to_be_bytes());
+                payload.push(pointer.visible as u8);
+                payload.extend_from_slice(&pointer.color);
+                payload.extend_from_slice(pointer.name.as_bytes());
+                (TAG_POINTER, payload)
+            }
+            Message::Chat(chat) => {
+                let mut payload =
+                    Vec::with_capacity(CHAT_HEADER_SIZE + chat.name.len() + chat.text.len());
+                payload.extend_from_slice(&chat.timestamp.to_be_bytes());
+                payload.extend_from_slice(&(chat.name.len() as u32).to_be_bytes());
+                payload.extend_from_slice(chat.name.as_bytes());
+                payload.extend_from_slice(chat.text.as_bytes());
+                (TAG_CHAT, payload)
+            }
+        };
+
+        let mut buf = Vec::with_capacity(HEADER_SIZE + payload.len());
+        buf.extend_from_slice(&tag);
+        buf.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        buf.extend_from_slice(&payload);
+        buf
+    }
+
+    fn from_parts(tag: [u8; 4], payload: Vec<u8>) -> io::Result<Self> {
+        match tag {
+            TAG_FRAME => {
+                if payload.len() < FRAME_HEADER_SIZE {
+                    return Err(invalid_data("Frame packet too short"));
+                }
+                Ok(Message::Frame(FramePacket {
+                    stream_id: read_u32(&payload, 0)?,
+                    sequence: read_u64(&payload, 4)?,
+                    capture_timestamp: read_u64(&payload, 12)?,
+                    is_keyframe: payload[20] != 0,
+                    data: payload[FRAME_HEADER_SIZE..].to_vec(),
+                }))
+            }
+            TAG_SLATE => {
+                let message_len = read_u32(&payload, 16)? as usize;
+                let message = payload
+                    .get(SLATE_HEADER_SIZE..SLATE_HEADER_SIZE + message_len)
+                    .ok_or_else(|| invalid_data("Slate message too short"))?;
+                Ok(Message::Slate(SlatePacket {
+                    width: read_u32(&payload, 0)?,
+                    height: read_u32(&payload, 4)?,
+                    countdown_until: read_u64(&payload, 8)?,
+                    message: String::from_utf8_lossy(message).into_owned(),
+                    image: payload[SLATE_HEADER_SIZE + message_len..].to_vec(),
+                }))
+            }
+            TAG_END => Ok(Message::EndOfStream),
+            TAG_STREAM_LIST => {
+                let count = read_u32(&payload, 0)?;
+                let mut offset = 4;
+                let mut streams = Vec::new();
+                for _ in 0..count {
+                    let id = read_u32(&payload, offset)?;
+                    let name_len = read_u32(&payload, offset + 4)? as usize;
+                    let name = payload
+                        .get(offset + 8..offset + 8 + name_len)
+                        .ok_or_else(|| invalid_data("Stream name too short"))?;
+                    streams.push(StreamInfo {
+                        id,
+                        name: String::from_utf8_lossy(name).into_owned(),
+                    });
+                    offset += 8 + name_len;
+                }
+                Ok(Message::StreamList(streams))
+            }
+            TAG_CURSOR => {
+                if payload.len() < CURSOR_SIZE {
+                    return Err(invalid_data("Cursor packet too short"));
+                }
+                Ok(Message::Cursor(CursorPacket {
+                    stream_id: read_u32(&payload, 0)?,
+                    x: read_u32(&payload, 4)? as i32,
+                    y: read_u32(&payload, 8)? as i32,
+                    visible: payload[12] & 1 != 0,
+                    pressed: payload[12] & 2 != 0,
+                    shape_serial: read_u32(&payload, 13)?,
+                    capture_timestamp: read_u64(&payload, 17)?,
+                }))
+            }
+            TAG_CURSOR_SHAPE => {
+                let (width, height) = (read_u32(&payload, 4)?, read_u32(&payload, 8)?);
+                let rgba = payload
+                    .get(CURSOR_SHAPE_HEADER_SIZE..)
+                    .filter(|rgba| rgba.len() == width as usize * height as usize * 4)
+                    .ok_or_else(|| invalid_data("Cursor image doesn't match its size"))?;
+                Ok(Message::CursorShape(CursorShapePacket {
+                    serial: read_u32(&payload, 0)?,
+                    width,
+                    height,
+                    hot_x: read_u32(&payload, 12)?,
+                    hot_y: read_u32(&payload, 16)?,
+                    rgba: rgba.to_vec(),
+                }))
+            }
+            TAG_POINTERS_ALLOWED => {
+                let allowed = payload.first().ok_or_else(|| invalid_data("Empty message"))?;
+                Ok(Message::PointersAllowed(*allowed != 0))
+            }
+            TAG_ANNOTATION => {
+                let shape = payload
+                    .get(ANNOTATION_HEADER_SIZE..)
+                    .ok_or_else(|| invalid_data("Annotation packet too short"))?;
+                Ok(Message::Annotation(AnnotationPacket {
+                    id: read_u32(&payload, 0)?,
+                    pixels_per_point: f32::from_bits(read_u32(&payload, 4)?),
+                    shape: serde_json::from_slice(shape)
+                        .map_err(|e| invalid_data(&format!("Invalid annotation: {}", e)))?,
+                }))
+            }
+            TAG_ANNOTATION_REMOVE => Ok(Message::RemoveAnnotation(read_u32(&payload, 0)?)),
+            TAG_ANNOTATIONS_CLEAR => Ok(Message::ClearAnnotations),
+            TAG_PAUSE => Ok(Message::Paused),
+            TAG_RESUME => Ok(Message::Resumed),
+            TAG_PONG => Ok(Message::Pong {
+                ping_sent: read_u64(&payload, 0)?,
+                ping_received: read_u64(&payload, 8)?,
+                pong_sent: read_u64(&payload, 16)?,
+            }),
+            TAG_KEYFRAME_REQUEST => Ok(Message::KeyframeRequest),
+            TAG_SUBSCRIBE => {
+                let count = read_u32(&payload, 0)? as usize;
+                let streams = (0..count)
+                    .map(|i| read_u32(&payload, 4 + i * 4))
+                    .collect::<io::Result<_>>()?;
+                Ok(Message::Subscribe(streams))
+            }
+            TAG_PING => Ok(Message::Ping {
+                sent: read_u64(&payload, 0)?,
+            }),
+            TAG_POINTER => {
+                if payload.len() < POINTER_HEADER_SIZE {
+                    return Err(invalid_data("Pointer packet too short"));
+                }
+                Ok(Message::Pointer(PointerPacket {
+                    viewer_id: read_u32(&payload, 0)?,
+                    stream_id: read_u32(&payload, 4)?,
+                    x: f32::from_bits(read_u32(&payload, 8)?),
+                    y: f32::from_bits(read_u32(&payload, 12)?),
+                    visible: payload[16] != 0,
+                    color: [payload[17], payload[18], payload[19]],
+                    name: String::from_utf8_lossy(&payload[POINTER_HEADER_SIZE..]).into_owned(),
+                }))
+            }
+            TAG_CHAT => {
+                let name_len = read_u32(&payload, 8)? as usize;
+                let name = payload
+                    .get(CHAT_HEADER_SIZE..CHAT_HEADER_SIZE + name_len)
+                    .ok_or_else(|| invalid_data("Chat name too short"))?;
+                Ok(Message::Chat(ChatPacket {
+                    timestamp: read_u64(&payload, 0)?,
+                    name: String::from_utf8_lossy(name).into_owned(),
+                    text: String::from_utf8_lossy(&payload[CHAT_HEADER_SIZE + name_len..])
+                        .into_owned(),
+                }))
+            }
+            _ => Err(invalid_data(&format!(
+                "Unknown message tag: {:?}",
+                String::from_utf8_lossy(&tag)
+            ))),
+        }
+    }
+}
+
+// Read the next message from the stream, returns None if the peer closed the connection
+pub async fn read_message<R>(reader: &mut R) -> io::Result<Option<Message>>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut header = [0u8; HEADER_SIZE];
+    match reader.read_exact(&mut header).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+
+    let tag: [u8; 4] = header[0..4].try_into().unwrap();
+    let payload_size = u32::from_be_bytes(header[4..8].try_into().unwrap()) as usize;
+    if payload_size > MAX_PAYLOAD_SIZE {
+        return Err(invalid_data(&format!(
+            "Payload of {} bytes exceeds the maximum size",
+            payload_size
+        )));
+    }
+
+    let mut payload = vec![0; payload_size];
+    match reader.read_exact(&mut payload).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+
+    Message::from_parts(tag, payload).map(Some)
+}
+
+fn read_u32(payload: &[u8], offset: usize) -> io::Result<u32> {
+    payload
+        .get(offset..offset + 4)
+        .map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()))
+        .ok_or_else(|| invalid_data("Message payload too short"))
+}
+
+fn read_u64(payload: &[u8], offset: usize) -> io::Result<u64> {
+    payload
+        .get(offset..offset + 8)
+        .map(|bytes| u64::from_be_bytes(bytes.try_into().unwrap()))
+        .ok_or_else(|| invalid_data("Message payload too short"))
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
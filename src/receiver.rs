@@ -1,18 +1,29 @@
+use crate::chat::{ChatHistory, ChatMessage};
+use crate::common::unix_timestamp_micros;
+use crate::jitter_buffer::JitterBuffer;
+use crate::protocol::{
+    read_message, AnnotationPacket, CursorPacket, CursorShapePacket, FramePacket, Message,
+    PointerPacket, SlatePacket, StreamInfo, MAIN_STREAM,
+};
 use crate::screen_capture::{decode_from_h265_to_rgba, CapturedFrame};
+use crate::sender::KEYFRAME_REQUEST_INTERVAL;
+use crate::stats::StreamStats;
 
-use std::collections::VecDeque;
+use image::imageops::{self, FilterType};
+use image::{ImageFormat, RgbaImage};
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::net::SocketAddr;
-use std::str::from_utf8;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use std::time::Instant;
-use tokio::io::AsyncReadExt;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+use tokio::net::tcp::OwnedReadHalf;
 use tokio::net::TcpStream;
 use tokio::sync::{mpsc, Mutex, Notify};
 
 pub struct Receiver {
-    socket: TcpStream,
-    pub started_receiving: bool,
+    socket: OwnedReadHalf,
+    control_tx: mpsc::Sender<Message>, // Messages sent back to the caster
 }
 
 impl Receiver {
@@ -22,9 +33,22 @@ impl Receiver {
             Ok(stream) => {
                 println!("Connected to sender at {}", caster);
 
+                let (read_half, mut write_half) = stream.into_split();
+                let (control_tx, mut control_rx) = mpsc::channel::<Message>(16);
+
+                // Send the control messages to the caster, the task ends when the receiver is dropped
+                tokio::spawn(async move {
+                    while let Some(message) = control_rx.recv().await {
+                        if let Err(e) = write_half.write_all(&message.to_bytes()).await {
+                            eprintln!("Error sending control message to sender: {}", e);
+                            break;
+                        }
+                    }
+                });
+
                 Ok(Self {
-                    socket: stream,
-                    started_receiving: false,
+                    socket: read_half,
+                    control_tx,
                 })
             }
             Err(e) => {
@@ -34,71 +58,41 @@ impl Receiver {
         }
     }
 
+    pub fn control_sender(&self) -> mpsc::Sender<Message> {
+        self.control_tx.clone()
+    }
+
     pub async fn recv_data(
         &mut self,
-        tx: mpsc::Sender<Vec<u8>>,
+        tx: mpsc::Sender<Message>,
         stop_notify: Arc<Notify>,
         stream_ended: Arc<AtomicBool>,
     ) -> Result<(), std::io::Error> {
         loop {
-            let mut buf = vec![0; 4]; // Buffer to read frame size
-
             tokio::select! {
                 _ = stop_notify.notified() => {
                     println!("Received stop signal, exiting recv_data");
                     break; // exit when `notify_waiters()` is called
                 }
-                result = self.socket.read_exact(&mut buf) => {  //read frame size or end message
+                result = read_message(&mut self.socket) => {
                     match result {
-                        Ok(0) => {
+                        Ok(None) => {
                             println!("Connection closed by sender");
                             stream_ended.store(true, Ordering::SeqCst);
                             break;
                         }
-                        Ok(_) => {
-                            // if received END message, return earlier
-                            if let Ok(message) = from_utf8(&buf) {
-                                if message.trim_matches('\0') == "END" {
-                                    println!("Received END message");
-                                    stream_ended.store(true, Ordering::SeqCst);
-                                    break;
-                                }
-                                else if message.trim_matches('\0') == "BLNK" {
-                                    println!("Received BLNK message");
-                                    if let Err(e) = tx.send(message.as_bytes().to_vec()).await {
-                                        eprintln!("Error sending encoded frame to start_receiving: {}", e);
-                                    }
-                                    continue;
-                                }
-                            }
-
-                            let frame_size = u32::from_ne_bytes(buf.try_into().unwrap());
-                            let mut frame = vec![0; frame_size as usize];
-                            println!("Frame size: {:?}", frame_size);
-
-                            // Read the complete frame
-                            match self.socket.read_exact(&mut frame).await {
-                                Ok(0) => {
-                                    println!("Connection closed by sender");
-                                    stream_ended.store(true, Ordering::SeqCst);
-                                    break;
-                                }
-                                Ok(_) => {
-                                    println!("Frame received");
-                                    if let Err(e) = tx.send(frame).await {
-                                        eprintln!("Error sending encoded frame to start_receiving: {}", e);
-                                    }
-
-                                }
-                                Err(e) => {
-                                    eprintln!("Error receiving frame: {}", e);
-                                    return Err(e);
-                                }
+                        Ok(Some(Message::EndOfStream)) => {
+                            println!("Received END message");
+                            stream_ended.store(true, Ordering::SeqCst);
+                            break;
+                        }
+                        Ok(Some(message)) => {
+                            if let Err(e) = tx.send(message).await {
+                                eprintln!("Error sending message to start_receiving: {}", e);
                             }
-
                         }
                         Err(e) => {
-                            eprintln!("Error receiving frame size: {}", e);
+                            eprintln!("Error receiving message: {}", e);
                             return Err(e);
                         }
                     }
@@ -109,32 +103,269 @@ impl Receiver {
     }
 }
 
-async fn process_frame(frames_vec: Arc<std::sync::Mutex<VecDeque<CapturedFrame>>>, frame: Vec<u8>) {
+// Tracks whether the decoder lost its reference frame and asks the caster for a new keyframe
+pub struct KeyframeRecovery {
+    control_tx: mpsc::Sender<Message>,
+    awaiting_keyframe: AtomicBool,
+    last_request: std::sync::Mutex<Option<Instant>>,
+}
+
+impl KeyframeRecovery {
+    pub fn new(control_tx: mpsc::Sender<Message>) -> Self {
+        Self {
+            control_tx,
+            // Joining mid-stream, nothing can be decoded before the first keyframe
+            awaiting_keyframe: AtomicBool::new(true),
+            last_request: std::sync::Mutex::new(None),
+        }
+    }
+
+    // Returns true if the frame can be handed to the decoder
+    pub fn accept(&self, frame: &FramePacket) -> bool {
+        if frame.is_keyframe {
+            self.awaiting_keyframe.store(false, Ordering::SeqCst);
+            return true;
+        }
+
+        if self.awaiting_keyframe.load(Ordering::SeqCst) {
+            println!("Dropping frame {}: waiting for a keyframe", frame.sequence);
+            self.request_keyframe();
+            return false;
+        }
+        true
+    }
+
+    // Called when a frame fails to decode, following frames would be corrupted as well
+    pub fn decode_failed(&self) {
+        self.awaiting_keyframe.store(true, Ordering::SeqCst);
+        self.request_keyframe();
+    }
+
+    fn request_keyframe(&self) {
+        let mut last_request = self.last_request.lock().unwrap();
+        if last_request.is_some_and(|last| last.elapsed() < KEYFRAME_REQUEST_INTERVAL) {
+            return;
+        }
+        *last_request = Some(Instant::now());
+
+        println!("Requesting a keyframe");
+        if let Err(e) = self.control_tx.try_send(Message::KeyframeRequest) {
+            eprintln!("Error requesting keyframe: {}", e);
+        }
+    }
+}
+
+// Playout buffers of the subscribed streams, by stream id
+pub type StreamBuffers = HashMap<u32, JitterBuffer>;
+
+// Pointer samples kept for each stream, enough to cover the playout delay
+const CURSOR_SAMPLES: usize = 32;
+
+// Pointer of the caster as received, drawn by the UI over the frames
+#[derive(Default)]
+pub struct RemoteCursor {
+    pub shape: Option<CursorShapePacket>,
+    samples: HashMap<u32, VecDeque<CursorPacket>>, // By stream, oldest first
+}
+
+// Where the pointer is at a given time, in the pixels of the stream
+#[derive(Debug, Clone, Copy)]
+pub struct CursorPosition {
+    pub x: f32,
+    pub y: f32,
+    pub pressed: bool,
+}
+
+impl RemoteCursor {
+    fn push(&mut self, packet: CursorPacket) {
+        // Sent in capture order, the oldest samples are behind the frame shown
+        let samples = self.samples.entry(packet.stream_id).or_default();
+        samples.push_back(packet);
+        if samples.len() > CURSOR_SAMPLES {
+            samples.pop_front();
+        }
+    }
+
+    // Position at the given caster time, interpolated between the samples around it
+    pub fn position_at(&self, stream_id: u32, capture_timestamp: u64) -> Option<CursorPosition> {
+        let samples = self.samples.get(&stream_id)?;
+        let next = samples
+            .iter()
+            .position(|sample| sample.capture_timestamp > capture_timestamp);
+        let (from, to) = match next {
+            Some(0) => (samples.front()?, None),
+            Some(next) => (&samples[next - 1], Some(&samples[next])),
+            None => (samples.back()?, None),
+        };
+        if !from.visible {
+            return None;
+        }
+
+        let position = CursorPosition {
+            x: from.x as f32,
+            y: from.y as f32,
+            pressed: from.pressed,
+        };
+        match to.filter(|to| to.visible) {
+            Some(to) => {
+                let span = (to.capture_timestamp - from.capture_timestamp).max(1) as f32;
+                let t = (capture_timestamp.saturating_sub(from.capture_timestamp)) as f32 / span;
+                Some(CursorPosition {
+                    x: position.x + (to.x - from.x) as f32 * t,
+                    y: position.y + (to.y - from.y) as f32 * t,
+                    ..position
+                })
+            }
+            None => Some(position),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+}
+
+// Laser pointers of the other receivers, relayed by the caster
+#[derive(Default)]
+pub struct RemotePointers {
+    pub allowed: bool, // The caster shows the pointers, ours included
+    pub pointers: HashMap<u32, PointerPacket>, // Visible pointers, by viewer
+}
+
+// Interval between two clock synchronization pings
+const PING_INTERVAL: Duration = Duration::from_secs(1);
+
+async fn process_frame(
+    frames_vec: Arc<std::sync::Mutex<StreamBuffers>>,
+    packet: FramePacket,
+    recovery: Arc<KeyframeRecovery>,
+    stats: Arc<std::sync::Mutex<StreamStats>>,
+) {
     let start = Instant::now();
-    let decoded_frame = decode_from_h265_to_rgba(frame);
+    let decoded_frame = decode_from_h265_to_rgba(packet.data);
     let decode_time = start.elapsed();
     println!("Decoding time: {:?}", decode_time);
     match decoded_frame {
-        Ok(frame) => {
+        Ok(mut frame) => {
+            stats.lock().unwrap().record_decode_time(decode_time);
+            frame.sequence = packet.sequence;
+            frame.capture_timestamp = packet.capture_timestamp;
+
             let mut frames = frames_vec.lock().unwrap();
-            frames.push_back(frame);
+            frames
+                .entry(packet.stream_id)
+                .or_default()
+                .push(frame, unix_timestamp_micros());
         }
         Err(e) => {
-            eprintln!("Error decoding frame: {}", e);
+            eprintln!("Error decoding frame {}: {}", packet.sequence, e);
+            stats.lock().unwrap().record_dropped(1);
+            recovery.decode_failed();
         }
     };
 }
 
+// Background of the slate, the image chosen by the caster is centered on it
+const SLATE_BACKGROUND: [u8; 4] = [24, 24, 28, 255];
+
+// Render the slate at the resolution of the stream, the text is drawn by the UI on top
+fn render_slate(slate: &SlatePacket) -> CapturedFrame {
+    let mut canvas = RgbaImage::from_pixel(
+        slate.width.max(1),
+        slate.height.max(1),
+        image::Rgba(SLATE_BACKGROUND),
+    );
+
+    if !slate.image.is_empty() {
+        match image::load_from_memory_with_format(&slate.image, ImageFormat::Png) {
+            Ok(image) => {
+                // Leave room for the message below the image
+                let image = image
+                    .resize(canvas.width() / 2, canvas.height() / 2, FilterType::Triangle)
+                    .to_rgba8();
+                let x = (canvas.width() - image.width()) / 2;
+                let y = (canvas.height() - image.height()) / 3;
+                imageops::overlay(&mut canvas, &image, x as i64, y as i64);
+            }
+            Err(e) => eprintln!("Error decoding slate image: {}", e),
+        }
+    }
+
+    CapturedFrame::from_rgba_vec(
+        canvas.into_raw(),
+        slate.width.max(1) as usize,
+        slate.height.max(1) as usize,
+    )
+}
+
+// Periodically ping the caster to measure the round trip time and the clock offset
+async fn ping_caster(control_tx: mpsc::Sender<Message>, stop_notify: Arc<Notify>) {
+    let mut interval = tokio::time::interval(PING_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = stop_notify.notified() => break,
+            _ = interval.tick() => {
+                let ping = Message::Ping { sent: unix_timestamp_micros() };
+                if let Err(mpsc::error::TrySendError::Closed(_)) = control_tx.try_send(ping) {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+// Flags shared between the receiving tasks and the UI
+#[derive(Clone)]
+pub struct ReceiverFlags {
+    pub host_unreachable: Arc<AtomicBool>,
+    pub stream_ended: Arc<AtomicBool>,
+    pub is_paused: Arc<AtomicBool>,        // Paused locally by the user
+    pub paused_by_caster: Arc<AtomicBool>, // Transmission paused by the caster
+}
+
+// Shapes of the caster with when they arrived, fading ink fades from then. Drawn in id order.
+pub type RemoteAnnotations = BTreeMap<u32, (Instant, AnnotationPacket)>;
+
+// What the caster sends besides the frames, shown by the UI
+#[derive(Clone)]
+pub struct CasterState {
+    pub slate: Arc<std::sync::Mutex<Option<SlatePacket>>>,
+    pub streams: Arc<std::sync::Mutex<Vec<StreamInfo>>>,
+    pub cursor: Arc<std::sync::Mutex<RemoteCursor>>,
+    pub pointers: Arc<std::sync::Mutex<RemotePointers>>,
+    pub chat: Arc<std::sync::Mutex<ChatHistory>>,
+    pub annotations: Arc<std::sync::Mutex<RemoteAnnotations>>,
+}
+
 pub async fn start_receiving(
-    frames_vec: Arc<std::sync::Mutex<VecDeque<CapturedFrame>>>,
+    frames_vec: Arc<std::sync::Mutex<StreamBuffers>>,
     receiver: Arc<Mutex<Receiver>>,
     stop_notify: Arc<Notify>,
-    host_unreachable: Arc<AtomicBool>,
-    stream_ended: Arc<AtomicBool>,
-    is_paused: Arc<AtomicBool>,
+    flags: ReceiverFlags,
+    stats: Arc<std::sync::Mutex<StreamStats>>,
+    caster: CasterState,
 ) {
+    let ReceiverFlags {
+        host_unreachable,
+        stream_ended,
+        is_paused,
+        paused_by_caster,
+    } = flags;
+    let CasterState {
+        slate,
+        streams,
+        cursor,
+        pointers,
+        chat,
+        annotations,
+    } = caster;
     let stop_notify1 = stop_notify.clone();
-    let (tx, mut rx) = mpsc::channel::<Vec<u8>>(100);
+    let (tx, mut rx) = mpsc::channel::<Message>(100);
+    let control_tx = receiver.lock().await.control_sender();
+    let recovery = Arc::new(KeyframeRecovery::new(control_tx.clone()));
+    stats.lock().unwrap().start();
+
+    tokio::spawn(ping_caster(control_tx, stop_notify.clone()));
 
     tokio::spawn(async move {
         let mut recv = receiver.lock().await;
@@ -159,34 +390,122 @@ pub async fn start_receiving(
                 break; // exit when notify_waiters() is called
             }
 
-            Some(frame) = rx.recv() => {
+            Some(message) = rx.recv() => {
 
-                if !is_paused.load(Ordering::SeqCst) {
+                // Clock synchronization keeps running while paused
+                if let Message::Pong { ping_sent, ping_received, pong_sent } = message {
+                    stats.lock().unwrap().record_pong(
+                        ping_sent,
+                        ping_received,
+                        pong_sent,
+                        unix_timestamp_micros(),
+                    );
+                    continue;
+                }
 
-                    if let Ok(message) = from_utf8(&frame) {
-                        //blank screen
-                        if message.trim_matches('\0') == "BLNK" {
+                // The last frame stays on screen until the caster resumes
+                match message {
+                    Message::Paused => {
+                        println!("Transmission paused by caster");
+                        paused_by_caster.store(true, Ordering::SeqCst);
+                        continue;
+                    }
+                    Message::Resumed => {
+                        println!("Transmission resumed by caster");
+                        paused_by_caster.store(false, Ordering::SeqCst);
+                        continue;
+                    }
+                    Message::StreamList(list) => {
+                        println!("Caster publishes {} streams", list.len());
+                        *streams.lock().unwrap() = list;
+                        continue;
+                    }
+                    // Kept while paused too, the UI draws the position matching the frame shown
+                    Message::Cursor(packet) => {
+                        cursor.lock().unwrap().push(packet);
+                        continue;
+                    }
+                    Message::CursorShape(packet) => {
+                        cursor.lock().unwrap().shape = Some(packet);
+                        continue;
+                    }
+                    Message::PointersAllowed(allowed) => {
+                        println!("Laser pointers allowed by caster: {}", allowed);
+                        let mut pointers = pointers.lock().unwrap();
+                        pointers.allowed = allowed;
+                        if !allowed {
+                            pointers.pointers.clear();
+                        }
+                        continue;
+                    }
+                    Message::Pointer(packet) => {
+                        let mut pointers = pointers.lock().unwrap();
+                        if packet.visible {
+                            pointers.pointers.insert(packet.viewer_id, packet);
+                        } else {
+                            pointers.pointers.remove(&packet.viewer_id);
+                        }
+                        continue;
+                    }
+                    Message::Chat(packet) => {
+                        chat.lock().unwrap().push(ChatMessage::received(packet));
+                        continue;
+                    }
+                    // Kept while paused, like the pointer
+                    Message::Annotation(packet) => {
+                        annotations
+                            .lock()
+                            .unwrap()
+                            .insert(packet.id, (Instant::now(), packet));
+                        continue;
+                    }
+                    Message::RemoveAnnotation(id) => {
+                        annotations.lock().unwrap().remove(&id);
+                        continue;
+                    }
+                    Message::ClearAnnotations => {
+                        annotations.lock().unwrap().clear();
+                        continue;
+                    }
+                    _ => {}
+                }
 
-                            let mut blank_frame = Vec::with_capacity(1920 * 1080 * 4);
-                            for _ in 0..(1920 * 1080) {
-                                blank_frame.extend_from_slice(&[0, 0, 0, 255]); // BGRA o RGBA nero opaco
-                            }
+                if !is_paused.load(Ordering::SeqCst) {
+
+                    match message {
+                        // The caster hid the screen, the slate stays until the next frame
+                        Message::Slate(mut packet) => {
+                            println!("Received slate: {:?}", packet.message);
+                            let frame = render_slate(&packet);
+                            // The whole screen is hidden, every stream shows the slate
                             let mut frames = frames_vec1.lock().unwrap();
+                            frames.entry(MAIN_STREAM).or_default();
+                            for buffer in frames.values_mut() {
+                                buffer.push(frame.clone(), unix_timestamp_micros());
+                            }
+                            drop(frames);
 
-                            let frame = CapturedFrame::from_rgba_vec(
-                                blank_frame,
-                                1920,
-                                1080,
-                            );
-                            frames.push_back(frame);
+                            packet.image.clear(); // Already rendered, the UI only needs the text
+                            *slate.lock().unwrap() = Some(packet);
+                        }
+                        Message::Frame(frame) => {
+                            slate.lock().unwrap().take();
+                            stats.lock().unwrap().record_frame_received(frame.data.len());
+                            if !recovery.accept(&frame) {
+                                stats.lock().unwrap().record_dropped(1);
+                                continue;
+                            }
+                            let recovery = recovery.clone();
+                            let stats = stats.clone();
+                            tokio::spawn(async move {
+                                println!("Calling process_frame");
+                                process_frame(frames_vec1, frame, recovery, stats).await;
+                                // frames_vec is the playout buffer shared with ui
+                            });
+                        }
+                        other => {
+                            eprintln!("Unexpected message from sender: {:?}", other);
                         }
-                    }
-                    else {
-                        tokio::spawn(async move {
-                            println!("Calling process_frame");
-                            process_frame(frames_vec1, frame).await;
-                            // frames_vec is the vector of frames to share with ui
-                        });
                     }
                 }
             }
@@ -1,12 +1,20 @@
-use super::CapturedFrame;
+use super::compositor::{composite, stitch};
+use super::cursor::{Cursor, CursorTracker};
+use super::window::{TrackingState, WindowError, WindowGeometry, WindowTracker};
+use super::{CapturedFrame, WindowInfo};
 
-use crate::config::Config;
+use crate::common::{unix_timestamp_micros, CaptureArea};
+use crate::config::{
+    display_id, CaptureRegion, Config, CursorMode, MaskStyle, NamedStream, Redaction,
+};
+
+use display_info::DisplayInfo;
 
 use image::{ImageBuffer, RgbaImage};
 use log::{debug, error};
 use scrap::{Capturer, Display};
 use std::{
-    collections::VecDeque,
+    collections::{HashMap, VecDeque},
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc, Mutex,
@@ -27,10 +35,13 @@ pub enum CaptureError {
 pub struct ScreenCapture {
     config: Arc<Mutex<Config>>,
     monitors: Vec<String>,
+    display_ids: Vec<String>, // Of the monitors, in the same order
     capturer: Option<Capturer>,
     width: usize,
     height: usize,
     stop_capture: Arc<AtomicBool>,
+    window_state: Arc<Mutex<TrackingState>>,
+    redactions: Arc<Mutex<Vec<Redaction>>>, // Drawn on the annotation overlay, gone when it closes
 }
 
 impl Default for ScreenCapture {
@@ -48,13 +59,22 @@ impl ScreenCapture {
             }
         }
 
+        let display_ids = DisplayInfo::all()
+            .unwrap_or_default()
+            .iter()
+            .map(display_id)
+            .collect();
+
         Self {
             config,
             monitors: monitors_list,
+            display_ids,
             capturer: None,
             width: 0,
             height: 0,
             stop_capture: Arc::new(AtomicBool::new(false)),
+            window_state: Arc::new(Mutex::new(TrackingState::default())),
+            redactions: Arc::new(Mutex::new(Vec::new())),
         }
     }
 
@@ -73,93 +93,173 @@ impl ScreenCapture {
         &self.monitors
     }
 
+    // Key of the privacy masks of a monitor
+    pub fn display_id(&self, monitor: usize) -> String {
+        self.display_ids.get(monitor).cloned().unwrap_or_default()
+    }
+
+    pub fn window_state(&self) -> TrackingState {
+        *self.window_state.lock().unwrap()
+    }
+
+    // Applied to the frames of every stream as soon as they change
+    pub fn redactions(&self) -> Arc<Mutex<Vec<Redaction>>> {
+        self.redactions.clone()
+    }
+
     pub fn start_capture(&self, captured_frames: Arc<Mutex<VecDeque<CapturedFrame>>>) {
         let config = self.config.clone();
         let stop_capture = self.stop_capture.clone();
+        let window_state = self.window_state.clone();
+        let redactions = self.redactions.clone();
         stop_capture.store(false, Ordering::SeqCst);
 
         thread::spawn(move || {
-            let mut current_monitor_index: Option<usize> = None;
-            let mut capturer: Option<Capturer> = None;
-            let mut current_dimensions = (0, 0);
+            // One capturer for each monitor the stream is made of
+            let mut sources: HashMap<usize, MonitorSource> = HashMap::new();
+            let mut cursor_source = CursorSource::default();
+            let mut window_source = WindowSource::default();
+            let mut sequence: u64 = 0;
 
             while !stop_capture.load(Ordering::SeqCst) {
-                // Check if monitor selection changed
-                let new_monitor_index = {
-                    let conf_lock = config.lock().unwrap();
-                    conf_lock.capture.selected_monitor
-                };
+                // Check if the selection changed
+                let capture = config.lock().unwrap().capture.clone();
 
-                // Reinitialize capturer if monitor changed or not initialized
-                if current_monitor_index != Some(new_monitor_index) || capturer.is_none() {
-                    let monitor = match get_monitor_from_index(new_monitor_index) {
-                        Ok(m) => m,
-                        Err(e) => {
-                            error!("Error: {}", e);
-                            thread::sleep(std::time::Duration::from_secs(1));
-                            return;
+                // A followed window is a single region that moves with it
+                let regions = match &capture.window {
+                    Some(window) => match window_source.follow(window, &window_state) {
+                        Some(region) => vec![region],
+                        None => {
+                            // Hold the last frame rather than showing anything else
+                            thread::sleep(std::time::Duration::from_millis(1000 / 6));
+                            continue;
                         }
-                    };
+                    },
+                    None => {
+                        *window_state.lock().unwrap() = TrackingState::Idle;
+                        capture.regions.clone()
+                    }
+                };
 
-                    let width = monitor.width() as u32;
-                    let height = monitor.height() as u32;
+                // Monitors of the virtual desktop, in the same order as the capturers
+                let displays = if regions.is_empty() && capture.whole_desktop {
+                    DisplayInfo::all().unwrap_or_default()
+                } else {
+                    Vec::new()
+                };
 
-                    debug!("Monitor dimensions: {}x{}", width, height,);
+                let mut monitors: Vec<usize> = Vec::new();
+                if !displays.is_empty() {
+                    monitors.extend(0..displays.len());
+                } else if regions.is_empty() {
+                    monitors.push(capture.selected_monitor);
+                } else {
+                    for region in &regions {
+                        if !monitors.contains(&region.monitor) {
+                            monitors.push(region.monitor);
+                        }
+                    }
+                }
+                sources.retain(|index, _| monitors.contains(index));
 
-                    match Capturer::new(monitor) {
-                        Ok(c) => {
-                            capturer = Some(c);
-                            current_monitor_index = Some(new_monitor_index);
-                            current_dimensions = (width, height);
+                // Initialize the capturers of newly selected monitors
+                for &index in &monitors {
+                    if sources.contains_key(&index) {
+                        continue;
+                    }
+                    match MonitorSource::new(index) {
+                        Ok(source) => {
+                            sources.insert(index, source);
                         }
                         Err(e) => {
-                            error!("{}", CaptureError::InitError(e.to_string()));
+                            error!("Error: {}", e);
+                            thread::sleep(std::time::Duration::from_secs(1));
                             return;
                         }
-                    };
+                    }
                 }
 
-                // Capture frame using the current capturer
-                if let Some(ref mut cap) = capturer {
-                    match cap.frame() {
-                        Ok(raw_frame) => {
-                            let img_buffer: RgbaImage = ImageBuffer::from_raw(
-                                current_dimensions.0,
-                                current_dimensions.1,
-                                raw_frame.to_vec(),
-                            )
-                            .expect("Couldn't create image buffer from raw frame");
-
-                            let rgba_img = CapturedFrame::from_bgra(
-                                current_dimensions.0,
-                                current_dimensions.1,
-                                img_buffer,
-                            );
-
-                            let mut frames = captured_frames.lock().unwrap();
-
-                            frames.push_back(rgba_img);
-                        }
-
+                // Capture frame using the current capturers
+                let capture_timestamp = unix_timestamp_micros();
+                let cursor = cursor_source.sample(capture.cursor);
+                let mut has_new_frame = false;
+                for &index in &monitors {
+                    let source = sources.get_mut(&index).unwrap();
+                    let hidden_areas =
+                        capture.hidden_areas(&source.display_id, &redactions.lock().unwrap());
+                    match source.grab(&hidden_areas, cursor.as_ref()) {
+                        Ok(updated) => has_new_frame |= updated,
                         Err(e) => match e.kind() {
-                            std::io::ErrorKind::WouldBlock => {
-                                //debug!("Frame not ready; skipping this frame.");
-                            }
                             std::io::ErrorKind::ConnectionReset => {
                                 error!(
                                     r"Strange Error: {e}.
                                     Resetting capturer.
                                     Make sure that if you changed your screen size, keep it at 16:9 ratio."
                                 );
-                                capturer = None; // Force reinitialization on next iteration
+                                sources.remove(&index); // Force reinitialization on next iteration
                             }
                             _ => {
                                 error!("What did just happen? {e:?}");
-                                capturer = None; // Force reinitialization on next iteration
+                                sources.remove(&index); // Force reinitialization on next iteration
                             }
                         },
                     }
                 }
+
+                let frame = if !has_new_frame {
+                    None
+                } else if !displays.is_empty() {
+                    // Monitors without a frame yet are left black until they have one
+                    let frames = displays
+                        .iter()
+                        .enumerate()
+                        .map(|(index, display)| {
+                            let frame = sources.get(&index).and_then(|s| s.last_frame.clone());
+                            (frame, display)
+                        })
+                        .collect();
+                    Some(stitch(frames))
+                } else if regions.is_empty() {
+                    sources
+                        .get_mut(&capture.selected_monitor)
+                        .and_then(|source| source.last_frame.take())
+                } else {
+                    // Regions of monitors without a frame yet are left out until they have one
+                    let regions: Vec<CapturedFrame> = regions
+                        .iter()
+                        .filter_map(|region| {
+                            let frame = sources.get(&region.monitor)?.last_frame.clone()?;
+                            let area = CaptureArea::new_with_safeguards(
+                                region.area.x,
+                                region.area.y,
+                                region.area.width,
+                                region.area.height,
+                                frame.width,
+                                frame.height,
+                            );
+                            frame.view(
+                                area.x as u32,
+                                area.y as u32,
+                                area.width as u32,
+                                area.height as u32,
+                            )
+                        })
+                        .collect();
+                    Some(composite(regions, capture.layout))
+                };
+
+                if let Some(mut frame) = frame {
+                    sequence += 1;
+                    frame.sequence = sequence;
+                    frame.capture_timestamp = capture_timestamp;
+                    if capture.cursor == CursorMode::Composited {
+                        frame.draw_cursor();
+                    }
+
+                    let mut frames = captured_frames.lock().unwrap();
+
+                    frames.push_back(frame);
+                }
                 thread::sleep(std::time::Duration::from_millis(1000 / 6)); //capture 6 FPS
             }
             debug!("Capture thread stopped");
@@ -168,6 +268,327 @@ impl ScreenCapture {
     }
 }
 
+// Capture thread of an additional named stream, stopped when dropped
+pub struct StreamCapture {
+    stop_capture: Arc<AtomicBool>,
+    frames: Arc<Mutex<VecDeque<CapturedFrame>>>,
+}
+
+impl StreamCapture {
+    pub fn start(
+        stream: NamedStream,
+        config: Arc<Mutex<Config>>,
+        redactions: Arc<Mutex<Vec<Redaction>>>,
+    ) -> Self {
+        let stop_capture = Arc::new(AtomicBool::new(false));
+        let frames = Arc::new(Mutex::new(VecDeque::new()));
+
+        let stop = stop_capture.clone();
+        let captured_frames = frames.clone();
+        thread::spawn(move || {
+            let mut source = match MonitorSource::new(stream.monitor) {
+                Ok(source) => source,
+                Err(e) => {
+                    error!("Error starting stream {:?}: {}", stream.name, e);
+                    return;
+                }
+            };
+            let mut cursor_source = CursorSource::default();
+            let mut sequence: u64 = 0;
+
+            while !stop.load(Ordering::SeqCst) {
+                let (masks, cursor_mode) = {
+                    let capture = &config.lock().unwrap().capture;
+                    let redactions = redactions.lock().unwrap();
+                    (
+                        capture.hidden_areas(&source.display_id, &redactions),
+                        capture.cursor,
+                    )
+                };
+
+                let capture_timestamp = unix_timestamp_micros();
+                let cursor = cursor_source.sample(cursor_mode);
+                match source.grab(&masks, cursor.as_ref()) {
+                    Ok(true) => {
+                        let mut frame = source.last_frame.take().unwrap();
+                        if let Some(area) = stream.area {
+                            let area = CaptureArea::new_with_safeguards(
+                                area.x,
+                                area.y,
+                                area.width,
+                                area.height,
+                                frame.width,
+                                frame.height,
+                            );
+                            frame = frame
+                                .view(
+                                    area.x as u32,
+                                    area.y as u32,
+                                    area.width as u32,
+                                    area.height as u32,
+                                )
+                                .unwrap();
+                        }
+                        sequence += 1;
+                        frame.sequence = sequence;
+                        frame.capture_timestamp = capture_timestamp;
+                        if cursor_mode == CursorMode::Composited {
+                            frame.draw_cursor();
+                        }
+
+                        // Only the newest frame matters, the stream is polled by the UI
+                        let mut frames = captured_frames.lock().unwrap();
+                        frames.clear();
+                        frames.push_back(frame);
+                    }
+                    Ok(false) => {}
+                    Err(e) => {
+                        error!("Error capturing stream {:?}: {}", stream.name, e);
+                        match MonitorSource::new(stream.monitor) {
+                            Ok(new_source) => source = new_source,
+                            Err(e) => {
+                                error!("Error restarting stream {:?}: {}", stream.name, e);
+                                return;
+                            }
+                        }
+                    }
+                }
+                thread::sleep(std::time::Duration::from_millis(1000 / 6)); //capture 6 FPS
+            }
+            debug!("Stream {:?} capture thread stopped", stream.name);
+        });
+
+        Self {
+            stop_capture,
+            frames,
+        }
+    }
+
+    pub fn pop_frame(&self) -> Option<CapturedFrame> {
+        self.frames.lock().unwrap().pop_front()
+    }
+}
+
+impl Drop for StreamCapture {
+    fn drop(&mut self) {
+        self.stop_capture.store(true, Ordering::SeqCst);
+    }
+}
+
+// Followed window, its geometry is queried once per captured frame
+#[derive(Default)]
+struct WindowSource {
+    tracker: Option<WindowTracker>,
+    displays: Vec<DisplayInfo>, // Listed again when the window is on none of them
+}
+
+impl WindowSource {
+    // Region of the monitor showing the window, None while it can't be captured
+    fn follow(
+        &mut self,
+        window: &WindowInfo,
+        window_state: &Mutex<TrackingState>,
+    ) -> Option<CaptureRegion> {
+        if self.tracker.is_none() {
+            self.tracker = WindowTracker::new()
+                .inspect_err(|e| error!("Failed to follow window {:?}: {}", window.title, e))
+                .ok();
+        }
+
+        let geometry = match &self.tracker {
+            Some(tracker) => tracker.geometry(window.id),
+            None => Err(WindowError::Unsupported()),
+        };
+        let (state, region) = match geometry {
+            Ok(WindowGeometry::Visible {
+                x,
+                y,
+                width,
+                height,
+            }) => {
+                let region = window_region(&self.displays, x, y, width, height).or_else(|| {
+                    // The monitors changed since they were listed
+                    self.displays = DisplayInfo::all().unwrap_or_default();
+                    window_region(&self.displays, x, y, width, height)
+                });
+                (TrackingState::Following, region)
+            }
+            Ok(WindowGeometry::Minimized) => (TrackingState::Minimized, None),
+            Err(WindowError::Closed(_)) => (TrackingState::Closed, None),
+            Err(WindowError::Unsupported()) => (TrackingState::Closed, None),
+            Err(e) => {
+                error!("Failed to follow window {:?}: {}", window.title, e);
+                self.tracker = None; // Connect again on the next frame
+                (TrackingState::Closed, None)
+            }
+        };
+
+        let mut current_state = window_state.lock().unwrap();
+        if *current_state != state {
+            debug!("Window {:?}: {:?}", window.title, state);
+            *current_state = state;
+        }
+        region
+    }
+}
+
+// The window is captured from the monitor containing its center, cropped to that monitor
+fn window_region(
+    displays: &[DisplayInfo],
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+) -> Option<CaptureRegion> {
+    let (center_x, center_y) = (x + width as i32 / 2, y + height as i32 / 2);
+    let (monitor, display) = displays.iter().enumerate().find(|(_, d)| {
+        (d.x..d.x + d.width as i32).contains(&center_x)
+            && (d.y..d.y + d.height as i32).contains(&center_y)
+    })?;
+
+    let left = x.max(display.x);
+    let top = y.max(display.y);
+    let right = (x + width as i32).min(display.x + display.width as i32);
+    let bottom = (y + height as i32).min(display.y + display.height as i32);
+
+    Some(CaptureRegion {
+        monitor,
+        area: CaptureArea {
+            x: (left - display.x) as usize,
+            y: (top - display.y) as usize,
+            width: (right - left) as usize,
+            height: (bottom - top) as usize,
+        },
+    })
+}
+
+// Pointer sampled once per captured frame, nothing when hidden or unavailable
+#[derive(Default)]
+struct CursorSource {
+    tracker: Option<CursorTracker>,
+    unavailable: bool, // Reported once, the pointer is not sampled again
+}
+
+impl CursorSource {
+    fn sample(&mut self, mode: CursorMode) -> Option<Cursor> {
+        if mode == CursorMode::Hidden {
+            self.tracker = None;
+            return None;
+        }
+        if self.tracker.is_none() && !self.unavailable {
+            match CursorTracker::new() {
+                Ok(tracker) => self.tracker = Some(tracker),
+                Err(e) => {
+                    error!("The cursor will not be captured: {}", e);
+                    self.unavailable = true;
+                }
+            }
+        }
+
+        match self.tracker.as_mut()?.sample() {
+            Ok(cursor) => Some(cursor),
+            Err(e) => {
+                // The connection is opened again on the next frame
+                error!("Error sampling the cursor: {}", e);
+                self.tracker = None;
+                None
+            }
+        }
+    }
+}
+
+// Capturer of a single monitor, with the last frame it produced
+struct MonitorSource {
+    capturer: Capturer,
+    dimensions: (u32, u32),
+    display: Option<DisplayInfo>, // Position on the virtual desktop, used to place the pointer
+    display_id: String,           // Its privacy masks and redactions
+    last_frame: Option<CapturedFrame>,
+}
+
+impl MonitorSource {
+    fn new(index: usize) -> Result<Self, CaptureError> {
+        let monitor = get_monitor_from_index(index)?;
+
+        let width = monitor.width() as u32;
+        let height = monitor.height() as u32;
+
+        debug!("Monitor {} dimensions: {}x{}", index, width, height);
+
+        let capturer =
+            Capturer::new(monitor).map_err(|e| CaptureError::InitError(e.to_string()))?;
+        // Monitors are listed in the same order by both crates
+        let display = DisplayInfo::all()
+            .ok()
+            .and_then(|mut displays| (index < displays.len()).then(|| displays.remove(index)));
+        Ok(Self {
+            capturer,
+            dimensions: (width, height),
+            display_id: display.as_ref().map(display_id).unwrap_or_default(),
+            display,
+            last_frame: None,
+        })
+    }
+
+    // Returns true if a new frame was captured, the masked regions are gone before it reaches anyone.
+    // The pointer is moved to the last frame even when it didn't change.
+    fn grab(
+        &mut self,
+        masks: &[(CaptureArea, MaskStyle)],
+        cursor: Option<&Cursor>,
+    ) -> std::io::Result<bool> {
+        let result = self.grab_frame(masks);
+        let cursor = cursor.and_then(|cursor| self.monitor_cursor(cursor));
+        if let Some(frame) = &mut self.last_frame {
+            frame.cursor = cursor;
+        }
+        result
+    }
+
+    // The pointer in the pixels of this monitor, None if it is on another one
+    fn monitor_cursor(&self, cursor: &Cursor) -> Option<Cursor> {
+        let (width, height) = self.dimensions;
+        let (x, y, scale_x, scale_y) = match &self.display {
+            // Desktop coordinates may be logical points while frames are physical pixels
+            Some(display) => (
+                display.x,
+                display.y,
+                width as f64 / display.width.max(1) as f64,
+                height as f64 / display.height.max(1) as f64,
+            ),
+            None => (0, 0, 1.0, 1.0),
+        };
+        cursor
+            .translate(-x, -y, i32::MAX as usize, i32::MAX as usize)?
+            .place(scale_x, scale_y, 0, 0)
+            .translate(0, 0, width as usize, height as usize)
+    }
+
+    fn grab_frame(&mut self, masks: &[(CaptureArea, MaskStyle)]) -> std::io::Result<bool> {
+        match self.capturer.frame() {
+            Ok(raw_frame) => {
+                let img_buffer: RgbaImage = ImageBuffer::from_raw(
+                    self.dimensions.0,
+                    self.dimensions.1,
+                    raw_frame.to_vec(),
+                )
+                .expect("Couldn't create image buffer from raw frame");
+
+                let mut rgba_img =
+                    CapturedFrame::from_bgra(self.dimensions.0, self.dimensions.1, img_buffer);
+                rgba_img.mask(masks);
+                self.last_frame = Some(rgba_img);
+                Ok(true)
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                //debug!("Frame not ready; skipping this frame.");
+                Ok(false)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
 fn get_monitors() -> Result<Vec<Display>, CaptureError> {
     let monitors: Vec<Display> = Display::all().map_err(|_| CaptureError::NoMonitors())?;
 
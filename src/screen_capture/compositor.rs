@@ -0,0 +1,226 @@
+use super::cursor::Cursor;
+use super::CapturedFrame;
+
+use crate::config::CompositeLayout;
+
+use display_info::DisplayInfo;
+use image::imageops::{self, FilterType};
+use image::{ImageBuffer, Rgba, RgbaImage};
+
+// Space around the picture in picture insets
+const INSET_MARGIN: u32 = 16;
+// Insets are scaled to fit in this fraction of the main region
+const INSET_FRACTION: u32 = 4;
+
+// Where an image ended up on the canvas, used to move its pointer along with it
+struct Placement {
+    scale_x: f64,
+    scale_y: f64,
+    x: i64,
+    y: i64,
+}
+
+impl Placement {
+    fn new(original: &RgbaImage, scaled: &RgbaImage, x: i64, y: i64) -> Self {
+        Self {
+            scale_x: scaled.width() as f64 / original.width() as f64,
+            scale_y: scaled.height() as f64 / original.height() as f64,
+            x,
+            y,
+        }
+    }
+
+    fn cursor(&self, cursor: &Cursor) -> Cursor {
+        cursor.place(self.scale_x, self.scale_y, self.x, self.y)
+    }
+}
+
+// Arrange the regions into a single frame, in the order they were selected
+pub fn composite(regions: Vec<CapturedFrame>, layout: CompositeLayout) -> CapturedFrame {
+    let (images, cursors): (Vec<RgbaImage>, Vec<Option<Cursor>>) = regions
+        .into_iter()
+        .filter_map(|frame| {
+            let cursor = frame.cursor.clone();
+            Some((to_image(frame)?, cursor))
+        })
+        .unzip();
+
+    let (canvas, placements) = match images.len() {
+        0 => (RgbaImage::new(2, 2), Vec::new()),
+        1 => {
+            let placement = Placement::new(&images[0], &images[0], 0, 0);
+            (images.into_iter().next().unwrap(), vec![placement])
+        }
+        _ => match layout {
+            CompositeLayout::SideBySide => side_by_side(&images),
+            CompositeLayout::Grid => grid(&images),
+            CompositeLayout::PictureInPicture => picture_in_picture(&images),
+        },
+    };
+
+    // The pointer of the first region showing it, insets included
+    let cursor = cursors
+        .iter()
+        .zip(&placements)
+        .find_map(|(cursor, placement)| Some(placement.cursor(cursor.as_ref()?)));
+
+    // The encoder works on 4:2:0 chroma, odd sizes are rejected
+    let canvas = pad_to_even(canvas);
+    to_frame(canvas, cursor)
+}
+
+// Place every monitor where it is on the virtual desktop. Desktop coordinates are logical points
+// on some platforms while frames are physical pixels: the canvas uses the highest pixels per point
+// ratio, the scale factor of the densest monitor, so no monitor loses resolution.
+// Monitors without a frame are left black.
+pub fn stitch(monitors: Vec<(Option<CapturedFrame>, &DisplayInfo)>) -> CapturedFrame {
+    let scale = monitors
+        .iter()
+        .filter_map(|(frame, display)| {
+            Some(frame.as_ref()?.width as f64 / display.width.max(1) as f64)
+        })
+        .fold(1.0, f64::max);
+    let left = monitors.iter().map(|(_, d)| d.x).min().unwrap_or(0);
+    let top = monitors.iter().map(|(_, d)| d.y).min().unwrap_or(0);
+    let right = monitors.iter().map(|(_, d)| d.x + d.width as i32).max().unwrap_or(0);
+    let bottom = monitors.iter().map(|(_, d)| d.y + d.height as i32).max().unwrap_or(0);
+    let scaled = |length: i64| (length as f64 * scale).round() as u32;
+
+    let mut canvas = blank_canvas(
+        scaled((right - left) as i64),
+        scaled((bottom - top) as i64),
+    );
+    let mut cursor = None;
+    for (frame, display) in monitors {
+        let Some(frame) = frame else {
+            continue;
+        };
+        let frame_cursor = frame.cursor.clone();
+        let Some(image) = to_image(frame) else {
+            continue;
+        };
+        let (width, height) = (scaled(display.width as i64), scaled(display.height as i64));
+        let resized = if image.dimensions() == (width, height) {
+            image.clone()
+        } else {
+            imageops::resize(&image, width.max(1), height.max(1), FilterType::Triangle)
+        };
+        let (x, y) = (
+            scaled((display.x - left) as i64) as i64,
+            scaled((display.y - top) as i64) as i64,
+        );
+        imageops::overlay(&mut canvas, &resized, x, y);
+
+        if let Some(frame_cursor) = frame_cursor {
+            cursor = Some(Placement::new(&image, &resized, x, y).cursor(&frame_cursor));
+        }
+    }
+
+    let canvas = pad_to_even(canvas);
+    to_frame(canvas, cursor)
+}
+
+// Every region scaled to the height of the tallest one, left to right
+fn side_by_side(images: &[RgbaImage]) -> (RgbaImage, Vec<Placement>) {
+    let height = images.iter().map(|image| image.height()).max().unwrap_or(1);
+    let scaled: Vec<RgbaImage> = images
+        .iter()
+        .map(|image| {
+            let width = (image.width() as u64 * height as u64 / image.height() as u64) as u32;
+            fit(image, width.max(1), height)
+        })
+        .collect();
+
+    let width = scaled.iter().map(|image| image.width()).sum();
+    let mut canvas = blank_canvas(width, height);
+    let mut placements = Vec::with_capacity(images.len());
+    let mut x = 0;
+    for (image, scaled) in images.iter().zip(&scaled) {
+        imageops::overlay(&mut canvas, scaled, x as i64, 0);
+        placements.push(Placement::new(image, scaled, x as i64, 0));
+        x += scaled.width();
+    }
+    (canvas, placements)
+}
+
+// Cells as big as the largest region, each region letterboxed in its cell
+fn grid(images: &[RgbaImage]) -> (RgbaImage, Vec<Placement>) {
+    let columns = (images.len() as f64).sqrt().ceil() as u32;
+    let rows = (images.len() as u32).div_ceil(columns);
+    let cell_width = images.iter().map(|image| image.width()).max().unwrap_or(1);
+    let cell_height = images.iter().map(|image| image.height()).max().unwrap_or(1);
+
+    let mut canvas = blank_canvas(columns * cell_width, rows * cell_height);
+    let mut placements = Vec::with_capacity(images.len());
+    for (i, image) in images.iter().enumerate() {
+        let scaled = fit(image, cell_width, cell_height);
+        let (column, row) = (i as u32 % columns, i as u32 / columns);
+        let x = column * cell_width + (cell_width - scaled.width()) / 2;
+        let y = row * cell_height + (cell_height - scaled.height()) / 2;
+        imageops::overlay(&mut canvas, &scaled, x as i64, y as i64);
+        placements.push(Placement::new(image, &scaled, x as i64, y as i64));
+    }
+    (canvas, placements)
+}
+
+// The first region at full size, the others stacked in the bottom right corner
+fn picture_in_picture(images: &[RgbaImage]) -> (RgbaImage, Vec<Placement>) {
+    let mut canvas = images[0].clone();
+    let (width, height) = canvas.dimensions();
+    let mut placements = vec![Placement::new(&images[0], &images[0], 0, 0)];
+
+    let mut bottom = height.saturating_sub(INSET_MARGIN);
+    for image in &images[1..] {
+        let inset = fit(image, width / INSET_FRACTION, height / INSET_FRACTION);
+        let x = width.saturating_sub(INSET_MARGIN + inset.width());
+        let y = bottom.saturating_sub(inset.height());
+        imageops::overlay(&mut canvas, &inset, x as i64, y as i64);
+        placements.push(Placement::new(image, &inset, x as i64, y as i64));
+        bottom = y.saturating_sub(INSET_MARGIN);
+    }
+    (canvas, placements)
+}
+
+// Scale to the largest size that fits in the box, keeping the aspect ratio
+fn fit(image: &RgbaImage, max_width: u32, max_height: u32) -> RgbaImage {
+    let scale = f64::min(
+        max_width as f64 / image.width() as f64,
+        max_height as f64 / image.height() as f64,
+    );
+    let width = ((image.width() as f64 * scale).round() as u32).max(1);
+    let height = ((image.height() as f64 * scale).round() as u32).max(1);
+
+    if (width, height) == image.dimensions() {
+        return image.clone();
+    }
+    imageops::resize(image, width, height, FilterType::Triangle)
+}
+
+fn pad_to_even(image: RgbaImage) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    if width % 2 == 0 && height % 2 == 0 {
+        return image;
+    }
+    let mut canvas = blank_canvas(width + width % 2, height + height % 2);
+    imageops::replace(&mut canvas, &image, 0, 0);
+    canvas
+}
+
+fn blank_canvas(width: u32, height: u32) -> RgbaImage {
+    RgbaImage::from_pixel(width.max(1), height.max(1), Rgba([0, 0, 0, 255]))
+}
+
+fn to_frame(canvas: RgbaImage, cursor: Option<Cursor>) -> CapturedFrame {
+    let (width, height) = canvas.dimensions();
+    let mut frame =
+        CapturedFrame::from_rgba_vec(canvas.into_raw(), width as usize, height as usize);
+    frame.cursor = cursor.and_then(|cursor| cursor.translate(0, 0, frame.width, frame.height));
+    frame
+}
+
+fn to_image(frame: CapturedFrame) -> Option<RgbaImage> {
+    if frame.width == 0 || frame.height == 0 {
+        return None;
+    }
+    ImageBuffer::from_vec(frame.width as u32, frame.height as u32, frame.rgba_data)
+}
@@ -0,0 +1,171 @@
+use std::sync::Arc;
+
+#[cfg(target_os = "linux")]
+use x11rb::{
+    connection::Connection,
+    protocol::{
+        xfixes::ConnectionExt as _,
+        xproto::{ConnectionExt as _, KeyButMask, Window},
+    },
+    rust_connection::RustConnection,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum CursorError {
+    #[error("Cursor capture is only supported on X11")]
+    Unsupported(),
+    #[error("Failed to query the cursor: {0}")]
+    X11(String),
+}
+
+// Image of the pointer, shared by every frame until it changes
+#[derive(Debug, PartialEq)]
+pub struct CursorShape {
+    pub serial: u32, // Changes with the image, receivers only get new shapes
+    pub width: u32,
+    pub height: u32,
+    pub hot_x: u32, // Hotspot, relative to the top left corner of the image
+    pub hot_y: u32,
+    pub rgba: Vec<u8>,
+}
+
+// Position of the hotspot: on the virtual screen when sampled, then in the pixels of the frame
+#[derive(Debug, Clone)]
+pub struct Cursor {
+    pub x: i32,
+    pub y: i32,
+    pub pressed: bool, // Primary button held down
+    pub shape: Arc<CursorShape>,
+}
+
+impl Cursor {
+    // The same pointer in another coordinate space, None if it isn't inside the given size
+    pub fn translate(&self, dx: i32, dy: i32, width: usize, height: usize) -> Option<Self> {
+        let (x, y) = (self.x + dx, self.y + dy);
+        if x < 0 || y < 0 || x >= width as i32 || y >= height as i32 {
+            return None;
+        }
+        Some(Self {
+            x,
+            y,
+            ..self.clone()
+        })
+    }
+
+    // The pointer of a frame that was scaled and placed on a larger canvas
+    pub fn place(&self, scale_x: f64, scale_y: f64, offset_x: i64, offset_y: i64) -> Self {
+        Self {
+            x: (self.x as f64 * scale_x) as i32 + offset_x as i32,
+            y: (self.y as f64 * scale_y) as i32 + offset_y as i32,
+            ..self.clone()
+        }
+    }
+}
+
+// Connection used to sample the pointer, one for each capture thread
+pub struct CursorTracker {
+    #[cfg(target_os = "linux")]
+    connection: RustConnection,
+    #[cfg(target_os = "linux")]
+    root: Window,
+    #[cfg(target_os = "linux")]
+    shape: Option<Arc<CursorShape>>, // Last converted image, reused while the serial is the same
+}
+
+impl CursorTracker {
+    #[cfg(target_os = "linux")]
+    pub fn new() -> Result<Self, CursorError> {
+        // Wayland sessions without Xwayland have no X server to ask
+        if std::env::var_os("DISPLAY").is_none() {
+            return Err(CursorError::Unsupported());
+        }
+
+        let (connection, screen) =
+            RustConnection::connect(None).map_err(|e| CursorError::X11(e.to_string()))?;
+        let root = connection.setup().roots[screen].root;
+
+        // XFixes has to be initialized before any of its requests
+        connection
+            .xfixes_query_version(4, 0)
+            .map_err(|e| CursorError::X11(e.to_string()))?
+            .reply()
+            .map_err(|e| CursorError::X11(e.to_string()))?;
+
+        Ok(Self {
+            connection,
+            root,
+            shape: None,
+        })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn new() -> Result<Self, CursorError> {
+        Err(CursorError::Unsupported())
+    }
+
+    // Current position, button state and image of the pointer
+    #[cfg(target_os = "linux")]
+    pub fn sample(&mut self) -> Result<Cursor, CursorError> {
+        let x11_error = |e: &dyn std::fmt::Display| CursorError::X11(e.to_string());
+
+        let image = self
+            .connection
+            .xfixes_get_cursor_image()
+            .map_err(|e| x11_error(&e))?
+            .reply()
+            .map_err(|e| x11_error(&e))?;
+        let pointer = self
+            .connection
+            .query_pointer(self.root)
+            .map_err(|e| x11_error(&e))?
+            .reply()
+            .map_err(|e| x11_error(&e))?;
+
+        // The image is only converted when the pointer changes
+        let shape = match &self.shape {
+            Some(shape) if shape.serial == image.cursor_serial => shape.clone(),
+            _ => {
+                let shape = Arc::new(CursorShape {
+                    serial: image.cursor_serial,
+                    width: image.width as u32,
+                    height: image.height as u32,
+                    hot_x: image.xhot as u32,
+                    hot_y: image.yhot as u32,
+                    rgba: argb_to_rgba(&image.cursor_image),
+                });
+                self.shape = Some(shape.clone());
+                shape
+            }
+        };
+
+        Ok(Cursor {
+            x: image.x as i32,
+            y: image.y as i32,
+            pressed: pointer.mask.contains(KeyButMask::BUTTON1),
+            shape,
+        })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn sample(&mut self) -> Result<Cursor, CursorError> {
+        Err(CursorError::Unsupported())
+    }
+}
+
+// XFixes gives premultiplied ARGB pixels, one per u32
+#[cfg(target_os = "linux")]
+fn argb_to_rgba(pixels: &[u32]) -> Vec<u8> {
+    let mut rgba = Vec::with_capacity(pixels.len() * 4);
+    for pixel in pixels {
+        let [a, r, g, b] = pixel.to_be_bytes();
+        let unpremultiply = |c: u8| {
+            if a == 0 {
+                0
+            } else {
+                (c as u32 * 255 / a as u32).min(255) as u8
+            }
+        };
+        rgba.extend_from_slice(&[unpremultiply(r), unpremultiply(g), unpremultiply(b), a]);
+    }
+    rgba
+}
@@ -1,16 +1,32 @@
 // use image::{ImageBuffer, RgbaImage};
-use image::{GenericImageView, ImageBuffer, RgbaImage};
+use image::imageops::{self, FilterType};
+use image::{GenericImageView, ImageBuffer, Rgba, RgbaImage};
 use std::io::Write;
 use std::path::PathBuf;
 use std::process::{Command, Stdio};
 
-use crate::common::RgbaBuffer;
+use super::cursor::Cursor;
+
+use crate::common::{CaptureArea, RgbaBuffer};
+use crate::config::MaskStyle;
+
+// Codec used to encode the frames sent over the network
+pub const VIDEO_CODEC: &str = "H.265 (HEVC)";
+
+// Strong enough that no text inside a blurred mask stays readable
+const MASK_BLUR_FACTOR: u32 = 24;
+
+// Side of the blocks of a pixelated mask
+const MASK_PIXEL_SIZE: u32 = 16;
 
 #[derive(Debug, Default, Clone)]
 pub struct CapturedFrame {
     pub width: usize,
     pub height: usize,
     pub rgba_data: Vec<u8>,
+    pub sequence: u64,          // Incremented for every captured frame
+    pub capture_timestamp: u64, // Microseconds since UNIX epoch, caster clock
+    pub cursor: Option<Cursor>, // Pointer in the pixels of the frame, sent apart from the video
 }
 
 impl CapturedFrame {
@@ -23,6 +39,7 @@ impl CapturedFrame {
             width: buffer_width,
             height: buffer_height,
             rgba_data: buffer_rgba,
+            ..Default::default()
         }
     }
 
@@ -35,6 +52,7 @@ impl CapturedFrame {
             width: width as usize,
             height: height as usize,
             rgba_data: bgra_buffer.to_vec(),
+            ..Default::default()
         }
     }
 
@@ -52,9 +70,122 @@ impl CapturedFrame {
             width: view_width as usize,
             height: view_height as usize,
             rgba_data: cropped_image,
+            sequence: self.sequence,
+            capture_timestamp: self.capture_timestamp,
+            cursor: self.cursor.and_then(|cursor| {
+                cursor.translate(
+                    -(x as i32),
+                    -(y as i32),
+                    view_width as usize,
+                    view_height as usize,
+                )
+            }),
         })
     }
 
+    // Composite the pointer into the frame, it isn't sent apart anymore
+    pub fn draw_cursor(&mut self) {
+        let Some(cursor) = self.cursor.take() else {
+            return;
+        };
+        let Some(pointer) = RgbaImage::from_raw(
+            cursor.shape.width,
+            cursor.shape.height,
+            cursor.shape.rgba.clone(),
+        ) else {
+            return;
+        };
+
+        let mut image: RgbaImage = ImageBuffer::from_vec(
+            self.width as u32,
+            self.height as u32,
+            std::mem::take(&mut self.rgba_data),
+        )
+        .expect("Couldn't create image buffer from raw frame");
+        imageops::overlay(
+            &mut image,
+            &pointer,
+            (cursor.x - cursor.shape.hot_x as i32) as i64,
+            (cursor.y - cursor.shape.hot_y as i32) as i64,
+        );
+        self.rgba_data = image.into_raw();
+    }
+
+    // Average blocks of `block_size` pixels, nothing smaller than a block stays readable
+    pub fn pixelate(self, block_size: u32) -> Self {
+        self.resample(block_size, FilterType::Nearest)
+    }
+
+    // Cheap blur: shrinking averages the pixels, enlarging again smooths the blocks out
+    pub fn blur(self, radius: u32) -> Self {
+        self.resample(radius, FilterType::Triangle)
+    }
+
+    fn resample(self, factor: u32, upscale_filter: FilterType) -> Self {
+        let image: RgbaImage =
+            ImageBuffer::from_vec(self.width as u32, self.height as u32, self.rgba_data)
+                .expect("Couldn't create image buffer from raw frame");
+
+        Self {
+            rgba_data: resample_image(&image, factor, upscale_filter).into_raw(),
+            ..self
+        }
+    }
+
+    // Hide the given areas before the frame is shown, sent or recorded
+    pub fn mask(&mut self, areas: &[(CaptureArea, MaskStyle)]) {
+        if areas.is_empty() {
+            return;
+        }
+
+        let mut image: RgbaImage = ImageBuffer::from_vec(
+            self.width as u32,
+            self.height as u32,
+            std::mem::take(&mut self.rgba_data),
+        )
+        .expect("Couldn't create image buffer from raw frame");
+
+        for (area, style) in areas {
+            // A mask that doesn't fit anymore covers the whole frame, rather than leaking anything
+            let area = CaptureArea::new_with_safeguards(
+                area.x,
+                area.y,
+                area.width,
+                area.height,
+                self.width,
+                self.height,
+            );
+            let (x, y, width, height) = (
+                area.x as u32,
+                area.y as u32,
+                area.width as u32,
+                area.height as u32,
+            );
+
+            match style {
+                MaskStyle::Black => {
+                    for row in y..y + height {
+                        for col in x..x + width {
+                            image.put_pixel(col, row, Rgba([0, 0, 0, 255]));
+                        }
+                    }
+                }
+                MaskStyle::Blur => {
+                    let region = imageops::crop_imm(&image, x, y, width, height).to_image();
+                    let blurred = resample_image(&region, MASK_BLUR_FACTOR, FilterType::Triangle);
+                    imageops::replace(&mut image, &blurred, x as i64, y as i64);
+                }
+                MaskStyle::Pixelate => {
+                    let region = imageops::crop_imm(&image, x, y, width, height).to_image();
+                    let pixelated = resample_image(&region, MASK_PIXEL_SIZE, FilterType::Nearest);
+                    imageops::replace(&mut image, &pixelated, x as i64, y as i64);
+                }
+            }
+        }
+
+        self.rgba_data = image.into_raw();
+    }
+
     pub fn encode_to_h265(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
         let mut command = Command::new("ffmpeg");
 
@@ -113,6 +244,19 @@ impl CapturedFrame {
     }
 }
 
+// Shrink by `factor` and enlarge back to the original size
+fn resample_image(image: &RgbaImage, factor: u32, upscale_filter: FilterType) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    let factor = factor.max(1);
+    let small = imageops::resize(
+        image,
+        (width / factor).max(1),
+        (height / factor).max(1),
+        FilterType::Triangle,
+    );
+    imageops::resize(&small, width, height, upscale_filter)
+}
+
 pub fn decode_from_h265_to_rgba(
     frame: Vec<u8>,
 ) -> Result<CapturedFrame, Box<dyn std::error::Error + Send + Sync>> {
@@ -1,7 +1,12 @@
 mod capturer;
+mod compositor;
+mod cursor;
 mod frame;
+mod window;
 
-pub use capturer::ScreenCapture;
-pub use frame::CapturedFrame;
+pub use capturer::{ScreenCapture, StreamCapture};
+pub use cursor::Cursor;
+pub use frame::{CapturedFrame, VIDEO_CODEC};
+pub use window::{list_windows, TrackingState, WindowInfo};
 
 pub use frame::decode_from_h265_to_rgba;
@@ -0,0 +1,203 @@
+#[cfg(target_os = "linux")]
+use x11rb::{
+    connection::Connection,
+    errors::ReplyError,
+    protocol::{
+        xproto::{Atom, AtomEnum, ConnectionExt as _, GetPropertyReply, MapState, Window},
+        ErrorKind,
+    },
+    rust_connection::RustConnection,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum WindowError {
+    #[error("Window capture is only supported on X11")]
+    Unsupported(),
+    #[error("Failed to query the windows: {0}")]
+    X11(String),
+    #[error("Window {0:#x} no longer exists")]
+    Closed(u64),
+}
+
+// Top-level window that can be followed by the capture
+#[derive(Debug, Clone, PartialEq)]
+pub struct WindowInfo {
+    pub id: u64, // X11 window id
+    pub title: String,
+}
+
+// What the capture knows about the followed window, shown to the caster
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum TrackingState {
+    #[default]
+    Idle, // No window selected
+    Following,
+    Minimized, // The last frame is held until the window is restored
+    Closed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WindowGeometry {
+    // Physical pixels, relative to the virtual screen
+    Visible {
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+    },
+    Minimized,
+}
+
+// List the top-level windows managed by the window manager
+pub fn list_windows() -> Result<Vec<WindowInfo>, WindowError> {
+    WindowTracker::new()?.windows()
+}
+
+// Connection used to list and follow windows, one for each capture thread
+pub struct WindowTracker {
+    #[cfg(target_os = "linux")]
+    connection: RustConnection,
+    #[cfg(target_os = "linux")]
+    root: Window,
+}
+
+impl WindowTracker {
+    #[cfg(target_os = "linux")]
+    pub fn new() -> Result<Self, WindowError> {
+        // Wayland sessions without Xwayland have no X server to ask
+        if std::env::var_os("DISPLAY").is_none() {
+            return Err(WindowError::Unsupported());
+        }
+
+        let (connection, screen) = RustConnection::connect(None).map_err(x11_error)?;
+        let root = connection.setup().roots[screen].root;
+        Ok(Self { connection, root })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn new() -> Result<Self, WindowError> {
+        Err(WindowError::Unsupported())
+    }
+
+    #[cfg(target_os = "linux")]
+    fn windows(&self) -> Result<Vec<WindowInfo>, WindowError> {
+        let client_list = self.atom(b"_NET_CLIENT_LIST")?;
+        let desktop = self.atom(b"_NET_WM_DESKTOP")?;
+        let name = self.atom(b"_NET_WM_NAME")?;
+        let utf8_string = self.atom(b"UTF8_STRING")?;
+
+        let ids: Vec<Window> = self
+            .property(self.root, client_list, AtomEnum::WINDOW)
+            .as_ref()
+            .and_then(GetPropertyReply::value32)
+            .ok_or_else(|| WindowError::X11("the window manager doesn't list windows".into()))?
+            .collect();
+
+        // Windows closed in the meantime have no properties left and are skipped
+        Ok(ids
+            .into_iter()
+            .filter_map(|id| {
+                // Sticky windows such as panels and docks are on every desktop
+                let desktop = self.property(id, desktop, AtomEnum::CARDINAL)?;
+                if desktop.value32().and_then(|mut value| value.next()) == Some(u32::MAX) {
+                    return None;
+                }
+                let title = self
+                    .property(id, name, utf8_string)
+                    .filter(|title| !title.value.is_empty())
+                    .or_else(|| self.property(id, AtomEnum::WM_NAME, AtomEnum::STRING))?;
+                Some(WindowInfo {
+                    id: id as u64,
+                    title: String::from_utf8_lossy(&title.value).into_owned(),
+                })
+            })
+            .collect())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn windows(&self) -> Result<Vec<WindowInfo>, WindowError> {
+        Err(WindowError::Unsupported())
+    }
+
+    // Current position of a window, queried on every captured frame to follow it
+    #[cfg(target_os = "linux")]
+    pub fn geometry(&self, id: u64) -> Result<WindowGeometry, WindowError> {
+        // Requests about a destroyed window fail with BadWindow or BadDrawable
+        let closed = |e: ReplyError| match e {
+            ReplyError::X11Error(ref error)
+                if matches!(error.error_kind, ErrorKind::Window | ErrorKind::Drawable) =>
+            {
+                WindowError::Closed(id)
+            }
+            e => x11_error(e),
+        };
+        let window = id as Window;
+
+        let attributes = self
+            .connection
+            .get_window_attributes(window)
+            .map_err(x11_error)?
+            .reply()
+            .map_err(closed)?;
+        if attributes.map_state != MapState::VIEWABLE {
+            return Ok(WindowGeometry::Minimized);
+        }
+
+        let geometry = self
+            .connection
+            .get_geometry(window)
+            .map_err(x11_error)?
+            .reply()
+            .map_err(closed)?;
+        let origin = self
+            .connection
+            .translate_coordinates(window, self.root, 0, 0)
+            .map_err(x11_error)?
+            .reply()
+            .map_err(closed)?;
+        Ok(WindowGeometry::Visible {
+            x: origin.dst_x as i32,
+            y: origin.dst_y as i32,
+            width: geometry.width as u32,
+            height: geometry.height as u32,
+        })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn geometry(&self, _id: u64) -> Result<WindowGeometry, WindowError> {
+        Err(WindowError::Unsupported())
+    }
+
+    #[cfg(target_os = "linux")]
+    fn atom(&self, name: &[u8]) -> Result<Atom, WindowError> {
+        let reply = self
+            .connection
+            .intern_atom(false, name)
+            .map_err(x11_error)?
+            .reply()
+            .map_err(x11_error)?;
+        Ok(reply.atom)
+    }
+
+    // The whole value of a property, None if the window doesn't have it or is gone
+    #[cfg(target_os = "linux")]
+    fn property(
+        &self,
+        window: Window,
+        property: impl Into<Atom>,
+        kind: impl Into<Atom>,
+    ) -> Option<GetPropertyReply> {
+        let reply = self
+            .connection
+            .get_property(false, window, property, kind, 0, u32::MAX)
+            .ok()?
+            .reply()
+            .ok()?;
+        (reply.type_ != u32::from(AtomEnum::NONE)).then_some(reply)
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn x11_error(e: impl std::fmt::Display) -> WindowError {
+    WindowError::X11(e.to_string())
+}
@@ -1,39 +1,219 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::net::SocketAddr;
-use std::sync::atomic::AtomicBool;
 use std::sync::atomic::Ordering;
+use std::sync::atomic::{AtomicBool, AtomicUsize};
 use std::sync::Arc;
-use tokio::io::{self, Interest};
-use tokio::net::{TcpListener, TcpStream};
-use tokio::sync::{Mutex, Notify, RwLock};
-
-use crate::screen_capture::CapturedFrame;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::TcpListener;
+use tokio::sync::{mpsc, Mutex, Notify, RwLock};
+
+use crate::annotation::Shape;
+use crate::chat::{ChatHistory, ChatMessage};
+use crate::common::unix_timestamp_micros;
+use crate::config::{PrivacyMode, SlateConfig};
+use crate::protocol::{
+    read_message, AnnotationPacket, ChatPacket, CursorPacket, CursorShapePacket, FramePacket,
+    Message, PointerPacket, SlatePacket, StreamInfo, MAIN_STREAM, TAG_FRAME,
+};
+use crate::screen_capture::{CapturedFrame, Cursor};
 
 pub const PORT: u16 = 56123;
 
+// Max number of packets waiting to be written to a single receiver,
+// a receiver that falls behind drops frames instead of accumulating latency
+const PEER_QUEUE_SIZE: usize = 4;
+
+// Minimum time between two keyframes forced by the requests of a receiver
+pub const KEYFRAME_REQUEST_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Clone)]
+struct Peer {
+    tx: mpsc::Sender<Arc<Vec<u8>>>, // Packets queued for the writer task
+    chat: mpsc::UnboundedSender<Arc<Vec<u8>>>, // Never dropped, written ahead of the packets
+    subscriptions: Arc<std::sync::Mutex<Vec<u32>>>, // Streams the peer wants frames of
+    needs_keyframe: Arc<AtomicBool>, // The peer can't decode until the next keyframe
+    missed_state: Arc<AtomicBool>,  // A state message didn't fit in the queue, resend it all
+    frames_queued: Arc<AtomicUsize>, // Frames in the queue the writer task hasn't written yet
+}
+
+impl Peer {
+    // Nothing new is being encoded while paused, show the frames everyone else is seeing
+    // on the streams the peer watches
+    fn send_paused_frames(&self, last_keyframes: &std::sync::Mutex<HashMap<u32, Arc<Vec<u8>>>>) {
+        let subscriptions = self.subscriptions.lock().unwrap().clone();
+        let last_keyframes = last_keyframes.lock().unwrap();
+        let mut sent_all = true;
+        for stream_id in subscriptions {
+            match last_keyframes.get(&stream_id) {
+                Some(packet) if self.tx.try_send(packet.clone()).is_ok() => {
+                    self.frames_queued.fetch_add(1, Ordering::SeqCst);
+                }
+                _ => sent_all = false,
+            }
+        }
+        if sent_all {
+            self.needs_keyframe.store(false, Ordering::SeqCst);
+        }
+    }
+}
+
+// Flags shared between the caster UI and the streaming tasks
+#[derive(Clone)]
+pub struct StreamFlags {
+    pub is_blank_screen: Arc<AtomicBool>,
+    pub is_annotation_open: Arc<AtomicBool>,
+    pub is_transmission_paused: Arc<AtomicBool>,
+}
+
+// Laser pointers of the receivers, drawn on the caster screen and relayed to the other receivers
+#[derive(Clone)]
+pub struct LaserPointers {
+    pub enabled: Arc<AtomicBool>, // Pointers of the receivers are ignored when disabled
+    pub active: Arc<std::sync::Mutex<HashMap<u32, PointerPacket>>>, // Visible pointers, by viewer
+}
+
+impl LaserPointers {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled: Arc::new(AtomicBool::new(enabled)),
+            active: Arc::new(std::sync::Mutex::new(HashMap::new())),
+        }
+    }
+
+    fn update(&self, pointer: PointerPacket) {
+        let mut active = self.active.lock().unwrap();
+        if pointer.visible {
+            active.insert(pointer.viewer_id, pointer);
+        } else {
+            active.remove(&pointer.viewer_id);
+        }
+    }
+}
+
+// Caster state the tasks reading the messages of the receivers act on
+#[derive(Clone)]
+struct ReaderContext {
+    receivers: Arc<RwLock<HashMap<SocketAddr, Peer>>>,
+    disconnected_peers: Arc<Mutex<Vec<SocketAddr>>>,
+    laser: LaserPointers,
+    chat: Arc<std::sync::Mutex<ChatHistory>>,
+    is_paused: Arc<AtomicBool>,
+    last_keyframes: Arc<std::sync::Mutex<HashMap<u32, Arc<Vec<u8>>>>>,
+}
+
+// Pointer sent apart from the frames, the image only when it changes
+#[derive(Default)]
+struct CursorState {
+    shape_serial: Option<u32>,
+    shape_packet: Arc<std::sync::Mutex<Option<Arc<Vec<u8>>>>>, // Sent to receivers on connection
+    visible_streams: HashSet<u32>, // Streams whose receivers are showing the pointer
+}
+
+// Shapes drawn by the caster, receivers only get what changed
+#[derive(Default)]
+struct AnnotationState {
+    shapes: Vec<(u32, Shape)>, // As last sent, by id
+    next_id: u32,
+    packets: Arc<std::sync::Mutex<Vec<Arc<Vec<u8>>>>>, // Sent to receivers on connection
+}
+
+// What receivers were told once and keep showing, for receivers that join or missed some of it
+struct ReceiverState {
+    is_paused: Arc<AtomicBool>,
+    pointers_allowed: Arc<AtomicBool>,
+    slate_packet: Arc<std::sync::Mutex<Option<Arc<Vec<u8>>>>>,
+    stream_list_packet: Arc<std::sync::Mutex<Option<Arc<Vec<u8>>>>>,
+    cursor_shape_packet: Arc<std::sync::Mutex<Option<Arc<Vec<u8>>>>>,
+    annotation_packets: Arc<std::sync::Mutex<Vec<Arc<Vec<u8>>>>>,
+}
+
+impl ReceiverState {
+    // All of it in a single packet, it only needs one place in the queue
+    fn packet(&self) -> Arc<Vec<u8>> {
+        let mut packet = Vec::new();
+        if let Some(stream_list) = &*self.stream_list_packet.lock().unwrap() {
+            packet.extend_from_slice(stream_list);
+        }
+        if let Some(cursor_shape) = &*self.cursor_shape_packet.lock().unwrap() {
+            packet.extend_from_slice(cursor_shape);
+        }
+        // Receivers only offer the laser pointer when the caster shows it
+        let pointers_allowed = self.pointers_allowed.load(Ordering::SeqCst);
+        packet.extend_from_slice(&Message::PointersAllowed(pointers_allowed).to_bytes());
+
+        packet.extend_from_slice(&Message::ClearAnnotations.to_bytes());
+        for annotation in self.annotation_packets.lock().unwrap().iter() {
+            packet.extend_from_slice(annotation);
+        }
+
+        let paused = if self.is_paused.load(Ordering::SeqCst) {
+            Message::Paused
+        } else {
+            Message::Resumed
+        };
+        packet.extend_from_slice(&paused.to_bytes());
+        // Last, receivers drop the slate when a frame arrives
+        if let Some(slate) = &*self.slate_packet.lock().unwrap() {
+            packet.extend_from_slice(slate);
+        }
+        Arc::new(packet)
+    }
+}
+
 pub struct Sender {
-    receivers: Arc<RwLock<HashMap<SocketAddr, Arc<TcpStream>>>>,
+    receivers: Arc<RwLock<HashMap<SocketAddr, Peer>>>,
     disconnected_peers: Arc<Mutex<Vec<SocketAddr>>>,
-    frame_id: u32,
     started_sending: bool,
-    is_sending_frame: Arc<AtomicBool>,
+    is_paused: Arc<AtomicBool>, // Receivers were told the transmission is paused
+    last_keyframes: Arc<std::sync::Mutex<HashMap<u32, Arc<Vec<u8>>>>>, // By stream, shown to receivers joining or switching streams while paused
+    active_slate: Option<SlateConfig>, // Slate receivers are showing instead of the stream
+    slate_packet: Arc<std::sync::Mutex<Option<Arc<Vec<u8>>>>>, // Sent to receivers joining while the slate is shown
+    streams: Vec<StreamInfo>, // Published streams, receivers subscribe to them
+    stream_list_packet: Arc<std::sync::Mutex<Option<Arc<Vec<u8>>>>>, // Sent to receivers on connection
+    cursor: CursorState,
+    annotations: AnnotationState,
+    laser: LaserPointers,
+    pointers_allowed: bool,                   // Last told to the receivers
+    chat: Arc<std::sync::Mutex<ChatHistory>>, // Shown by the caster UI, kept across streams
 }
 
 impl Sender {
     //initialize caster UdpSocket
-    pub async fn new() -> Self {
+    pub async fn new(laser: LaserPointers, chat: Arc<std::sync::Mutex<ChatHistory>>) -> Self {
         Self {
             receivers: Arc::new(RwLock::new(HashMap::new())),
             disconnected_peers: Arc::new(Mutex::new(Vec::new())),
-            frame_id: 0,
             started_sending: false,
-            is_sending_frame: Arc::new(AtomicBool::new(false)),
+            is_paused: Arc::new(AtomicBool::new(false)),
+            last_keyframes: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            active_slate: None,
+            slate_packet: Arc::new(std::sync::Mutex::new(None)),
+            streams: Vec::new(),
+            stream_list_packet: Arc::new(std::sync::Mutex::new(None)),
+            cursor: CursorState::default(),
+            annotations: AnnotationState::default(),
+            pointers_allowed: laser.enabled.load(Ordering::SeqCst),
+            laser,
+            chat,
         }
     }
 
     // Start listening for new receivers in background
     pub async fn listen_for_receivers(&self, stop_notify: Arc<Notify>) {
         let receivers = self.receivers.clone();
+        let disconnected_peers = self.disconnected_peers.clone();
+        let last_keyframes = self.last_keyframes.clone();
+        let state = self.receiver_state();
+        let context = ReaderContext {
+            receivers: self.receivers.clone(),
+            disconnected_peers: self.disconnected_peers.clone(),
+            laser: self.laser.clone(),
+            chat: self.chat.clone(),
+            is_paused: self.is_paused.clone(),
+            last_keyframes: self.last_keyframes.clone(),
+        };
 
         let listener = TcpListener::bind(format!("0.0.0.0:{}", PORT))
             .await
@@ -41,6 +221,7 @@ impl Sender {
         println!("TCP Server listening on port {}", PORT);
 
         tokio::spawn(async move {
+            let mut next_viewer_id: u32 = 1;
             loop {
                 tokio::select! {
                     _ = stop_notify.notified() => {
@@ -49,8 +230,49 @@ impl Sender {
                     },
 
                     Ok((socket, peer_addr)) = listener.accept() => {
-                        //match result {
-                        receivers.write().await.insert(peer_addr, Arc::new(socket));
+                        let (read_half, write_half) = socket.into_split();
+                        let (tx, rx) = mpsc::channel(PEER_QUEUE_SIZE);
+                        let (chat_tx, chat_rx) = mpsc::unbounded_channel();
+
+                        // New receivers can only start decoding from a keyframe
+                        let peer = Peer {
+                            tx: tx.clone(),
+                            chat: chat_tx,
+                            subscriptions: Arc::new(std::sync::Mutex::new(vec![MAIN_STREAM])),
+                            needs_keyframe: Arc::new(AtomicBool::new(true)),
+                            missed_state: Arc::new(AtomicBool::new(false)),
+                            frames_queued: Arc::new(AtomicUsize::new(0)),
+                        };
+
+                        spawn_peer_writer(
+                            write_half,
+                            rx,
+                            chat_rx,
+                            peer.frames_queued.clone(),
+                            peer_addr,
+                            disconnected_peers.clone(),
+                        );
+                        spawn_peer_reader(
+                            read_half,
+                            peer_addr,
+                            next_viewer_id,
+                            peer.clone(),
+                            context.clone(),
+                            stop_notify.clone(),
+                        );
+                        next_viewer_id += 1;
+
+                        // The state only changes under the receivers lock, the receiver gets
+                        // what it is now and every later change
+                        let mut receivers = receivers.write().await;
+                        if state.is_paused.load(Ordering::SeqCst) {
+                            peer.send_paused_frames(&last_keyframes);
+                        }
+                        // Let the receiver know what it can watch and what the others are seeing
+                        if tx.try_send(state.packet()).is_err() {
+                            peer.missed_state.store(true, Ordering::SeqCst);
+                        }
+                        receivers.insert(peer_addr, peer);
                         println!("New receiver connected: {}", peer_addr);
                     }
                 }
@@ -58,19 +280,99 @@ impl Sender {
         });
     }
 
+    // Publish the streams receivers can choose from, only sent when they change
+    pub async fn publish_streams(&mut self, streams: Vec<StreamInfo>) {
+        if self.streams == streams {
+            return;
+        }
+
+        let packet = Arc::new(Message::StreamList(streams.clone()).to_bytes());
+        let receivers = self.receivers.read().await;
+        *self.stream_list_packet.lock().unwrap() = Some(packet.clone());
+        // Frames of the streams taken down can't be subscribed to anymore
+        self.last_keyframes
+            .lock()
+            .unwrap()
+            .retain(|id, _| *id == MAIN_STREAM || streams.iter().any(|stream| stream.id == *id));
+        self.streams = streams;
+        broadcast_packet(&receivers, packet);
+    }
+
+    // Send the shapes drawn by the caster, in pixels of the main stream. Shapes already
+    // sent keep their id, the others are added or removed.
+    pub async fn publish_annotations(&mut self, shapes: Vec<Shape>, pixels_per_point: f32) {
+        let state = &mut self.annotations;
+        if state.shapes.iter().map(|(_, shape)| shape).eq(&shapes) {
+            return;
+        }
+
+        let receivers = self.receivers.read().await;
+        let mut previous = std::mem::take(&mut state.shapes);
+        if shapes.is_empty() {
+            broadcast(&receivers, Message::ClearAnnotations);
+        }
+
+        let mut added = Vec::new();
+        for shape in shapes {
+            match previous.iter().position(|(_, old)| *old == shape) {
+                Some(index) => state.shapes.push(previous.remove(index)),
+                None => {
+                    let id = state.next_id;
+                    state.next_id += 1;
+                    added.push(AnnotationPacket {
+                        id,
+                        pixels_per_point,
+                        shape: shape.clone(),
+                    });
+                    state.shapes.push((id, shape));
+                }
+            }
+        }
+
+        // Whatever is left was erased, already gone if everything was cleared
+        if !state.shapes.is_empty() {
+            for (id, _) in previous {
+                broadcast(&receivers, Message::RemoveAnnotation(id));
+            }
+        }
+        for annotation in added {
+            broadcast(&receivers, Message::Annotation(annotation));
+        }
+
+        *state.packets.lock().unwrap() = state
+            .shapes
+            .iter()
+            .map(|(id, shape)| {
+                let annotation = AnnotationPacket {
+                    id: *id,
+                    pixels_per_point,
+                    shape: shape.clone(),
+                };
+                Arc::new(Message::Annotation(annotation).to_bytes())
+            })
+            .collect();
+    }
+
     pub async fn send_data(
         &mut self,
-        frame: CapturedFrame,
-        is_blank_screen: Arc<AtomicBool>,
-        is_annotation_open: Arc<AtomicBool>,
+        mut frame: CapturedFrame,
+        stream_id: u32,
+        flags: StreamFlags,
+        slate: SlateConfig,
     ) -> Result<(), Box<dyn std::error::Error>> {
+        let StreamFlags {
+            is_blank_screen,
+            is_annotation_open,
+            is_transmission_paused,
+        } = flags;
 
         let mut disconnected_peers = self.disconnected_peers.lock().await;
 
         //Remove disconnected peers before sending data
         if !disconnected_peers.is_empty() {
             for peer in disconnected_peers.iter() {
-                self.receivers.write().await.remove(peer); //the Drop trait for TcpStream will close the connection
+                // Dropping the peer closes its queue, the writer task then drops the connection
+                self.receivers.write().await.remove(peer);
                 println!("Receiver {} disconnected", peer);
             }
             disconnected_peers.clear(); // Clear the disconnected peers after processing
@@ -79,155 +381,477 @@ impl Sender {
 
         let receivers = self.receivers.read().await;
 
+        // Receivers that missed a state message get all of it again once they have room
+        let mut state = None;
+        for peer in receivers.values() {
+            if peer.missed_state.load(Ordering::SeqCst) {
+                let packet = state.get_or_insert_with(|| self.receiver_state().packet());
+                if peer.tx.try_send(packet.clone()).is_ok() {
+                    peer.missed_state.store(false, Ordering::SeqCst);
+                }
+            }
+        }
+
+        // Pointers already shown are hidden by the receivers themselves
+        let pointers_allowed = self.laser.enabled.load(Ordering::SeqCst);
+        if self.pointers_allowed != pointers_allowed {
+            self.pointers_allowed = pointers_allowed;
+            self.laser.active.lock().unwrap().clear();
+            broadcast(&receivers, Message::PointersAllowed(pointers_allowed));
+        }
+
         // Return early if no receivers
         if receivers.is_empty() {
             println!("No receivers connected");
             return Ok(());
         }
 
-        // Return early if still sending previous frame while annotation is open, to avoid latency
-        if self.is_sending_frame.swap(true, Ordering::SeqCst) && is_annotation_open.load(Ordering::SeqCst) {
+        // While paused nothing is encoded, receivers hold the last frame
+        if is_transmission_paused.load(Ordering::SeqCst) {
+            if !self.is_paused.swap(true, Ordering::SeqCst) {
+                println!("Transmission paused");
+                broadcast(&receivers, Message::Paused);
+            }
+            return Ok(());
+        } else if self.is_paused.swap(false, Ordering::SeqCst) {
+            println!("Transmission resumed");
+            broadcast(&receivers, Message::Resumed);
+        }
+
+        let is_blank_screen = is_blank_screen.load(Ordering::SeqCst);
+        if is_blank_screen && slate.mode == PrivacyMode::Slate {
+            // The slate is sent once, and again only if the caster edits it
+            if self.active_slate.as_ref() != Some(&slate) {
+                println!("Showing slate to receivers");
+                let packet = Arc::new(
+                    Message::Slate(slate_packet(&slate, frame.width, frame.height)).to_bytes(),
+                );
+                *self.slate_packet.lock().unwrap() = Some(packet.clone());
+                self.active_slate = Some(slate);
+                broadcast_packet(&receivers, packet);
+            }
+            return Ok(());
+        } else if self.active_slate.take().is_some() {
+            // Receivers drop the slate as soon as a frame arrives
+            *self.slate_packet.lock().unwrap() = None;
+        }
+
+        // Obscure the frame before encoding, the original never leaves this machine
+        if is_blank_screen {
+            frame = match slate.mode {
+                PrivacyMode::Blur => frame.blur(slate.strength),
+                PrivacyMode::Pixelate => frame.pixelate(slate.strength),
+                PrivacyMode::Slate => frame,
+            };
+        }
+
+        // Nobody is watching this stream, don't waste time encoding it
+        let subscribers: Vec<(&SocketAddr, &Peer)> = receivers
+            .iter()
+            .filter(|(_, peer)| peer.subscriptions.lock().unwrap().contains(&stream_id))
+            .collect();
+        if subscribers.is_empty() {
+            return Ok(());
+        }
+
+        // Return early if still sending previous frame while annotation is open, to avoid latency.
+        // Only frames count, the small packets queued next to them are written in no time.
+        let is_sending_frame = subscribers
+            .iter()
+            .any(|(_, peer)| peer.frames_queued.load(Ordering::SeqCst) > 0);
+        if is_sending_frame && is_annotation_open.load(Ordering::SeqCst) {
             println!("Still sending previous frame: skipping current");
             return Ok(());
         }
 
+        // Receivers that joined, switched streams or lost a frame wait for a keyframe
+        let force_keyframe = subscribers
+            .iter()
+            .any(|(_, peer)| peer.needs_keyframe.load(Ordering::SeqCst));
+
         //let start = Instant::now();
-        let encoded_frame = frame.encode_to_h265()?;
+        let (encoded_frame, is_keyframe) = encode_frame(&frame, force_keyframe)?;
         //let encode_time = start.elapsed();
         //println!("Encoding time: {:?}", encode_time);
         println!("Frame encoded to h265");
 
-        // Increase frame_id
-        self.frame_id += 1;
-        let fid = self.frame_id;
+        let fid = frame.sequence;
         println!("Frame id: {:?}", fid);
-        
-        for (peer_addr, stream) in receivers.iter() {
-            let disc_peers = self.disconnected_peers.clone();
-            let encoded_frame1 = encoded_frame.clone();
-            let stream1 = stream.clone();
-            let peer_addr = *peer_addr;
-            let is_blank_clone = is_blank_screen.clone();
-            let is_sending = self.is_sending_frame.clone();
-
-            tokio::spawn(async move {
-                loop {
-                    // Check if the stream is writable
-                    let ready = stream1.ready(Interest::WRITABLE).await.unwrap();
-
-                    if ready.is_writable() {
-                        // Try to write data, this may still fail with `WouldBlock`
-                        // if the readiness event is a false positive.
-
-                        let mut pkt = Vec::new();
-
-                        if is_blank_clone.load(Ordering::SeqCst) {
-                            // If it's a blank screen, send BLNK message
-                            let message = b"BLNK";
-                            pkt.extend_from_slice(message);
-                        } else {
-                            let frame_size = (encoded_frame1.len() as u32).to_ne_bytes();
-                            // Create a packet with frame size and encoded frame
-                            pkt.extend_from_slice(&frame_size);
-                            pkt.extend_from_slice(&encoded_frame1);
-                        }
 
-                        match stream1.try_write(&pkt) {
-                            Ok(0) => {
-                                // If 0 bytes are written, the connection was likely closed.
-                                eprintln!("Connection closed by peer");
-
-                                //Add peer to disconnected_peers
-                                let mut disconnected_peers = disc_peers.lock().await;
-                                disconnected_peers.push(peer_addr);
-                                println!("Peer added to disconnected_peers: {}", peer_addr);
-                                drop(disconnected_peers);
-                                return;
-                            }
-                            Ok(_) => {
-                                println!("Sent frame {}", fid);
-                                break;
-                            }
-                            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
-                                // If the readiness event is a false positive, try again
-                                continue;
-                            }
-                            Err(ref e)
-                                if e.kind() == io::ErrorKind::BrokenPipe
-                                    || e.kind() == io::ErrorKind::ConnectionReset
-                                    || e.kind() == io::ErrorKind::ConnectionAborted =>
-                            {
-                                // Connection was closed by the peer
-                                eprintln!("Connection closed: {:?}", e);
-
-                                //Add peer to disconnected_peers
-                                let mut disconnected_peers = disc_peers.lock().await;
-                                disconnected_peers.push(peer_addr);
-                                println!("Peer added to disconnected_peers: {}", peer_addr);
-                                drop(disconnected_peers);
-                                return;
-                            }
-                            Err(e) => {
-                                // Handle other errors
-                                eprintln!("Failed to write to socket: {:?}", e);
-                                return;
-                            }
-                        }
+        let packet = Arc::new(
+            Message::Frame(FramePacket {
+                stream_id,
+                sequence: fid,
+                capture_timestamp: frame.capture_timestamp,
+                is_keyframe,
+                data: encoded_frame,
+            })
+            .to_bytes(),
+        );
+        if is_keyframe {
+            self.last_keyframes
+                .lock()
+                .unwrap()
+                .insert(stream_id, packet.clone());
+        }
+
+        for &(peer_addr, peer) in &subscribers {
+            // Delta frames are useless to a peer that lost the reference frame
+            if !is_keyframe && peer.needs_keyframe.load(Ordering::SeqCst) {
+                continue;
+            }
+
+            match peer.tx.try_send(packet.clone()) {
+                Ok(_) => {
+                    peer.frames_queued.fetch_add(1, Ordering::SeqCst);
+                    if is_keyframe {
+                        peer.needs_keyframe.store(false, Ordering::SeqCst);
                     }
                 }
-                is_sending.store(false, Ordering::SeqCst);
-            });
+                Err(mpsc::error::TrySendError::Full(_)) => {
+                    // The peer is too slow, skipping a frame breaks the reference chain
+                    println!("Receiver {} is lagging behind, dropping frame {}", peer_addr, fid);
+                    peer.needs_keyframe.store(true, Ordering::SeqCst);
+                }
+                Err(mpsc::error::TrySendError::Closed(_)) => {
+                    self.disconnected_peers.lock().await.push(*peer_addr);
+                }
+            }
         }
+
+        // Queued after the frame, so receivers never move the pointer ahead of the image.
+        // The pointer could give away what is hidden.
+        let cursor = frame.cursor.filter(|_| !is_blank_screen);
+        self.cursor.send(
+            &receivers,
+            &subscribers,
+            stream_id,
+            cursor,
+            frame.capture_timestamp,
+        );
         Ok(())
     }
 
+    // Message written by the caster, already in the history
+    pub async fn send_chat(&self, chat: ChatPacket) {
+        let packet = Arc::new(Message::Chat(chat).to_bytes());
+        for (peer_addr, peer) in self.receivers.read().await.iter() {
+            if let Err(e) = peer.chat.send(packet.clone()) {
+                eprintln!("Error sending chat message to {}: {}", peer_addr, e);
+            }
+        }
+    }
+
     // Send end of stream message to all receivers
     pub async fn end_stream(&self) {
-        let receivers = self.receivers.read().await;
+        let mut receivers = self.receivers.write().await;
+        let packet = Arc::new(Message::EndOfStream.to_bytes());
+
+        for (peer, receiver) in receivers.iter() {
+            // Queued after the pending frames, the writer task closes the connection once sent.
+            // A receiver without room is disconnected without it.
+            match receiver.tx.try_send(packet.clone()) {
+                Ok(_) => println!("Sent END to peer {}", peer),
+                Err(e) => eprintln!("Error sending END to {}: {}", peer, e),
+            }
+        }
+        receivers.clear();
+    }
 
-        for (peer, stream) in receivers.iter() {
-            let stream1 = stream.clone();
-            let peer1 = *peer;
-
-            tokio::spawn(async move {
-                let mut buf = vec![0; 4];
-                let message = b"END";
-                buf[..message.len()].copy_from_slice(message); // Copy message to buffer, last byte is 0
-
-                loop {
-                    let ready = stream1.ready(Interest::WRITABLE).await.unwrap();
-
-                    if ready.is_writable() {
-                        match stream1.try_write(&buf) {
-                            Ok(_) => {
-                                println!("Sent END to peer {}", peer1);
-                                break;
-                            }
-                            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
-                                // If the readiness event is a false positive, try again
-                                continue;
-                            }
-                            Err(e) => {
-                                // Handle other errors
-                                eprintln!("Error sending END to {}: {}", peer1, e);
-                                return;
-                            }
+    fn receiver_state(&self) -> ReceiverState {
+        ReceiverState {
+            is_paused: self.is_paused.clone(),
+            pointers_allowed: self.laser.enabled.clone(),
+            slate_packet: self.slate_packet.clone(),
+            stream_list_packet: self.stream_list_packet.clone(),
+            cursor_shape_packet: self.cursor.shape_packet.clone(),
+            annotation_packets: self.annotations.packets.clone(),
+        }
+    }
+}
+
+impl CursorState {
+    // Position updates are tiny, receivers move the pointer between frames with them
+    fn send(
+        &mut self,
+        receivers: &HashMap<SocketAddr, Peer>,
+        subscribers: &[(&SocketAddr, &Peer)],
+        stream_id: u32,
+        cursor: Option<Cursor>,
+        capture_timestamp: u64,
+    ) {
+        let packet = match cursor {
+            Some(cursor) => {
+                // Every stream shows the same pointer, all receivers get its image
+                if self.shape_serial != Some(cursor.shape.serial) {
+                    let shape = Arc::new(
+                        Message::CursorShape(CursorShapePacket {
+                            serial: cursor.shape.serial,
+                            width: cursor.shape.width,
+                            height: cursor.shape.height,
+                            hot_x: cursor.shape.hot_x,
+                            hot_y: cursor.shape.hot_y,
+                            rgba: cursor.shape.rgba.clone(),
+                        })
+                        .to_bytes(),
+                    );
+                    *self.shape_packet.lock().unwrap() = Some(shape.clone());
+                    self.shape_serial = Some(cursor.shape.serial);
+                    broadcast_packet(receivers, shape);
+                }
+
+                self.visible_streams.insert(stream_id);
+                CursorPacket {
+                    stream_id,
+                    x: cursor.x,
+                    y: cursor.y,
+                    visible: true,
+                    pressed: cursor.pressed,
+                    shape_serial: cursor.shape.serial,
+                    capture_timestamp,
+                }
+            }
+            // Receivers are told once that the pointer is gone
+            None if self.visible_streams.remove(&stream_id) => CursorPacket {
+                stream_id,
+                x: 0,
+                y: 0,
+                visible: false,
+                pressed: false,
+                shape_serial: self.shape_serial.unwrap_or_default(),
+                capture_timestamp,
+            },
+            None => return,
+        };
+
+        // A lost update is corrected by the next one
+        let packet = Arc::new(Message::Cursor(packet).to_bytes());
+        for (_, peer) in subscribers {
+            let _ = peer.tx.try_send(packet.clone());
+        }
+    }
+}
+
+// Queue a state message for every receiver without waiting for room, receivers lagging
+// behind get the whole state again instead
+fn broadcast(receivers: &HashMap<SocketAddr, Peer>, message: Message) {
+    broadcast_packet(receivers, Arc::new(message.to_bytes()));
+}
+
+fn broadcast_packet(receivers: &HashMap<SocketAddr, Peer>, packet: Arc<Vec<u8>>) {
+    for (peer_addr, peer) in receivers.iter() {
+        if let Err(mpsc::error::TrySendError::Full(_)) = peer.tx.try_send(packet.clone()) {
+            println!("Receiver {} missed a state message", peer_addr);
+            peer.missed_state.store(true, Ordering::SeqCst);
+        }
+    }
+}
+
+// Encode a frame of the stream, returns whether it is a keyframe. Every frame is encoded by
+// its own ffmpeg run, so it is always an IDR frame and forcing one takes nothing more. An
+// encoder keeping its state between frames has to insert one when `force_keyframe` is set.
+fn encode_frame(
+    frame: &CapturedFrame,
+    _force_keyframe: bool,
+) -> Result<(Vec<u8>, bool), Box<dyn std::error::Error>> {
+    Ok((frame.encode_to_h265()?, true))
+}
+
+fn slate_packet(slate: &SlateConfig, width: usize, height: usize) -> SlatePacket {
+    let image = match &slate.image_path {
+        Some(path) => std::fs::read(path).unwrap_or_else(|e| {
+            eprintln!("Error reading slate image {}: {}", path.display(), e);
+            Vec::new()
+        }),
+        None => Vec::new(),
+    };
+
+    let countdown_until = if slate.countdown_minutes > 0 {
+        unix_timestamp_micros() + slate.countdown_minutes as u64 * 60_000_000
+    } else {
+        0
+    };
+
+    SlatePacket {
+        width: width as u32,
+        height: height as u32,
+        countdown_until,
+        message: slate.message.clone(),
+        image,
+    }
+}
+
+// Write the queued packets of a single receiver, one at a time to keep messages intact.
+// Chat messages go first, they don't wait behind the frames.
+fn spawn_peer_writer(
+    mut write_half: OwnedWriteHalf,
+    mut rx: mpsc::Receiver<Arc<Vec<u8>>>,
+    mut chat_rx: mpsc::UnboundedReceiver<Arc<Vec<u8>>>,
+    frames_queued: Arc<AtomicUsize>,
+    peer_addr: SocketAddr,
+    disconnected_peers: Arc<Mutex<Vec<SocketAddr>>>,
+) {
+    tokio::spawn(async move {
+        loop {
+            let packet = tokio::select! {
+                biased;
+                Some(packet) = chat_rx.recv() => packet,
+                Some(packet) = rx.recv() => packet,
+                else => break,
+            };
+            if let Err(e) = write_half.write_all(&packet).await {
+                // Connection was closed by the peer
+                eprintln!("Connection closed: {:?}", e);
+
+                //Add peer to disconnected_peers
+                disconnected_peers.lock().await.push(peer_addr);
+                println!("Peer added to disconnected_peers: {}", peer_addr);
+                return;
+            }
+            if packet.starts_with(&TAG_FRAME) {
+                frames_queued.fetch_sub(1, Ordering::SeqCst);
+            }
+        }
+        let _ = write_half.shutdown().await;
+    });
+}
+
+// Handle the control messages sent back by a single receiver
+fn spawn_peer_reader(
+    mut read_half: OwnedReadHalf,
+    peer_addr: SocketAddr,
+    viewer_id: u32,
+    peer: Peer,
+    context: ReaderContext,
+    stop_notify: Arc<Notify>,
+) {
+    let ReaderContext {
+        receivers,
+        disconnected_peers,
+        laser,
+        chat,
+        is_paused,
+        last_keyframes,
+    } = context;
+
+    tokio::spawn(async move {
+        let mut last_keyframe_request: Option<Instant> = None;
+
+        loop {
+            tokio::select! {
+                _ = stop_notify.notified() => break,
+
+                result = read_message(&mut read_half) => match result {
+                    Ok(Some(Message::KeyframeRequest)) => {
+                        // Ignore peers asking for keyframes faster than we are willing to send them
+                        if last_keyframe_request
+                            .is_some_and(|last| last.elapsed() < KEYFRAME_REQUEST_INTERVAL)
+                        {
+                            continue;
+                        }
+                        last_keyframe_request = Some(Instant::now());
+
+                        println!("Receiver {} requested a keyframe", peer_addr);
+                        peer.needs_keyframe.store(true, Ordering::SeqCst);
+                    }
+                    Ok(Some(Message::Subscribe(streams))) => {
+                        println!("Receiver {} subscribed to streams {:?}", peer_addr, streams);
+                        *peer.subscriptions.lock().unwrap() = streams;
+                        // The newly watched streams start from a keyframe
+                        peer.needs_keyframe.store(true, Ordering::SeqCst);
+                        if is_paused.load(Ordering::SeqCst) {
+                            peer.send_paused_frames(&last_keyframes);
+                        }
+                    }
+                    Ok(Some(Message::Ping { sent })) => {
+                        let ping_received = unix_timestamp_micros();
+                        let pong = Message::Pong {
+                            ping_sent: sent,
+                            ping_received,
+                            pong_sent: unix_timestamp_micros(),
+                        };
+                        // A lost pong only costs one clock sample, never block on a full queue
+                        let _ = peer.tx.try_send(Arc::new(pong.to_bytes()));
+                    }
+                    Ok(Some(Message::Pointer(mut pointer))) => {
+                        if !laser.enabled.load(Ordering::SeqCst) {
+                            continue;
                         }
+                        // Receivers can't pretend to be someone else
+                        pointer.viewer_id = viewer_id;
+                        laser.update(pointer.clone());
+                        relay_pointer(&receivers, peer_addr, pointer).await;
+                    }
+                    Ok(Some(Message::Chat(packet))) => {
+                        chat.lock().unwrap().push(ChatMessage::received(packet.clone()));
+                        relay_chat(&receivers, peer_addr, packet).await;
+                    }
+                    Ok(Some(message)) => {
+                        eprintln!("Unexpected message from {}: {:?}", peer_addr, message);
+                    }
+                    Ok(None) => {
+                        println!("Connection closed by receiver {}", peer_addr);
+                        disconnected_peers.lock().await.push(peer_addr);
+                        break;
+                    }
+                    Err(e) => {
+                        eprintln!("Error reading from receiver {}: {}", peer_addr, e);
+                        disconnected_peers.lock().await.push(peer_addr);
+                        break;
                     }
                 }
-            });
+            }
+        }
+
+        // The pointer of a receiver that left doesn't stay on screen
+        let pointer = laser.active.lock().unwrap().remove(&viewer_id);
+        if let Some(pointer) = pointer {
+            let hidden = PointerPacket {
+                visible: false,
+                ..pointer
+            };
+            relay_pointer(&receivers, peer_addr, hidden).await;
+        }
+    });
+}
+
+// Pointer of a receiver for all the others, a lost update is corrected by the next one
+async fn relay_pointer(
+    receivers: &RwLock<HashMap<SocketAddr, Peer>>,
+    from: SocketAddr,
+    pointer: PointerPacket,
+) {
+    let packet = Arc::new(Message::Pointer(pointer).to_bytes());
+    for (peer_addr, peer) in receivers.read().await.iter() {
+        if *peer_addr != from {
+            let _ = peer.tx.try_send(packet.clone());
+        }
+    }
+}
+
+// Chat message of a receiver for all the others
+async fn relay_chat(
+    receivers: &RwLock<HashMap<SocketAddr, Peer>>,
+    from: SocketAddr,
+    chat: ChatPacket,
+) {
+    let packet = Arc::new(Message::Chat(chat).to_bytes());
+    for (peer_addr, peer) in receivers.read().await.iter() {
+        if *peer_addr != from {
+            if let Err(e) = peer.chat.send(packet.clone()) {
+                eprintln!("Error relaying chat message to {}: {}", peer_addr, e);
+            }
         }
-        let mut receivers = self.receivers.write().await;
-        receivers.clear();
     }
 }
 
 pub async fn start_streaming(
     sender: Arc<Mutex<Sender>>,
     frame: CapturedFrame,
+    stream_id: u32,
     stop_notify: Arc<Notify>,
-    is_blank_screen: Arc<AtomicBool>,
-    is_annotation_open: Arc<AtomicBool>,
+    flags: StreamFlags,
+    slate: SlateConfig,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let mut sender = sender.lock().await;
 
@@ -237,7 +861,10 @@ pub async fn start_streaming(
         sender.listen_for_receivers(stop_notify).await;
     }
 
-    return match sender.send_data(frame, is_blank_screen, is_annotation_open).await {
+    return match sender
+        .send_data(frame, stream_id, flags, slate)
+        .await
+    {
         Ok(_) => Ok(()),
         Err(e) => Err(e),
     };
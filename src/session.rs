@@ -0,0 +1,513 @@
+use crate::chat::{ChatHistory, ChatMessage};
+use crate::common::unix_timestamp_micros;
+use crate::config::{Config, LaserConfig, PlaybackConfig};
+use crate::protocol::{
+    AnnotationPacket, Message, PointerPacket, SlatePacket, StreamInfo, MAIN_STREAM,
+};
+use crate::receiver::{
+    start_receiving, CasterState, CursorPosition, Receiver, ReceiverFlags, RemoteAnnotations,
+    RemoteCursor, RemotePointers, StreamBuffers,
+};
+use crate::stats::StreamStats;
+use crate::video_recorder::VideoRecorder;
+
+use eframe::egui::{self, Context, TextureHandle};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot::{channel, error::TryRecvError};
+use tokio::sync::{mpsc, Notify};
+
+// Minimum time between two moves of the laser pointer sent to the caster
+const POINTER_INTERVAL: Duration = Duration::from_millis(30);
+
+// Connection to a single caster on the receiver page, shown as a tile of the grid
+pub struct ReceiverSession {
+    pub caster_addr: SocketAddr,
+    pub label: String, // Shown above the tile, the caster address by default
+    receiver_rx: Option<tokio::sync::oneshot::Receiver<Receiver>>,
+    stop_notify: Arc<Notify>, // Stops the receiving tasks of this session only
+    received_frames: Arc<Mutex<StreamBuffers>>, // Playout buffers of the received frames, by stream
+    pub display_texture: Option<TextureHandle>, // Texture of the watched stream
+    pub stream_textures: HashMap<u32, TextureHandle>, // Textures of the other streams shown side by side
+    pub host_unreachable: Arc<AtomicBool>,
+    pub stream_ended: Arc<AtomicBool>,
+    pub is_paused: Arc<AtomicBool>, // Paused locally by the user
+    pub paused_by_caster: Arc<AtomicBool>,
+    pub stream_stats: Arc<Mutex<StreamStats>>,
+    pub received_slate: Arc<Mutex<Option<SlatePacket>>>,
+    pub available_streams: Arc<Mutex<Vec<StreamInfo>>>, // Streams published by the caster
+    control_tx: Option<mpsc::Sender<Message>>, // Messages sent back to the caster
+    pub watched_stream: u32,                   // Stream shown, recorded and measured
+    pub side_by_side: bool,                    // Show every published stream at once
+    pub is_muted: bool, // The caster stops sending frames, the last one stays on the tile
+    subscribed: Vec<u32>, // Streams the caster sends us
+    pub show_stats: bool,
+    remote_cursor: Arc<Mutex<RemoteCursor>>, // Pointer of the caster, sent apart from the frames
+    pub cursor_texture: Option<(u32, TextureHandle)>, // Image of the pointer, by shape serial
+    presented: Option<(u64, Instant)>, // Capture time of the frame shown and when it was shown
+    pub show_cursor: bool,
+    pub highlight_clicks: bool,
+    remote_pointers: Arc<Mutex<RemotePointers>>, // Laser pointers of the other receivers
+    pub own_pointer: Option<PointerPacket>,      // Laser pointer of this receiver, as last sent
+    last_pointer_sent: Option<Instant>,
+    pub chat: Arc<Mutex<ChatHistory>>, // Messages of the caster and its receivers, ours included
+    remote_annotations: Arc<Mutex<RemoteAnnotations>>, // Shapes drawn by the caster
+    pub show_annotations: bool,
+    video_recorder: VideoRecorder,
+    last_frame_time: Option<Instant>,
+    frame_times: VecDeque<Duration>,
+    pub current_fps: f32,
+}
+
+impl ReceiverSession {
+    // Connect to the caster in background, the session is polled by the UI
+    pub fn connect(caster_addr: SocketAddr, config: Arc<Mutex<Config>>) -> Self {
+        let (tx, rx) = channel();
+        let host_unreachable = Arc::new(AtomicBool::new(false));
+
+        let unreachable = host_unreachable.clone();
+        tokio::spawn(async move {
+            match Receiver::new(caster_addr).await {
+                Ok(receiver) => {
+                    let _ = tx.send(receiver);
+                }
+                Err(e) => {
+                    eprintln!("Error initializing receiver: {}", e);
+                    unreachable.store(true, Ordering::SeqCst);
+                }
+            }
+        });
+
+        let label = caster_addr.ip().to_string();
+        let chat = Arc::new(Mutex::new(ChatHistory::default()));
+        Self {
+            caster_addr,
+            video_recorder: VideoRecorder::with_label(config, Some(&label)).with_chat(chat.clone()),
+            label,
+            receiver_rx: Some(rx),
+            stop_notify: Arc::new(Notify::new()),
+            received_frames: Arc::new(Mutex::new(HashMap::new())),
+            display_texture: None,
+            stream_textures: HashMap::new(),
+            host_unreachable,
+            stream_ended: Arc::new(AtomicBool::new(false)),
+            is_paused: Arc::new(AtomicBool::new(false)),
+            paused_by_caster: Arc::new(AtomicBool::new(false)),
+            stream_stats: Arc::new(Mutex::new(StreamStats::default())),
+            received_slate: Arc::new(Mutex::new(None)),
+            available_streams: Arc::new(Mutex::new(Vec::new())),
+            control_tx: None,
+            watched_stream: MAIN_STREAM,
+            side_by_side: false,
+            is_muted: false,
+            subscribed: vec![MAIN_STREAM],
+            show_stats: false,
+            remote_cursor: Arc::new(Mutex::new(RemoteCursor::default())),
+            cursor_texture: None,
+            presented: None,
+            show_cursor: true,
+            highlight_clicks: true,
+            remote_pointers: Arc::new(Mutex::new(RemotePointers::default())),
+            own_pointer: None,
+            last_pointer_sent: None,
+            chat,
+            remote_annotations: Arc::new(Mutex::new(BTreeMap::new())),
+            show_annotations: true,
+            last_frame_time: None,
+            frame_times: VecDeque::with_capacity(60),
+            current_fps: 0.0,
+        }
+    }
+
+    // Start receiving once connected and present the frames due now
+    pub fn update(&mut self, ctx: &Context, playback: &PlaybackConfig) {
+        // Check if we have a pending receiver initialization
+        if let Some(mut rx) = self.receiver_rx.take() {
+            match rx.try_recv() {
+                Ok(receiver) => {
+                    println!("Receiver for {} initialized successfully", self.caster_addr);
+                    self.control_tx = Some(receiver.control_sender());
+                    self.start_receiving(receiver);
+                }
+                Err(TryRecvError::Empty) => {
+                    // Put the channel receiver back if we haven't received yet
+                    self.receiver_rx = Some(rx);
+                }
+                Err(TryRecvError::Closed) => {
+                    eprintln!("Failed to receive receiver for {}", self.caster_addr);
+                }
+            }
+        }
+
+        if self.host_unreachable.load(Ordering::SeqCst) || self.stream_ended.load(Ordering::SeqCst)
+        {
+            self.received_frames.lock().unwrap().clear();
+            self.remote_cursor.lock().unwrap().clear();
+            self.remote_pointers.lock().unwrap().pointers.clear();
+            self.remote_annotations.lock().unwrap().clear();
+            self.display_texture = None;
+            self.stream_textures.clear();
+            self.presented = None;
+            return;
+        }
+
+        self.show_cursor = playback.show_cursor;
+        self.highlight_clicks = playback.highlight_clicks;
+        self.show_annotations = playback.show_annotations;
+        self.update_cursor_texture(ctx);
+
+        self.update_subscriptions();
+
+        // Retrieve the frame due now from the playout buffer of the watched stream
+        let (frame, queue_depth) = {
+            //in this way the lock is released immediately
+            let mut buffers = self.received_frames.lock().unwrap();
+            // Frames of streams we just unsubscribed from are discarded
+            buffers.retain(|id, _| self.subscribed.contains(id));
+            for buffer in buffers.values_mut() {
+                buffer.configure(
+                    Duration::from_millis(playback.target_delay_ms),
+                    playback.low_latency,
+                );
+            }
+            let frames = buffers.entry(self.watched_stream).or_default();
+            let curr_frame = frames.pop_ready(unix_timestamp_micros());
+
+            let mut stats = self.stream_stats.lock().unwrap();
+            stats.record_dropped(frames.take_dropped());
+            stats.record_late(frames.take_late());
+
+            (curr_frame, frames.len())
+        };
+        self.update_side_streams(ctx);
+
+        if let Some(frame) = frame {
+            self.stream_stats.lock().unwrap().record_presented(
+                frame.capture_timestamp,
+                unix_timestamp_micros(),
+                (frame.width, frame.height),
+                queue_depth,
+            );
+
+            if self.video_recorder.is_recording() {
+                self.video_recorder.record_frame(&frame);
+            }
+
+            // Convert to ColorImage for display
+            let image =
+                egui::ColorImage::from_rgba_unmultiplied([frame.width, frame.height], &frame.rgba_data);
+
+            // Update texture in memory
+            if let Some(ref mut texture) = self.display_texture {
+                texture.set(image, egui::TextureOptions::default());
+            } else {
+                self.display_texture = Some(ctx.load_texture(
+                    format!("display_texture_{}", self.caster_addr),
+                    image,
+                    egui::TextureOptions::default(),
+                ));
+            }
+
+            self.presented = Some((frame.capture_timestamp, Instant::now()));
+            self.update_fps_counter();
+        }
+        self.stream_stats
+            .lock()
+            .unwrap()
+            .sample_history(self.current_fps);
+    }
+
+    fn start_receiving(&mut self, receiver: Receiver) {
+        let receiver = Arc::new(tokio::sync::Mutex::new(receiver));
+        let flags = ReceiverFlags {
+            host_unreachable: self.host_unreachable.clone(),
+            stream_ended: self.stream_ended.clone(),
+            is_paused: self.is_paused.clone(),
+            paused_by_caster: self.paused_by_caster.clone(),
+        };
+
+        tokio::spawn(start_receiving(
+            self.received_frames.clone(),
+            receiver,
+            self.stop_notify.clone(),
+            flags,
+            self.stream_stats.clone(),
+            CasterState {
+                slate: self.received_slate.clone(),
+                streams: self.available_streams.clone(),
+                cursor: self.remote_cursor.clone(),
+                pointers: self.remote_pointers.clone(),
+                chat: self.chat.clone(),
+                annotations: self.remote_annotations.clone(),
+            },
+        ));
+    }
+
+    // Load the image of the pointer again only when the caster changes it
+    fn update_cursor_texture(&mut self, ctx: &Context) {
+        let remote_cursor = self.remote_cursor.lock().unwrap();
+        let Some(shape) = &remote_cursor.shape else {
+            return;
+        };
+        if self
+            .cursor_texture
+            .as_ref()
+            .is_some_and(|(serial, _)| *serial == shape.serial)
+        {
+            return;
+        }
+
+        let image = egui::ColorImage::from_rgba_unmultiplied(
+            [shape.width as usize, shape.height as usize],
+            &shape.rgba,
+        );
+        let texture = ctx.load_texture(
+            format!("cursor_texture_{}", self.caster_addr),
+            image,
+            egui::TextureOptions::default(),
+        );
+        self.cursor_texture = Some((shape.serial, texture));
+    }
+
+    // Pointer over the frame shown, moving smoothly until the next frame replaces it
+    pub fn cursor_position(&self) -> Option<CursorPosition> {
+        if !self.show_cursor || self.received_slate.lock().unwrap().is_some() {
+            return None;
+        }
+        let (capture_timestamp, shown_at) = self.presented?;
+        let now = capture_timestamp + shown_at.elapsed().as_micros() as u64;
+        self.remote_cursor
+            .lock()
+            .unwrap()
+            .position_at(self.watched_stream, now)
+    }
+
+    // Hotspot of the pointer image, relative to its top left corner
+    pub fn cursor_hotspot(&self) -> (f32, f32) {
+        let remote_cursor = self.remote_cursor.lock().unwrap();
+        remote_cursor
+            .shape
+            .as_ref()
+            .map_or((0.0, 0.0), |shape| (shape.hot_x as f32, shape.hot_y as f32))
+    }
+
+    // Tell the caster which streams to send, none while muted
+    fn update_subscriptions(&mut self) {
+        let streams = self.available_streams.lock().unwrap().clone();
+
+        // The watched stream was removed by the caster
+        if !streams.iter().any(|stream| stream.id == self.watched_stream) {
+            self.watched_stream = MAIN_STREAM;
+        }
+
+        // The watched stream comes first, it is the one recorded and measured
+        let mut subscribed = Vec::new();
+        if !self.is_muted {
+            subscribed.push(self.watched_stream);
+            if self.side_by_side {
+                subscribed.extend(
+                    streams
+                        .iter()
+                        .map(|stream| stream.id)
+                        .filter(|id| *id != self.watched_stream),
+                );
+            }
+        }
+
+        if subscribed == self.subscribed {
+            return;
+        }
+        if let Some(control_tx) = &self.control_tx {
+            if let Err(e) = control_tx.try_send(Message::Subscribe(subscribed.clone())) {
+                eprintln!("Error subscribing to streams: {}", e);
+                return;
+            }
+        }
+        // A muted tile keeps showing its last frame
+        if !self.is_muted {
+            self.display_texture = None;
+            self.presented = None;
+        }
+        self.stream_textures.clear();
+        self.subscribed = subscribed;
+    }
+
+    // Latest frames of the streams shown next to the watched one
+    fn update_side_streams(&mut self, ctx: &Context) {
+        let now = unix_timestamp_micros();
+        let mut buffers = self.received_frames.lock().unwrap();
+        for id in self.subscribed.iter().filter(|id| **id != self.watched_stream) {
+            let Some(frame) = buffers.get_mut(id).and_then(|frames| frames.pop_ready(now)) else {
+                continue;
+            };
+            let image =
+                egui::ColorImage::from_rgba_unmultiplied([frame.width, frame.height], &frame.rgba_data);
+            match self.stream_textures.get_mut(id) {
+                Some(texture) => texture.set(image, egui::TextureOptions::default()),
+                None => {
+                    let texture = ctx.load_texture(
+                        format!("stream_texture_{}_{}", self.caster_addr, id),
+                        image,
+                        egui::TextureOptions::default(),
+                    );
+                    self.stream_textures.insert(*id, texture);
+                }
+            }
+        }
+    }
+
+    // Shapes of the caster over the frame shown, they belong to the main stream. Fading ink
+    // is returned as it looks now.
+    pub fn annotations(&self) -> Vec<AnnotationPacket> {
+        if !self.show_annotations
+            || self.watched_stream != MAIN_STREAM
+            || self.received_slate.lock().unwrap().is_some()
+        {
+            return Vec::new();
+        }
+        self.remote_annotations
+            .lock()
+            .unwrap()
+            .values()
+            .filter_map(|(received, packet)| {
+                let shape = packet.shape.faded(received.elapsed())?;
+                Some(AnnotationPacket {
+                    shape,
+                    ..packet.clone()
+                })
+            })
+            .collect()
+    }
+
+    // Whether the caster shows the laser pointers of its receivers
+    pub fn pointers_allowed(&self) -> bool {
+        self.remote_pointers.lock().unwrap().allowed
+    }
+
+    // Laser pointers of the other receivers over the watched stream
+    pub fn laser_pointers(&self) -> Vec<PointerPacket> {
+        let remote_pointers = self.remote_pointers.lock().unwrap();
+        let mut pointers: Vec<PointerPacket> = remote_pointers
+            .pointers
+            .values()
+            .filter(|pointer| pointer.stream_id == self.watched_stream)
+            .cloned()
+            .collect();
+        pointers.sort_by_key(|pointer| pointer.viewer_id);
+        pointers
+    }
+
+    // Move the laser pointer of this receiver, a position is a fraction of the stream size.
+    // None hides it, the release is never rate limited.
+    pub fn point_at(&mut self, position: Option<(f32, f32)>, laser: &LaserConfig, name: &str) {
+        let position = position.filter(|_| self.pointers_allowed());
+        let current = self.own_pointer.as_ref().map(|pointer| (pointer.x, pointer.y));
+        if position == current {
+            return;
+        }
+        if position.is_some()
+            && current.is_some()
+            && self
+                .last_pointer_sent
+                .is_some_and(|last| last.elapsed() < POINTER_INTERVAL)
+        {
+            return;
+        }
+        let Some(control_tx) = &self.control_tx else {
+            return;
+        };
+
+        let (x, y) = position.unwrap_or_default();
+        let pointer = PointerPacket {
+            viewer_id: 0,
+            stream_id: self.watched_stream,
+            x,
+            y,
+            visible: position.is_some(),
+            color: laser.color,
+            name: name.to_string(),
+        };
+        if let Err(e) = control_tx.try_send(Message::Pointer(pointer.clone())) {
+            eprintln!("Error sending laser pointer: {}", e);
+            return;
+        }
+        self.own_pointer = pointer.visible.then_some(pointer);
+        self.last_pointer_sent = Some(Instant::now());
+    }
+
+    // Whether messages can be sent to the caster
+    pub fn is_connected(&self) -> bool {
+        self.control_tx.is_some()
+            && !self.host_unreachable.load(Ordering::SeqCst)
+            && !self.stream_ended.load(Ordering::SeqCst)
+    }
+
+    // The caster relays the message to the other receivers
+    pub fn send_chat(&mut self, name: &str, text: String) {
+        let Some(control_tx) = &self.control_tx else {
+            return;
+        };
+        let message = ChatMessage {
+            name: name.to_string(),
+            timestamp: unix_timestamp_micros(),
+            text,
+            is_own: true,
+        };
+        if let Err(e) = control_tx.try_send(Message::Chat(message.to_packet())) {
+            eprintln!("Error sending chat message: {}", e);
+            return;
+        }
+        self.chat.lock().unwrap().push(message);
+    }
+
+    pub fn toggle_pause(&self) {
+        self.is_paused.fetch_xor(true, Ordering::SeqCst);
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.video_recorder.is_recording()
+    }
+
+    pub fn is_finalizing(&self) -> bool {
+        self.video_recorder.is_finalizing()
+    }
+
+    pub fn toggle_recording(&mut self) {
+        if self.video_recorder.is_recording() {
+            self.video_recorder.stop(Some(self.current_fps as u32));
+        } else {
+            self.video_recorder.start();
+        }
+    }
+
+    fn update_fps_counter(&mut self) {
+        let now = Instant::now();
+        if let Some(last_frame_time) = self.last_frame_time {
+            let frame_time = now.duration_since(last_frame_time);
+            self.frame_times.push_back(frame_time);
+
+            // Keep only last 60 frames for moving average
+            if self.frame_times.len() > 60 {
+                self.frame_times.pop_front();
+            }
+
+            // Calculate average FPS
+            if !self.frame_times.is_empty() {
+                let avg_frame_time =
+                    self.frame_times.iter().sum::<Duration>() / self.frame_times.len() as u32;
+                self.current_fps = 1.0 / avg_frame_time.as_secs_f32();
+            }
+        }
+        self.last_frame_time = Some(now);
+    }
+}
+
+impl Drop for ReceiverSession {
+    // Closing a tile stops its tasks, a running recording is saved by the recorder
+    fn drop(&mut self) {
+        self.stop_notify.notify_waiters();
+    }
+}
@@ -0,0 +1,272 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// Number of ping/pong exchanges kept to estimate the clock offset
+const CLOCK_SAMPLES: usize = 8;
+// Weight of the newest sample in the moving averages
+const SMOOTHING: f64 = 0.1;
+// Window used to compute the bitrate
+const BITRATE_WINDOW: Duration = Duration::from_secs(2);
+// One history sample per second, one minute of history for the graph
+const HISTORY_INTERVAL: Duration = Duration::from_secs(1);
+pub const HISTORY_LEN: usize = 60;
+
+#[derive(Debug, Clone, Copy)]
+struct ClockSample {
+    offset_micros: i64, // Caster clock minus receiver clock
+    rtt: Duration,
+}
+
+// A point of the rolling graph
+#[derive(Debug, Clone, Copy)]
+pub struct HistorySample {
+    pub fps: f32,
+    pub bitrate_bps: f64,
+    pub latency: Option<Duration>,
+}
+
+// Measurements of the stream as seen by the receiver
+#[derive(Debug, Default)]
+pub struct StreamStats {
+    clock_samples: VecDeque<ClockSample>,
+    latency: Option<Duration>,     // Glass-to-glass, from capture on the caster to display here
+    rtt: Option<Duration>,         // Network round trip time
+    decode_time: Option<Duration>, // Time spent decoding a single frame
+    connected_at: Option<Instant>,
+    received_bytes: VecDeque<(Instant, usize)>, // Encoded frame sizes within the bitrate window
+    resolution: Option<(usize, usize)>,
+    frames_received: u64,
+    frames_dropped: u64, // Never shown: undecodable, failed to decode or flushed from the queue
+    frames_late: u64,    // Arrived after a newer frame was already shown
+    queue_depth: usize,
+    history: VecDeque<HistorySample>,
+    last_history_sample: Option<Instant>,
+}
+
+impl StreamStats {
+    pub fn start(&mut self) {
+        *self = Self {
+            connected_at: Some(Instant::now()),
+            ..Default::default()
+        };
+    }
+
+    // NTP-style exchange: t0 and t3 are taken on the receiver, t1 and t2 on the caster
+    pub fn record_pong(&mut self, t0: u64, t1: u64, t2: u64, t3: u64) {
+        let (t0, t1, t2, t3) = (t0 as i64, t1 as i64, t2 as i64, t3 as i64);
+        let rtt_micros = (t3 - t0) - (t2 - t1);
+        if rtt_micros < 0 {
+            return; // Clock adjusted during the exchange, the sample is meaningless
+        }
+
+        let rtt = Duration::from_micros(rtt_micros as u64);
+        self.clock_samples.push_back(ClockSample {
+            offset_micros: ((t1 - t0) + (t2 - t3)) / 2,
+            rtt,
+        });
+        if self.clock_samples.len() > CLOCK_SAMPLES {
+            self.clock_samples.pop_front();
+        }
+
+        self.rtt = Some(smooth(self.rtt, rtt));
+    }
+
+    // The sample with the lowest round trip suffered the least queuing, so its offset is the most accurate
+    pub fn clock_offset_micros(&self) -> Option<i64> {
+        self.clock_samples
+            .iter()
+            .min_by_key(|sample| sample.rtt)
+            .map(|sample| sample.offset_micros)
+    }
+
+    // Convert a caster timestamp to the receiver clock
+    pub fn to_local_time(&self, caster_timestamp: u64) -> Option<u64> {
+        self.clock_offset_micros()
+            .map(|offset| (caster_timestamp as i64 - offset).max(0) as u64)
+    }
+
+    pub fn record_frame_received(&mut self, encoded_size: usize) {
+        let now = Instant::now();
+        self.frames_received += 1;
+        self.received_bytes.push_back((now, encoded_size));
+        while self
+            .received_bytes
+            .front()
+            .is_some_and(|(at, _)| now.duration_since(*at) > BITRATE_WINDOW)
+        {
+            self.received_bytes.pop_front();
+        }
+    }
+
+    pub fn record_dropped(&mut self, count: usize) {
+        self.frames_dropped += count as u64;
+    }
+
+    pub fn record_late(&mut self, count: usize) {
+        self.frames_late += count as u64;
+    }
+
+    pub fn record_decode_time(&mut self, decode_time: Duration) {
+        self.decode_time = Some(smooth(self.decode_time, decode_time));
+    }
+
+    // Called when a frame is shown on screen
+    pub fn record_presented(
+        &mut self,
+        capture_timestamp: u64,
+        presented_at: u64,
+        resolution: (usize, usize),
+        queue_depth: usize,
+    ) {
+        self.resolution = Some(resolution);
+        self.queue_depth = queue_depth;
+
+        if capture_timestamp == 0 {
+            return; // Frames generated locally, e.g. the slate
+        }
+
+        if let Some(captured_at) = self.to_local_time(capture_timestamp) {
+            let latency = Duration::from_micros(presented_at.saturating_sub(captured_at));
+            self.latency = Some(smooth(self.latency, latency));
+        }
+    }
+
+    // Append a point to the rolling graph, at most once per HISTORY_INTERVAL
+    pub fn sample_history(&mut self, fps: f32) {
+        if self
+            .last_history_sample
+            .is_some_and(|last| last.elapsed() < HISTORY_INTERVAL)
+        {
+            return;
+        }
+        self.last_history_sample = Some(Instant::now());
+
+        self.history.push_back(HistorySample {
+            fps,
+            bitrate_bps: self.bitrate_bps(),
+            latency: self.latency,
+        });
+        if self.history.len() > HISTORY_LEN {
+            self.history.pop_front();
+        }
+    }
+
+    pub fn bitrate_bps(&self) -> f64 {
+        let (Some((first, _)), Some((last, _))) =
+            (self.received_bytes.front(), self.received_bytes.back())
+        else {
+            return 0.0;
+        };
+        let bytes: usize = self.received_bytes.iter().map(|(_, size)| size).sum();
+        let elapsed = last.duration_since(*first).as_secs_f64().max(1.0);
+        (bytes * 8) as f64 / elapsed
+    }
+
+    pub fn latency(&self) -> Option<Duration> {
+        self.latency
+    }
+
+    pub fn rtt(&self) -> Option<Duration> {
+        self.rtt
+    }
+
+    pub fn decode_time(&self) -> Option<Duration> {
+        self.decode_time
+    }
+
+    pub fn resolution(&self) -> Option<(usize, usize)> {
+        self.resolution
+    }
+
+    pub fn frames_received(&self) -> u64 {
+        self.frames_received
+    }
+
+    pub fn frames_dropped(&self) -> u64 {
+        self.frames_dropped
+    }
+
+    pub fn frames_late(&self) -> u64 {
+        self.frames_late
+    }
+
+    pub fn queue_depth(&self) -> usize {
+        self.queue_depth
+    }
+
+    pub fn uptime(&self) -> Option<Duration> {
+        self.connected_at.map(|at| at.elapsed())
+    }
+
+    pub fn history(&self) -> &VecDeque<HistorySample> {
+        &self.history
+    }
+
+    // Plain text summary meant to be pasted in bug reports
+    pub fn diagnostics_report(&self, fps: f32, codec: &str, caster: Option<&str>) -> String {
+        let resolution = self
+            .resolution
+            .map(|(w, h)| format!("{}x{}", w, h))
+            .unwrap_or_else(|| "-".to_string());
+
+        let mut report = String::new();
+        report.push_str(&format!(
+            "RUSTREAM {} diagnostics ({} {})\n",
+            env!("CARGO_PKG_VERSION"),
+            std::env::consts::OS,
+            std::env::consts::ARCH
+        ));
+        report.push_str(&format!("Caster: {}\n", caster.unwrap_or("-")));
+        report.push_str(&format!("Uptime: {}\n", format_uptime(self.uptime())));
+        report.push_str(&format!("Codec: {}\n", codec));
+        report.push_str(&format!("Resolution: {}\n", resolution));
+        report.push_str(&format!("FPS: {:.1}\n", fps));
+        report.push_str(&format!("Bitrate: {}\n", format_bitrate(self.bitrate_bps())));
+        report.push_str(&format!("Latency: {}\n", format_millis(self.latency)));
+        report.push_str(&format!("RTT: {}\n", format_millis(self.rtt)));
+        report.push_str(&format!(
+            "Clock offset: {}\n",
+            self.clock_offset_micros()
+                .map(|offset| format!("{:.1} ms", offset as f64 / 1000.0))
+                .unwrap_or_else(|| "-".to_string())
+        ));
+        report.push_str(&format!("Decode time: {}\n", format_millis(self.decode_time)));
+        report.push_str(&format!(
+            "Frames: {} received, {} dropped, {} late\n",
+            self.frames_received, self.frames_dropped, self.frames_late
+        ));
+        report.push_str(&format!("Queue depth: {}\n", self.queue_depth));
+        report
+    }
+}
+
+// Exponential moving average, keeps the overlay readable
+fn smooth(previous: Option<Duration>, sample: Duration) -> Duration {
+    match previous {
+        Some(previous) => previous.mul_f64(1.0 - SMOOTHING) + sample.mul_f64(SMOOTHING),
+        None => sample,
+    }
+}
+
+pub fn format_millis(duration: Option<Duration>) -> String {
+    duration
+        .map(|d| format!("{:.1} ms", d.as_secs_f64() * 1000.0))
+        .unwrap_or_else(|| "-".to_string())
+}
+
+pub fn format_bitrate(bits_per_second: f64) -> String {
+    if bits_per_second >= 1_000_000.0 {
+        format!("{:.2} Mbit/s", bits_per_second / 1_000_000.0)
+    } else {
+        format!("{:.0} kbit/s", bits_per_second / 1_000.0)
+    }
+}
+
+pub fn format_uptime(uptime: Option<Duration>) -> String {
+    uptime
+        .map(|d| {
+            let secs = d.as_secs();
+            format!("{:02}:{:02}:{:02}", secs / 3600, (secs / 60) % 60, secs % 60)
+        })
+        .unwrap_or_else(|| "-".to_string())
+}
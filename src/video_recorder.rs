@@ -1,3 +1,4 @@
+use crate::chat::ChatHistory;
 use crate::config::{Config, VideoConfig};
 use crate::screen_capture::CapturedFrame;
 
@@ -24,6 +25,8 @@ pub struct VideoRecorder {
     last_frame_time: Option<Instant>,
     target_frame_duration: Duration,
     frame_tx: Option<mpsc::Sender<(CapturedFrame, PathBuf)>>,
+    label: Option<String>, // Keeps recordings of different casters apart
+    chat: Option<Arc<Mutex<ChatHistory>>>, // Saved next to the video if enabled in the config
 }
 
 impl Default for VideoRecorder {
@@ -34,6 +37,11 @@ impl Default for VideoRecorder {
 
 impl VideoRecorder {
     pub fn new(config: Arc<Mutex<Config>>) -> Self {
+        Self::with_label(config, None)
+    }
+
+    // Several recorders can run at once, each one with its own label
+    pub fn with_label(config: Arc<Mutex<Config>>, label: Option<&str>) -> Self {
         let temp_dir = config.lock().unwrap().video.temp_dir.clone();
         std::fs::create_dir_all(&temp_dir).expect("Failed to create temp directory");
 
@@ -50,7 +58,43 @@ impl VideoRecorder {
             start_time: None,
             last_frame_time: None,
             target_frame_duration: Duration::from_secs_f64(1.0 / fps as f64),
+            label: label.map(|label| {
+                label
+                    .chars()
+                    .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+                    .collect()
+            }),
+            chat: None,
+        }
+    }
+
+    pub fn with_chat(mut self, chat: Arc<Mutex<ChatHistory>>) -> Self {
+        self.chat = Some(chat);
+        self
+    }
+
+    // Temp directory and output file of this recorder
+    fn video_config(&self) -> VideoConfig {
+        let mut video_config = self.config.lock().unwrap().video.clone();
+        if let Some(label) = &self.label {
+            video_config.temp_dir = video_config.temp_dir.join(label);
+            let stem = video_config
+                .output_path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .unwrap_or("output")
+                .to_string();
+            let ext = video_config
+                .output_path
+                .extension()
+                .and_then(|s| s.to_str())
+                .unwrap_or("mkv")
+                .to_string();
+            video_config
+                .output_path
+                .set_file_name(format!("{}_{}.{}", stem, label, ext));
         }
+        video_config
     }
 
     fn cleanup(&mut self) {
@@ -68,8 +112,7 @@ impl VideoRecorder {
 
         // Create temp directory if it doesn't exist
         let temp_dir = {
-            let config = self.config.lock().unwrap();
-            let temp_dir = config.video.temp_dir.clone();
+            let temp_dir = self.video_config().temp_dir;
             if !temp_dir.exists() {
                 if let Err(e) = std::fs::create_dir_all(&temp_dir) {
                     error!("Failed to create temp directory: {}", e);
@@ -122,7 +165,7 @@ impl VideoRecorder {
             self.last_frame_time = Some(last_time + self.target_frame_duration * frames_to_skip);
         }
 
-        let temp_dir: PathBuf = self.config.lock().unwrap().video.temp_dir.clone();
+        let temp_dir: PathBuf = self.video_config().temp_dir;
         let frame_path: PathBuf = temp_dir.join(format!("frame_{:06}.png", self.frame_counter));
         self.frame_counter += 1;
 
@@ -149,12 +192,13 @@ impl VideoRecorder {
 
         // Get necessary data before spawning thread
         let writer_handle = self.frame_writer_handle.take();
-        let config = self.config.clone();
+        let video_config = self.video_config();
+        let transcript = self.transcript();
         let is_finalizing = self.is_finalizing.clone();
         let frame_counter = self.frame_counter;
         let fps = match fps {
             Some(val) => val,
-            None => config.lock().unwrap().video.fps,
+            None => video_config.fps,
         };
 
         std::thread::spawn(move || {
@@ -163,10 +207,6 @@ impl VideoRecorder {
                 let _ = handle.join();
             }
 
-            let config_guard = config.lock().unwrap();
-            let video_config = config_guard.video.clone();
-            drop(config_guard); // Release lock early
-
             info!(
                 "Recording metrics - Frames: {}, Duration: {:.2}s, Calculated FPS: {}",
                 frame_counter,
@@ -174,7 +214,14 @@ impl VideoRecorder {
                 fps,
             );
 
-            VideoRecorder::run_ffmpeg_command(&video_config, fps);
+            let output_path = VideoRecorder::run_ffmpeg_command(&video_config, fps);
+            if let Some(transcript) = transcript {
+                let transcript_path = output_path.with_extension("txt");
+                match std::fs::write(&transcript_path, transcript) {
+                    Ok(_) => info!("Chat saved to {}", transcript_path.to_string_lossy()),
+                    Err(e) => error!("Failed to save chat: {}", e),
+                }
+            }
             std::fs::remove_dir_all(&video_config.temp_dir)
                 .expect("Failed to clean temp directory");
             is_finalizing.store(false, Ordering::SeqCst);
@@ -183,8 +230,19 @@ impl VideoRecorder {
         true
     }
 
+    // Chat of the session so far, None if there is nothing to save
+    fn transcript(&self) -> Option<String> {
+        let chat = self.chat.as_ref()?;
+        if !self.config.lock().unwrap().chat.save_with_recordings {
+            return None;
+        }
+        let chat = chat.lock().unwrap();
+        (!chat.is_empty()).then(|| chat.transcript())
+    }
+
     //https://www.ffmpeg.org/ffmpeg.html
-    fn run_ffmpeg_command(video_config: &VideoConfig, fps: u32) {
+    // Returns the path of the video
+    fn run_ffmpeg_command(video_config: &VideoConfig, fps: u32) -> PathBuf {
         let output_path = Self::generate_unique_path(video_config.output_path.clone());
         info!("Generating video...");
         let mut command = Command::new("ffmpeg");
@@ -246,6 +304,7 @@ impl VideoRecorder {
             }
             Err(e) => error!("FFmpeg execution failed: {}", e),
         }
+        output_path
     }
 
     pub fn is_finalizing(&self) -> bool {